@@ -0,0 +1,277 @@
+// Choosing the GPU.
+// An instance is created for a set of backends (Vulkan, Metal, DX12, GL...), and every
+// backend lists the adapters it can drive: each GPU, and software renderers like llvmpipe
+// or WARP. By default wgpu picks one for us from the power preference:
+// - LowPower is often the integrated GPU of a laptop
+// - HighPerformance the discrete one
+// An adapter can also be asked for by its index in the list or by (part of) its name,
+// e.g. "nvidia" or "llvmpipe". Listing the adapters isn't possible on the web, where the
+// browser only hands out one.
+//
+// `learn_wgpu --list-adapters` prints what each adapter is and supports (AdapterReport),
+// with the index to pick it by. The adapter in use is logged when State is created.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::InitError;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AdapterOptions {
+    // None for the WGPU_BACKEND environment variable, or every backend if it isn't set
+    // (WebGL on the web)
+    pub backends: Option<wgpu::Backends>,
+    pub power_preference: wgpu::PowerPreference,
+    // Overrides the power preference
+    pub adapter: Option<AdapterSelector>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdapterSelector {
+    // Position in the list of adapters of the instance
+    Index(usize),
+    // Part of the adapter name, case insensitive
+    Name(String),
+}
+
+// A number is an index, anything else a name
+impl FromStr for AdapterSelector {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.parse() {
+            Ok(index) => Self::Index(index),
+            Err(_) => Self::Name(s.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for AdapterSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "#{}", index),
+            Self::Name(name) => write!(f, "\"{}\"", name),
+        }
+    }
+}
+
+impl AdapterSelector {
+    // Whether the adapter at `index` in the list, described by `info`, is the one asked for
+    pub fn matches(&self, index: usize, info: &wgpu::AdapterInfo) -> bool {
+        match self {
+            Self::Index(wanted) => *wanted == index,
+            Self::Name(name) => info.name.to_lowercase().contains(&name.to_lowercase()),
+        }
+    }
+}
+
+// Names accepted for the power preference: low, high or none
+pub fn parse_power_preference(s: &str) -> Option<wgpu::PowerPreference> {
+    match s.to_lowercase().as_str() {
+        "low" => Some(wgpu::PowerPreference::LowPower),
+        "high" => Some(wgpu::PowerPreference::HighPerformance),
+        "none" => Some(wgpu::PowerPreference::None),
+        _ => None,
+    }
+}
+
+// Names accepted for a backend, with the aliases wgpu uses
+pub fn parse_backend(s: &str) -> Option<wgpu::Backends> {
+    match s.trim().to_lowercase().as_str() {
+        "vulkan" | "vk" => Some(wgpu::Backends::VULKAN),
+        "gl" | "gles" | "opengl" => Some(wgpu::Backends::GL),
+        "dx12" | "d3d12" => Some(wgpu::Backends::DX12),
+        "metal" | "mtl" => Some(wgpu::Backends::METAL),
+        "webgpu" => Some(wgpu::Backends::BROWSER_WEBGPU),
+        _ => None,
+    }
+}
+
+// What an adapter is and what it supports
+#[derive(Debug, Clone)]
+pub struct AdapterReport {
+    // Position in the list of adapters, what AdapterSelector::Index refers to
+    pub index: usize,
+    pub info: wgpu::AdapterInfo,
+    pub features: wgpu::Features,
+    pub limits: wgpu::Limits,
+}
+
+impl AdapterReport {
+    pub fn new(index: usize, adapter: &wgpu::Adapter) -> Self {
+        Self {
+            index,
+            info: adapter.get_info(),
+            features: adapter.features(),
+            limits: adapter.limits(),
+        }
+    }
+
+    // One line: name, type and backend
+    pub fn summary(&self) -> String {
+        summary(&self.info)
+    }
+}
+
+fn summary(info: &wgpu::AdapterInfo) -> String {
+    format!("{} ({:?}, {:?})", info.name, info.device_type, info.backend)
+}
+
+// The vendor of the PCI vendor id reported by the adapter, for the common ones
+pub fn vendor_name(vendor: u32) -> Option<&'static str> {
+    Some(match vendor {
+        0x1002 | 0x1022 => "AMD",
+        0x106B => "Apple",
+        0x10DE => "NVIDIA",
+        0x13B5 => "ARM",
+        0x1414 => "Microsoft",
+        0x14E4 => "Broadcom",
+        0x5143 => "Qualcomm",
+        0x8086 => "Intel",
+        0x1010 => "Imagination",
+        // Khronos ids of vendors without a PCI id
+        0x10005 => "Mesa",
+        _ => return None,
+    })
+}
+
+// The full report, over several lines:
+//
+//     #0 llvmpipe (LLVM 15.0.6, 256 bits)
+//       Vendor: Mesa (0x10005), device 0x0
+//       ...
+impl fmt::Display for AdapterReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let info = &self.info;
+        writeln!(f, "#{} {}", self.index, info.name)?;
+        match vendor_name(info.vendor) {
+            Some(name) => write!(f, "  Vendor: {} ({:#x})", name, info.vendor)?,
+            None => write!(f, "  Vendor: {:#x}", info.vendor)?,
+        }
+        writeln!(f, ", device {:#x}", info.device)?;
+        writeln!(f, "  Type: {:?}", info.device_type)?;
+        writeln!(f, "  Backend: {:?}", info.backend)?;
+        let driver = [info.driver.as_str(), info.driver_info.as_str()];
+        let driver: Vec<_> = driver.into_iter().filter(|s| !s.is_empty()).collect();
+        writeln!(f, "  Driver: {}", driver.join(" "))?;
+        let features: Vec<_> = self.features.iter_names().map(|(name, _)| name).collect();
+        if features.is_empty() {
+            writeln!(f, "  Features: none")?;
+        } else {
+            writeln!(f, "  Features: {}", features.join(", "))?;
+        }
+        // Limits only has Debug, which puts one limit per line with {:#?}
+        let limits = format!("{:#?}", self.limits);
+        writeln!(f, "  Limits:")?;
+        for line in limits.lines().skip(1) {
+            if line != "}" {
+                writeln!(f, "  {}", line.trim_end_matches(','))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AdapterOptions {
+    pub fn backends(&self) -> wgpu::Backends {
+        #[cfg(not(target_arch = "wasm32"))]
+        let default = wgpu::Backends::from_env().unwrap_or(wgpu::Backends::all());
+        // Some browsers do not support WebGPU yet, WebGL2 works everywhere
+        #[cfg(target_arch = "wasm32")]
+        let default = wgpu::Backends::GL;
+        self.backends.unwrap_or(default)
+    }
+
+    pub fn create_instance(&self) -> wgpu::Instance {
+        wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: self.backends(),
+            ..Default::default()
+        })
+    }
+
+    // Every adapter of the backends, in the order AdapterSelector::Index counts them.
+    // Always empty on the web.
+    pub fn enumerate_adapters(&self, instance: &wgpu::Instance) -> Vec<wgpu::Adapter> {
+        #[cfg(not(target_arch = "wasm32"))]
+        return instance.enumerate_adapters(self.backends());
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = instance;
+            Vec::new()
+        }
+    }
+
+    // The reports of enumerate_adapters, for a new instance
+    pub fn list_adapters(&self) -> Vec<AdapterReport> {
+        let instance = self.create_instance();
+        self.enumerate_adapters(&instance)
+            .iter()
+            .enumerate()
+            .map(|(index, adapter)| AdapterReport::new(index, adapter))
+            .collect()
+    }
+
+    // The index of the adapter picked by the selector among `reports`
+    pub fn selected_index(&self, reports: &[AdapterReport]) -> Option<usize> {
+        let selector = self.adapter.as_ref()?;
+        reports
+            .iter()
+            .find(|report| selector.matches(report.index, &report.info))
+            .map(|report| report.index)
+    }
+
+    // Finds the adapter asked for, which has to be able to present to `surface` if given.
+    // The adapter found is logged.
+    pub async fn request_adapter(
+        &self,
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface<'_>>,
+        force_fallback_adapter: bool,
+    ) -> Result<wgpu::Adapter, InitError> {
+        let adapter = self
+            .find_adapter(instance, surface, force_fallback_adapter)
+            .await?;
+        log::info!("Using adapter {}", summary(&adapter.get_info()));
+        Ok(adapter)
+    }
+
+    async fn find_adapter(
+        &self,
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface<'_>>,
+        force_fallback_adapter: bool,
+    ) -> Result<wgpu::Adapter, InitError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(selector) = &self.adapter {
+            let adapter = self
+                .enumerate_adapters(instance)
+                .into_iter()
+                .enumerate()
+                .find(|(index, adapter)| selector.matches(*index, &adapter.get_info()))
+                .map(|(_, adapter)| adapter)
+                .ok_or_else(|| InitError::AdapterNotFound(selector.to_string()))?;
+            if let Some(surface) = surface
+                && !adapter.is_surface_supported(surface)
+            {
+                return Err(InitError::UnsupportedSurface(adapter.get_info().name));
+            }
+            return Ok(adapter);
+        }
+        #[cfg(target_arch = "wasm32")]
+        if let Some(selector) = &self.adapter {
+            log::warn!(
+                "Adapters can't be listed on the web, ignoring the adapter {}",
+                selector
+            );
+        }
+
+        // compatible_surface makes sure the adapter can present to our surface
+        Ok(instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: self.power_preference,
+                compatible_surface: surface,
+                force_fallback_adapter,
+            })
+            .await?)
+    }
+}
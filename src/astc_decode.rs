@@ -0,0 +1,594 @@
+// Software decoder for ASTC with the LDR profile (8 bit colors, linear or sRGB).
+// Every block is 128 bits whatever its size, from 4x4 to 12x12 texels. A block has:
+// - a block mode: the size of its grid of weights (which is stretched over the block),
+//   their precision and whether there is a second plane of weights for one channel
+// - 1 to 4 partitions, which texel is in which being computed from a 10 bit seed
+// - a color endpoint mode (CEM) per partition: luminance, RGB or RGBA, stored directly or
+//   as a base and an offset...
+// - the endpoint values from the bottom of the block up, and the weights from the top
+//   down, both in the bounded integer sequence encoding (ISE): values with 3 or 5 levels
+//   are packed together in trits or quints.
+// Blocks that are invalid, or use the HDR endpoint modes, decode to magenta.
+
+const ERROR_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+// Decodes one block of `width`x`height` texels into rows of RGBA8 `texels`
+pub(crate) fn decode_block(
+    block: &[u8],
+    width: usize,
+    height: usize,
+    srgb: bool,
+    texels: &mut [u8],
+) {
+    let bits = u128::from_le_bytes(block[..16].try_into().unwrap());
+    if decode(bits, width, height, srgb, texels).is_none() {
+        for texel in texels.chunks_exact_mut(4) {
+            texel.copy_from_slice(&ERROR_COLOR);
+        }
+    }
+}
+
+// `count` bits of `bits` starting at `start`, 0 past the end
+fn field(bits: u128, start: u32, count: u32) -> u32 {
+    (bits.checked_shr(start).unwrap_or(0) & mask(count)) as u32
+}
+
+fn mask(count: u32) -> u128 {
+    1u128.checked_shl(count).unwrap_or(0).wrapping_sub(1)
+}
+
+fn decode(bits: u128, width: usize, height: usize, srgb: bool, texels: &mut [u8]) -> Option<()> {
+    if field(bits, 0, 9) == 0x1fc {
+        return void_extent(bits, texels);
+    }
+
+    let mode = BlockMode::new(field(bits, 0, 11))?;
+    let (grid_width, grid_height) = (mode.grid_width, mode.grid_height);
+    let planes = if mode.dual_plane { 2 } else { 1 };
+    let weight_count = grid_width * grid_height * planes;
+    let weight_bits = mode.weight_range.bit_count(weight_count);
+    if grid_width > width || grid_height > height || weight_count > 64 {
+        return None;
+    }
+    if !(24..=96).contains(&weight_bits) {
+        return None;
+    }
+
+    let partitions = field(bits, 11, 2) as usize + 1;
+    if partitions == 4 && mode.dual_plane {
+        return None;
+    }
+    // With several partitions the CEMs either are all the same, or share a class
+    // (the number of values) give or take one. Some of their bits are then stored
+    // below the weights.
+    let mut cems = [0; 4];
+    let (partition_seed, color_start, extra_cem_bits) = if partitions == 1 {
+        cems[0] = field(bits, 13, 4);
+        (0, 17, 0)
+    } else {
+        let cem = field(bits, 23, 6);
+        let extra_cem_bits = if cem & 3 == 0 {
+            cems = [cem >> 2; 4];
+            0
+        } else {
+            let extra_cem_bits = 3 * partitions as u32 - 4;
+            let extra = field(bits, 128 - weight_bits - extra_cem_bits, extra_cem_bits);
+            let combined = (cem >> 2) | (extra << 4);
+            let class = (cem & 3) - 1;
+            for (i, cem) in cems[..partitions].iter_mut().enumerate() {
+                let class = class + ((combined >> i) & 1);
+                let mode = (combined >> (partitions + 2 * i)) & 3;
+                *cem = (class << 2) | mode;
+            }
+            extra_cem_bits
+        };
+        (field(bits, 13, 10), 29, extra_cem_bits)
+    };
+    let plane2_bits = if mode.dual_plane { 2 } else { 0 };
+    let color_end = (128 - weight_bits - extra_cem_bits).checked_sub(plane2_bits)?;
+    // The channel using the second plane of weights
+    let plane2_channel = field(bits, color_end, 2) as usize;
+
+    // The endpoint values get the most precision that fits in the bits left
+    let value_count: usize = cems[..partitions]
+        .iter()
+        .map(|cem| ((cem >> 2) as usize + 1) * 2)
+        .sum();
+    let color_bits = color_end.checked_sub(color_start)?;
+    if value_count > 18 {
+        return None;
+    }
+    let color_range = COLOR_LEVELS
+        .iter()
+        .rev()
+        .map(|&levels| Range::new(levels))
+        .find(|range| range.bit_count(value_count) <= color_bits)
+        .filter(|range| range.levels >= 6)?;
+    let mut values = [0; 18];
+    // Only the bits of the sequence are read: past its end the last trits or quints are 0
+    let color_data = field128(bits, color_start, color_range.bit_count(value_count));
+    decode_ise(color_data, color_range, &mut values[..value_count]);
+    let values = values.map(|value| color_range.unquantize_color(value));
+
+    let mut endpoints = [None; 4];
+    let mut values = &values[..];
+    for (cem, endpoints) in cems[..partitions].iter().zip(&mut endpoints) {
+        let count = ((cem >> 2) as usize + 1) * 2;
+        *endpoints = color_endpoints(*cem, &values[..count]);
+        values = &values[count..];
+    }
+
+    // The weights are stored in reverse from the top of the block
+    let mut weights = [0; 64];
+    let weight_data = bits.reverse_bits() & mask(weight_bits);
+    decode_ise(weight_data, mode.weight_range, &mut weights[..weight_count]);
+    let weights = weights.map(|weight| mode.weight_range.unquantize_weight(weight));
+
+    // Grid coordinates of texels are in 1/16 of a weight
+    let scale = |size: usize| (1024 + size / 2) / (size - 1).max(1);
+    let (scale_x, scale_y) = (scale(width), scale(height));
+    for (i, texel) in texels.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (i % width, i / width);
+        let partition = if partitions > 1 {
+            select_partition(partition_seed, x, y, partitions, width * height < 31)
+        } else {
+            0
+        };
+        let Some([e0, e1]) = endpoints[partition] else {
+            texel.copy_from_slice(&ERROR_COLOR);
+            continue;
+        };
+
+        let gx = (scale_x * x * (grid_width - 1) + 32) >> 6;
+        let gy = (scale_y * y * (grid_height - 1) + 32) >> 6;
+        let (fx, fy) = (gx & 0xf, gy & 0xf);
+        let base = (gy >> 4) * grid_width + (gx >> 4);
+        let w11 = (fx * fy + 8) >> 4;
+        let factors = [16 + w11 - fx - fy, fx - w11, fy - w11, w11];
+        let corners = [base, base + 1, base + grid_width, base + grid_width + 1];
+        // Corners past the edge of the grid have a factor of 0
+        let weight = |plane: usize| {
+            let sum: usize = corners
+                .iter()
+                .zip(factors)
+                .map(|(&corner, factor)| {
+                    let weight = weights.get(corner * planes + plane).copied().unwrap_or(0);
+                    weight as usize * factor
+                })
+                .sum();
+            ((sum + 8) >> 4) as u32
+        };
+        let (weight1, weight2) = (weight(0), weight(planes - 1));
+
+        for (channel, value) in texel.iter_mut().enumerate() {
+            let weight = if mode.dual_plane && channel == plane2_channel {
+                weight2
+            } else {
+                weight1
+            };
+            // The endpoints are interpolated with 16 bits
+            let expand = |e: u32| if srgb { (e << 8) | 0x80 } else { e * 257 };
+            let (c0, c1) = (expand(e0[channel]), expand(e1[channel]));
+            let color = (c0 * (64 - weight) + c1 * weight + 32) >> 6;
+            *value = (color >> 8) as u8;
+        }
+    }
+    Some(())
+}
+
+fn field128(bits: u128, start: u32, count: u32) -> u128 {
+    bits.checked_shr(start).unwrap_or(0) & mask(count)
+}
+
+// A block of a single color. The extent of the area of the texture with that color is
+// only a hint for the filtering, but has to be valid.
+fn void_extent(bits: u128, texels: &mut [u8]) -> Option<()> {
+    let hdr = field(bits, 9, 1) == 1;
+    if hdr || field(bits, 10, 2) != 3 {
+        return None;
+    }
+    let extent = [12, 25, 38, 51].map(|start| field(bits, start, 13));
+    let no_extent = extent.iter().all(|&coordinate| coordinate == 0x1fff);
+    if !no_extent && (extent[0] >= extent[1] || extent[2] >= extent[3]) {
+        return None;
+    }
+    // UNORM16 values
+    let color = [64, 80, 96, 112].map(|start| (field(bits, start, 16) >> 8) as u8);
+    for texel in texels.chunks_exact_mut(4) {
+        texel.copy_from_slice(&color);
+    }
+    Some(())
+}
+
+struct BlockMode {
+    grid_width: usize,
+    grid_height: usize,
+    dual_plane: bool,
+    weight_range: Range,
+}
+
+impl BlockMode {
+    // The 11 bit block mode, None for the reserved ones
+    fn new(mode: u32) -> Option<Self> {
+        let bit = |i: u32| (mode >> i) & 1;
+        let bits = |start: u32, count: u32| (mode >> start) & ((1 << count) - 1);
+        let (a, b) = (bits(5, 2), bits(7, 2));
+        let (mut dual_plane, mut high_precision) = (bit(10) == 1, bit(9) == 1);
+
+        let (range, width, height) = if bits(0, 2) != 0 {
+            let (width, height) = match bits(2, 2) {
+                0 => (b + 4, a + 2),
+                1 => (b + 8, a + 2),
+                2 => (a + 2, b + 8),
+                _ if bit(8) == 0 => (a + 2, bit(7) + 6),
+                _ => (bit(7) + 2, a + 2),
+            };
+            (bits(0, 2) << 1 | bit(4), width, height)
+        } else {
+            let (width, height) = match b {
+                0 => (12, a + 2),
+                1 => (a + 2, 12),
+                2 => {
+                    dual_plane = false;
+                    high_precision = false;
+                    (a + 6, bits(9, 2) + 6)
+                }
+                _ => match a {
+                    0 => (6, 10),
+                    1 => (10, 6),
+                    _ => return None,
+                },
+            };
+            (bits(2, 2) << 1 | bit(4), width, height)
+        };
+        // Ranges 0 and 1 are reserved, as is the mode of void extent blocks
+        let levels = [[2, 3, 4, 5, 6, 8], [10, 12, 16, 20, 24, 32]][high_precision as usize]
+            .get((range as usize).checked_sub(2)?)?;
+        Some(Self {
+            grid_width: width as usize,
+            grid_height: height as usize,
+            dual_plane,
+            weight_range: Range::new(*levels),
+        })
+    }
+}
+
+// Numbers of levels the endpoint values can have
+const COLOR_LEVELS: [u32; 21] = [
+    2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 24, 32, 40, 48, 64, 80, 96, 128, 160, 192, 256,
+];
+
+// The range of ISE values: 2^bits levels, times 3 with trits or 5 with quints
+#[derive(Debug, Clone, Copy)]
+struct Range {
+    levels: u32,
+    bits: u32,
+    trits: bool,
+    quints: bool,
+}
+
+impl Range {
+    fn new(levels: u32) -> Self {
+        let (trits, quints) = (levels.is_multiple_of(3), levels.is_multiple_of(5));
+        let power_of_two = levels / [1, 3][trits as usize] / [1, 5][quints as usize];
+        Self {
+            levels,
+            bits: power_of_two.trailing_zeros(),
+            trits,
+            quints,
+        }
+    }
+
+    // Size of `count` values, 5 values share 8 bits of trits and 3 values 7 bits of quints
+    fn bit_count(&self, count: usize) -> u32 {
+        let count = count as u32;
+        let packed = if self.trits {
+            (8 * count).div_ceil(5)
+        } else if self.quints {
+            (7 * count).div_ceil(3)
+        } else {
+            0
+        };
+        count * self.bits + packed
+    }
+
+    // Endpoint value to 0..=255. With trits and quints the bits are scrambled so the values
+    // are spread evenly.
+    fn unquantize_color(&self, value: u32) -> u32 {
+        if !self.trits && !self.quints {
+            return replicate(value, self.bits, 8);
+        }
+        let (d, m) = (value >> self.bits, value & ((1 << self.bits) - 1));
+        let a = if m & 1 == 1 { 0x1ff } else { 0 };
+        let (b, c) = ((m >> 1) & 0x1f, m >> 1);
+        let (scale, spread) = match (self.trits, self.bits) {
+            (true, 1) => (204, 0),
+            (true, 2) => (93, b << 8 | b << 4 | b << 2 | b << 1),
+            (true, 3) => (44, c << 7 | c << 2 | c),
+            (true, 4) => (22, c << 6 | c),
+            (true, 5) => (11, c << 5 | c >> 2),
+            (true, _) => (5, c << 4 | c >> 4),
+            (false, 1) => (113, 0),
+            (false, 2) => (54, b << 8 | b << 3 | b << 2),
+            (false, 3) => (26, c << 7 | c << 1 | c >> 1),
+            (false, 4) => (13, c << 6 | c >> 1),
+            (false, _) => (6, c << 5 | c >> 3),
+        };
+        let t = (d * scale + spread) ^ a;
+        (a & 0x80) | (t >> 2)
+    }
+
+    // Weight to 0..=64, the same way with 7 bits
+    fn unquantize_weight(&self, value: u32) -> u32 {
+        let weight = if !self.trits && !self.quints {
+            replicate(value, self.bits, 6)
+        } else if self.bits == 0 {
+            return value * if self.trits { 32 } else { 16 };
+        } else {
+            let (d, m) = (value >> self.bits, value & ((1 << self.bits) - 1));
+            let a = if m & 1 == 1 { 0x7f } else { 0 };
+            let b = m >> 1;
+            let (scale, spread) = match (self.trits, self.bits) {
+                (true, 1) => (50, 0),
+                (true, 2) => (23, b << 6 | b << 2 | b),
+                (true, _) => (11, b << 5 | b),
+                (false, 1) => (28, 0),
+                (false, _) => (13, b << 6 | b << 1),
+            };
+            let t = (d * scale + spread) ^ a;
+            (a & 0x20) | (t >> 2)
+        };
+        // 64 is reachable instead of 63
+        if weight > 32 { weight + 1 } else { weight }
+    }
+}
+
+// Repeats the `bits` bits of `value` to fill `to` bits
+fn replicate(value: u32, bits: u32, to: u32) -> u32 {
+    if bits == 0 {
+        return 0;
+    }
+    let mut result = 0;
+    let mut filled = 0;
+    while filled < to {
+        result = (result << bits) | value;
+        filled += bits;
+    }
+    result >> (filled - to)
+}
+
+// Decodes values.len() values of `range` from the bottom of `data`
+fn decode_ise(data: u128, range: Range, values: &mut [u32]) {
+    let mut position = 0;
+    let mut read = |count: u32| {
+        let value = field(data, position, count);
+        position += count;
+        value
+    };
+    let group = if range.trits {
+        5
+    } else if range.quints {
+        3
+    } else {
+        1
+    };
+    for chunk in values.chunks_mut(group) {
+        // The bits of all the values of a group are read, those past the end are 0
+        let mut low = [0; 5];
+        let mut packed = 0;
+        if range.trits {
+            let mut shift = 0;
+            for (i, count) in [2, 2, 1, 2, 1].into_iter().enumerate() {
+                low[i] = read(range.bits);
+                packed |= read(count) << shift;
+                shift += count;
+            }
+            let trits = decode_trits(packed);
+            for (value, (trit, low)) in chunk.iter_mut().zip(trits.iter().zip(low)) {
+                *value = trit << range.bits | low;
+            }
+        } else if range.quints {
+            for (i, (count, shift)) in [(3, 0), (2, 3), (2, 5)].into_iter().enumerate() {
+                low[i] = read(range.bits);
+                packed |= read(count) << shift;
+            }
+            let quints = decode_quints(packed);
+            for (value, (quint, low)) in chunk.iter_mut().zip(quints.iter().zip(low)) {
+                *value = quint << range.bits | low;
+            }
+        } else {
+            chunk[0] = read(range.bits);
+        }
+    }
+}
+
+// 5 values 0..=2 from 8 bits
+pub(crate) fn decode_trits(t: u32) -> [u32; 5] {
+    let bits = |start: u32, count: u32| (t >> start) & ((1 << count) - 1);
+    let (c, t4, t3) = if bits(2, 3) == 0b111 {
+        (bits(5, 3) << 2 | bits(0, 2), 2, 2)
+    } else if bits(5, 2) == 0b11 {
+        (bits(0, 5), 2, bits(7, 1))
+    } else {
+        (bits(0, 5), bits(7, 1), bits(5, 2))
+    };
+    let c_bit = |i: u32| (c >> i) & 1;
+    let (t2, t1, t0) = if c & 3 == 3 {
+        (2, c_bit(4), c_bit(3) << 1 | (c_bit(2) & !c_bit(3) & 1))
+    } else if (c >> 2) & 3 == 3 {
+        (2, 2, c & 3)
+    } else {
+        (
+            c_bit(4),
+            (c >> 2) & 3,
+            c_bit(1) << 1 | (c_bit(0) & !c_bit(1) & 1),
+        )
+    };
+    [t0, t1, t2, t3, t4]
+}
+
+// 3 values 0..=4 from 7 bits
+pub(crate) fn decode_quints(q: u32) -> [u32; 3] {
+    let bit = |i: u32| (q >> i) & 1;
+    let bits = |start: u32, count: u32| (q >> start) & ((1 << count) - 1);
+    if bits(1, 2) == 0b11 && bits(5, 2) == 0 {
+        let not0 = !bit(0) & 1;
+        let q2 = bit(0) << 2 | (bit(4) & not0) << 1 | (bit(3) & not0);
+        return [4, 4, q2];
+    }
+    let (q2, c) = if bits(1, 2) == 0b11 {
+        (4, bits(3, 2) << 3 | (!bits(5, 2) & 3) << 1 | bit(0))
+    } else {
+        (bits(5, 2), bits(0, 5))
+    };
+    let (q1, q0) = if c & 7 == 0b101 {
+        (4, c >> 3)
+    } else {
+        (c >> 3, c & 7)
+    };
+    [q0, q1, q2]
+}
+
+// The two RGBA endpoints of a partition, None for the HDR modes
+fn color_endpoints(cem: u32, v: &[u32]) -> Option<[[u32; 4]; 2]> {
+    let v: Vec<i32> = v.iter().map(|&value| value as i32).collect();
+    let clamp = |color: [i32; 4]| color.map(|c| c.clamp(0, 255));
+    // Moves precision from blue to red and green, for the endpoints stored the other way
+    // around
+    let blue_contract = |[r, g, b, a]: [i32; 4]| [(r + b) >> 1, (g + b) >> 1, b, a];
+    // An offset in the top 6 bits of `a` and a base with the top bit of `a` moved in
+    let bit_transfer = |a: i32, b: i32| {
+        let base = (b >> 1) | (a & 0x80);
+        let offset = (a >> 1) & 0x3f;
+        let offset = if offset & 0x20 != 0 {
+            offset - 0x40
+        } else {
+            offset
+        };
+        (base, offset)
+    };
+
+    let [e0, e1] = match cem {
+        // Luminance
+        0 => [[v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]],
+        1 => {
+            let l0 = (v[0] >> 2) | (v[1] & 0xc0);
+            let l1 = (l0 + (v[1] & 0x3f)).min(255);
+            [[l0, l0, l0, 255], [l1, l1, l1, 255]]
+        }
+        // Luminance and alpha
+        4 => [[v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]],
+        5 => {
+            let (l, dl) = bit_transfer(v[1], v[0]);
+            let (a, da) = bit_transfer(v[3], v[2]);
+            [[l, l, l, a], clamp([l + dl, l + dl, l + dl, a + da])]
+        }
+        // RGB scaled down for the first endpoint, with two alphas for mode 10
+        6 | 10 => {
+            let (a0, a1) = if cem == 10 { (v[4], v[5]) } else { (255, 255) };
+            [
+                [
+                    (v[0] * v[3]) >> 8,
+                    (v[1] * v[3]) >> 8,
+                    (v[2] * v[3]) >> 8,
+                    a0,
+                ],
+                [v[0], v[1], v[2], a1],
+            ]
+        }
+        // RGB(A), blue contracted when the second endpoint is the darker one
+        8 | 12 => {
+            let (a0, a1) = if cem == 12 { (v[6], v[7]) } else { (255, 255) };
+            if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+                [[v[0], v[2], v[4], a0], [v[1], v[3], v[5], a1]]
+            } else {
+                [
+                    blue_contract([v[1], v[3], v[5], a1]),
+                    blue_contract([v[0], v[2], v[4], a0]),
+                ]
+            }
+        }
+        // RGB(A) as a base and an offset
+        9 | 13 => {
+            let (r, dr) = bit_transfer(v[1], v[0]);
+            let (g, dg) = bit_transfer(v[3], v[2]);
+            let (b, db) = bit_transfer(v[5], v[4]);
+            let (a, da) = if cem == 13 {
+                bit_transfer(v[7], v[6])
+            } else {
+                (255, 0)
+            };
+            let (base, moved) = ([r, g, b, a], [r + dr, g + dg, b + db, a + da]);
+            if dr + dg + db >= 0 {
+                [base, moved]
+            } else {
+                [blue_contract(moved), blue_contract(base)]
+            }
+        }
+        _ => return None,
+    };
+    Some([clamp(e0).map(|c| c as u32), clamp(e1).map(|c| c as u32)])
+}
+
+// The partition of texel (x, y), computed from the seed with a hash. Blocks with fewer
+// than 31 texels use the coordinates doubled, for a finer pattern.
+pub(crate) fn select_partition(
+    seed: u32,
+    x: usize,
+    y: usize,
+    partitions: usize,
+    small: bool,
+) -> usize {
+    let (x, y) = if small {
+        (x as u32 * 2, y as u32 * 2)
+    } else {
+        (x as u32, y as u32)
+    };
+    let seed = seed + (partitions as u32 - 1) * 1024;
+    let random = hash52(seed);
+    let mut seeds: [u32; 8] = std::array::from_fn(|i| (random >> (4 * i)) & 0xf);
+    for s in &mut seeds {
+        *s *= *s;
+    }
+    let (sh1, sh2) = if seed & 1 == 1 {
+        (
+            if seed & 2 == 2 { 4 } else { 5 },
+            if partitions == 3 { 6 } else { 5 },
+        )
+    } else {
+        (
+            if partitions == 3 { 6 } else { 5 },
+            if seed & 2 == 2 { 4 } else { 5 },
+        )
+    };
+    for (i, s) in seeds.iter_mut().enumerate() {
+        *s >>= if i % 2 == 0 { sh1 } else { sh2 };
+    }
+    // The z coordinate is always 0 in 2D, seeds 9 to 12 are only used with it
+    let lines = [
+        seeds[0] * x + seeds[1] * y + (random >> 14),
+        seeds[2] * x + seeds[3] * y + (random >> 10),
+        seeds[4] * x + seeds[5] * y + (random >> 6),
+        seeds[6] * x + seeds[7] * y + (random >> 2),
+    ]
+    .map(|line| line & 0x3f);
+    let lines = &lines[..partitions];
+    // The first of the largest
+    let max = *lines.iter().max().unwrap();
+    lines.iter().position(|&line| line == max).unwrap()
+}
+
+fn hash52(seed: u32) -> u32 {
+    let mut p = seed;
+    p ^= p >> 15;
+    p = p.wrapping_sub(p << 17);
+    p = p.wrapping_add(p << 7);
+    p = p.wrapping_add(p << 4);
+    p ^= p >> 5;
+    p = p.wrapping_add(p << 16);
+    p ^= p >> 7;
+    p ^= p >> 3;
+    p ^= p << 6;
+    p ^= p >> 17;
+    p
+}
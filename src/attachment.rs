@@ -0,0 +1,106 @@
+// Attachments are textures whose size has to follow the surface (depth buffers,
+// multisampled color targets...). State keeps them in a list so `State::resize`
+// can recreate every one of them when the window size changes.
+
+// Describes how an attachment texture is created.
+// Cloneable so the texture can be recreated with the exact same settings after a resize.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachmentDescriptor {
+    pub label: String,
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    pub usage: wgpu::TextureUsages,
+}
+
+impl AttachmentDescriptor {
+    // Most attachments are only ever rendered to, so this is the default usage
+    pub fn new(label: &str, format: wgpu::TextureFormat) -> Self {
+        Self {
+            label: label.to_string(),
+            format,
+            sample_count: 1,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        }
+    }
+
+    pub fn with_sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    pub fn with_usage(mut self, usage: wgpu::TextureUsages) -> Self {
+        self.usage = usage;
+        self
+    }
+}
+
+// Handle returned by `State::add_attachment`.
+// It is only an index into State's attachment list, so it is cheap to copy around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AttachmentId(pub(crate) usize);
+
+pub struct Attachment {
+    desc: AttachmentDescriptor,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl Attachment {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        desc: AttachmentDescriptor,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let (texture, view) = Self::create_texture(device, &desc, width, height);
+        Self {
+            desc,
+            texture,
+            view,
+        }
+    }
+
+    // Drops the old texture and creates a new one with the same descriptor
+    pub(crate) fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let (texture, view) = Self::create_texture(device, &self.desc, width, height);
+        self.texture = texture;
+        self.view = view;
+    }
+
+    fn create_texture(
+        device: &wgpu::Device,
+        desc: &AttachmentDescriptor,
+        width: u32,
+        height: u32,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&desc.label),
+            // A texture can't have a zero size, which happens while the window is minimized
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: desc.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: desc.format,
+            usage: desc.usage,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    pub fn descriptor(&self) -> &AttachmentDescriptor {
+        &self.desc
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+}
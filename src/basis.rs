@@ -0,0 +1,856 @@
+// Transcoders for Basis Universal textures: KTX2 files with ETC1S or UASTC data. Both are
+// made to become GPU formats with little work on the device:
+// - ETC1S blocks are ETC1 blocks with a single base color and table for the whole block.
+//   The colors and tables (endpoints) and the 2 bit per texel selectors come from
+//   codebooks shared by the whole file, which blocks refer to with Huffman coded indices
+//   predicted from their neighbours (BasisLZ supercompression). They become ETC2 RGB
+//   blocks, ETC1 being part of ETC2, and the alpha slices, when there are some, EAC.
+// - UASTC blocks are 128 bits in one of 19 modes, each a subset of ASTC 4x4 storing the
+//   same endpoints and weights, but more compactly to leave room for hints making the
+//   transcoding to BC7 or ETC faster. They are repacked as ASTC 4x4 blocks.
+// compressed.rs re-encodes the result, or decodes it, for devices without ETC2 or ASTC.
+
+use std::ops::Range;
+
+use crate::{astc_decode, block_decode, block_encode};
+
+// Reads a stream of bits from the lowest bit of the first byte up, with 0s past its end
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn bits(&mut self, count: u32) -> u32 {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self.data.get(self.position / 8).copied().unwrap_or(0);
+            value |= (((byte >> (self.position % 8)) & 1) as u32) << i;
+            self.position += 1;
+        }
+        value
+    }
+
+    // A number in chunks of `chunk_bits` bits, lowest first, each followed by a bit saying
+    // whether another one follows
+    fn variable_length(&mut self, chunk_bits: u32) -> u32 {
+        let mut value = 0;
+        for shift in (0..32).step_by(chunk_bits as usize) {
+            let chunk = self.bits(chunk_bits + 1);
+            value |= (chunk & ((1 << chunk_bits) - 1)) << shift;
+            if chunk >> chunk_bits == 0 {
+                break;
+            }
+        }
+        value
+    }
+
+    fn symbol(&mut self, code: &HuffmanCode) -> anyhow::Result<u32> {
+        // Codes are read from their top bit down
+        let mut value = 0;
+        for length in 1..=MAX_CODE_LENGTH {
+            value = (value << 1) | self.bits(1);
+            let first = code.first_code[length];
+            if value >= first && value - first < code.counts[length] {
+                let index = code.first_index[length] + value - first;
+                return Ok(code.symbols[index as usize] as u32);
+            }
+        }
+        anyhow::bail!("Invalid Huffman code in a Basis Universal file")
+    }
+
+    // The code lengths of the symbols are themselves Huffman coded, with codes for runs
+    // of zeros and for repeating the previous length
+    fn huffman_code(&mut self) -> anyhow::Result<HuffmanCode> {
+        let symbol_count = self.bits(14) as usize;
+        if symbol_count == 0 {
+            return HuffmanCode::new(&[]);
+        }
+        let length_code_count = self.bits(5) as usize;
+        if !(1..=CODE_LENGTH_ORDER.len()).contains(&length_code_count) {
+            anyhow::bail!("Invalid Huffman table in a Basis Universal file");
+        }
+        let mut length_code_lengths = [0; CODE_LENGTH_ORDER.len()];
+        for &symbol in &CODE_LENGTH_ORDER[..length_code_count] {
+            length_code_lengths[symbol] = self.bits(3) as u8;
+        }
+        let length_code = HuffmanCode::new(&length_code_lengths)?;
+
+        let mut lengths = Vec::with_capacity(symbol_count);
+        while lengths.len() < symbol_count {
+            let (length, count) = match self.symbol(&length_code)? {
+                length @ 0..=16 => (length as u8, 1),
+                17 => (0, self.bits(3) + 3),
+                18 => (0, self.bits(7) + 11),
+                symbol => {
+                    let count = if symbol == 19 {
+                        self.bits(2) + 3
+                    } else {
+                        self.bits(7) + 7
+                    };
+                    match lengths.last() {
+                        Some(&previous) if previous > 0 => (previous, count),
+                        _ => anyhow::bail!("Invalid Huffman table in a Basis Universal file"),
+                    }
+                }
+            };
+            lengths.extend(std::iter::repeat_n(length, count as usize));
+        }
+        if lengths.len() != symbol_count {
+            anyhow::bail!("Invalid Huffman table in a Basis Universal file");
+        }
+        HuffmanCode::new(&lengths)
+    }
+}
+
+const MAX_CODE_LENGTH: usize = 16;
+
+// The order the lengths of the code length symbols are stored in: the run symbols,
+// then the lengths from the middle out
+const CODE_LENGTH_ORDER: [usize; 21] = [
+    17, 18, 19, 20, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15, 16,
+];
+
+// A canonical Huffman code: the codes of each length are consecutive, in the order of
+// their symbols, and follow the last code of the previous length
+struct HuffmanCode {
+    // The symbols sorted by code length
+    symbols: Vec<u16>,
+    counts: [u32; MAX_CODE_LENGTH + 1],
+    first_code: [u32; MAX_CODE_LENGTH + 1],
+    first_index: [u32; MAX_CODE_LENGTH + 1],
+}
+
+impl HuffmanCode {
+    // From the code length of every symbol, 0 for the unused ones
+    fn new(lengths: &[u8]) -> anyhow::Result<Self> {
+        let mut counts = [0; MAX_CODE_LENGTH + 1];
+        for &length in lengths {
+            if length as usize > MAX_CODE_LENGTH {
+                anyhow::bail!("Invalid Huffman table in a Basis Universal file");
+            }
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let (mut first_code, mut first_index) =
+            ([0; MAX_CODE_LENGTH + 1], [0; MAX_CODE_LENGTH + 1]);
+        let (mut code, mut index) = (0, 0);
+        for length in 1..=MAX_CODE_LENGTH {
+            first_code[length] = code;
+            first_index[length] = index;
+            // More codes than fit in `length` bits
+            if code + counts[length] > 1 << length {
+                anyhow::bail!("Invalid Huffman table in a Basis Universal file");
+            }
+            code = (code + counts[length]) << 1;
+            index += counts[length];
+        }
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16)
+            .filter(|&symbol| lengths[symbol as usize] > 0)
+            .collect();
+        symbols.sort_by_key(|&symbol| lengths[symbol as usize]);
+        Ok(Self {
+            symbols,
+            counts,
+            first_code,
+            first_index,
+        })
+    }
+}
+
+// Where the slices of each image are in the data of its level
+struct ImageDesc {
+    rgb: Range<usize>,
+    alpha: Range<usize>,
+}
+
+// ETC1S blocks: a 5 bit per channel color and one of the 8 ETC1 modifier tables
+#[derive(Debug, Clone, Copy, Default)]
+struct Endpoint {
+    color: [u8; 3],
+    table: u8,
+}
+
+// The 2 bit selectors of the texels, one byte per row with the first texel in its low bits.
+// 0 and 3 pick the largest modifiers down and up, 1 and 2 the smallest ones.
+type Selectors = [u8; 4];
+
+// The codebooks and Huffman codes of an ETC1S file, shared by all the slices
+struct Etc1sCodebooks {
+    endpoints: Vec<Endpoint>,
+    selectors: Vec<Selectors>,
+    endpoint_prediction: HuffmanCode,
+    endpoint_delta: HuffmanCode,
+    selector: HuffmanCode,
+    selector_run: HuffmanCode,
+    selector_history_size: usize,
+}
+
+// Endpoint prediction symbols hold the predictions of 2x2 blocks, this one repeats the
+// previous symbol
+const REPEAT_ENDPOINT_PREDICTION: u32 = 256;
+const ENDPOINT_PREDICTION_REPEAT_MIN: u32 = 3;
+// Selector runs use the most recently used selector of the history for several blocks
+const SELECTOR_RUN_MIN: u32 = 3;
+const SELECTOR_RUN_LONG: u32 = 63;
+
+impl Etc1sCodebooks {
+    fn read(
+        endpoints: &[u8],
+        endpoint_count: usize,
+        selectors: &[u8],
+        selector_count: usize,
+        tables: &[u8],
+    ) -> anyhow::Result<Self> {
+        let mut bits = BitReader::new(tables);
+        let endpoint_prediction = bits.huffman_code()?;
+        let endpoint_delta = bits.huffman_code()?;
+        let selector = bits.huffman_code()?;
+        let selector_run = bits.huffman_code()?;
+        let selector_history_size = bits.bits(13) as usize;
+        Ok(Self {
+            endpoints: read_endpoints(endpoints, endpoint_count)?,
+            selectors: read_selectors(selectors, selector_count)?,
+            endpoint_prediction,
+            endpoint_delta,
+            selector,
+            selector_run,
+            selector_history_size,
+        })
+    }
+
+    // The endpoint and selectors of every block of a slice, row by row
+    fn decode_slice(
+        &self,
+        data: &[u8],
+        blocks_wide: usize,
+        blocks_high: usize,
+    ) -> anyhow::Result<Vec<(Endpoint, Selectors)>> {
+        let invalid = || anyhow::anyhow!("Invalid ETC1S slice in a Basis Universal file");
+        let mut bits = BitReader::new(data);
+        let selector_count = self.selectors.len() as u32;
+        let mut history = SelectorHistory::new(self.selector_history_size);
+        let run_symbol = selector_count + self.selector_history_size as u32;
+
+        // The endpoint index of each block of this row and the previous one, and the
+        // predictions of the odd rows, decoded with the even ones
+        let mut previous_row = vec![(0u32, 0u32); blocks_wide];
+        let mut row = vec![(0u32, 0u32); blocks_wide];
+        let (mut prediction_bits, mut previous_prediction, mut prediction_repeats) = (0, 0, 0);
+        let mut previous_endpoint = 0;
+        let mut selector_run = 0;
+
+        let mut blocks = Vec::with_capacity(blocks_wide * blocks_high);
+        for y in 0..blocks_high {
+            for x in 0..blocks_wide {
+                if x % 2 == 0 {
+                    if y % 2 == 0 {
+                        if prediction_repeats > 0 {
+                            prediction_repeats -= 1;
+                            prediction_bits = previous_prediction;
+                        } else {
+                            prediction_bits = bits.symbol(&self.endpoint_prediction)?;
+                            if prediction_bits == REPEAT_ENDPOINT_PREDICTION {
+                                prediction_repeats =
+                                    bits.variable_length(4) + ENDPOINT_PREDICTION_REPEAT_MIN - 1;
+                                prediction_bits = previous_prediction;
+                            } else {
+                                previous_prediction = prediction_bits;
+                            }
+                        }
+                        // The bottom half is for the blocks of the next row
+                        row[x].1 = prediction_bits >> 4;
+                    } else {
+                        prediction_bits = previous_row[x].1;
+                    }
+                }
+
+                let endpoint = match prediction_bits & 3 {
+                    // The block on the left
+                    0 if x > 0 => previous_endpoint,
+                    // Above
+                    1 if y > 0 => previous_row[x].0,
+                    // Above on the left
+                    2 if x > 0 && y > 0 => previous_row[x - 1].0,
+                    3 => {
+                        let delta = bits.symbol(&self.endpoint_delta)?;
+                        (previous_endpoint + delta) % self.endpoints.len().max(1) as u32
+                    }
+                    _ => return Err(invalid()),
+                };
+                prediction_bits >>= 2;
+                row[x].0 = endpoint;
+                previous_endpoint = endpoint;
+
+                let symbol = if selector_run > 0 {
+                    selector_run -= 1;
+                    selector_count
+                } else {
+                    let symbol = bits.symbol(&self.selector)?;
+                    if symbol == run_symbol {
+                        let run = bits.symbol(&self.selector_run)?;
+                        selector_run = if run == SELECTOR_RUN_LONG {
+                            bits.variable_length(7) + SELECTOR_RUN_MIN
+                        } else {
+                            run + SELECTOR_RUN_MIN
+                        };
+                        selector_run -= 1;
+                        selector_count
+                    } else {
+                        symbol
+                    }
+                };
+                let selector = if symbol >= selector_count {
+                    history
+                        .take((symbol - selector_count) as usize)
+                        .ok_or_else(invalid)?
+                } else {
+                    history.add(symbol);
+                    symbol
+                };
+
+                let endpoint = self.endpoints.get(endpoint as usize).ok_or_else(invalid)?;
+                let selectors = self.selectors.get(selector as usize).ok_or_else(invalid)?;
+                blocks.push((*endpoint, *selectors));
+            }
+            std::mem::swap(&mut previous_row, &mut row);
+        }
+        Ok(blocks)
+    }
+}
+
+// The selectors used recently, roughly the most used first: new ones go in the second
+// half, and a selector moves halfway to the front every time it is used again
+struct SelectorHistory {
+    values: Vec<u32>,
+    next: usize,
+}
+
+impl SelectorHistory {
+    fn new(size: usize) -> Self {
+        Self {
+            values: vec![0; size],
+            next: size / 2,
+        }
+    }
+
+    fn add(&mut self, selector: u32) {
+        if self.values.is_empty() {
+            return;
+        }
+        self.values[self.next] = selector;
+        self.next += 1;
+        if self.next == self.values.len() {
+            self.next = self.values.len() / 2;
+        }
+    }
+
+    fn take(&mut self, index: usize) -> Option<u32> {
+        let selector = *self.values.get(index)?;
+        self.values.swap(index / 2, index);
+        Some(selector)
+    }
+}
+
+// The endpoints are deltas from the previous one. Color deltas have a Huffman code for
+// each third of the range of the previous value.
+fn read_endpoints(data: &[u8], count: usize) -> anyhow::Result<Vec<Endpoint>> {
+    let mut bits = BitReader::new(data);
+    let color_deltas = [
+        bits.huffman_code()?,
+        bits.huffman_code()?,
+        bits.huffman_code()?,
+    ];
+    let table_delta = bits.huffman_code()?;
+    let grayscale = bits.bits(1) == 1;
+
+    let mut previous = Endpoint {
+        color: [16; 3],
+        table: 0,
+    };
+    let mut endpoints = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut endpoint = previous;
+        endpoint.table = ((previous.table as u32 + bits.symbol(&table_delta)?) & 7) as u8;
+        let channels = if grayscale { 1 } else { 3 };
+        for channel in 0..channels {
+            let previous = previous.color[channel] as u32;
+            let deltas = match previous {
+                0..=9 => &color_deltas[0],
+                10..=21 => &color_deltas[1],
+                _ => &color_deltas[2],
+            };
+            endpoint.color[channel] = ((previous + bits.symbol(deltas)?) & 31) as u8;
+        }
+        if grayscale {
+            endpoint.color = [endpoint.color[0]; 3];
+        }
+        endpoints.push(endpoint);
+        previous = endpoint;
+    }
+    Ok(endpoints)
+}
+
+// The selectors are stored raw, or the first one raw and the others as a Huffman coded
+// XOR with the previous one, a row at a time
+fn read_selectors(data: &[u8], count: usize) -> anyhow::Result<Vec<Selectors>> {
+    let mut bits = BitReader::new(data);
+    // Global and hybrid codebooks were dropped from the format
+    if bits.bits(1) == 1 || bits.bits(1) == 1 {
+        anyhow::bail!("Basis Universal files with a global selector codebook aren't supported");
+    }
+    let raw = bits.bits(1) == 1;
+    let deltas = if raw {
+        None
+    } else {
+        Some(bits.huffman_code()?)
+    };
+
+    let mut selectors: Vec<Selectors> = Vec::with_capacity(count);
+    for i in 0..count {
+        let rows = match (&deltas, selectors.last()) {
+            (Some(deltas), Some(previous)) if i > 0 => {
+                let mut rows = *previous;
+                for row in &mut rows {
+                    *row ^= bits.symbol(deltas)? as u8;
+                }
+                rows
+            }
+            _ => std::array::from_fn(|_| bits.bits(8) as u8),
+        };
+        selectors.push(rows);
+    }
+    Ok(selectors)
+}
+
+// ETC1S levels as ETC2 blocks: RGB8, or RGBA8 with an EAC block for the alpha slice
+// before every color block
+pub(crate) fn etc1s_to_etc2(
+    reader: &ktx2::Reader<&[u8]>,
+) -> anyhow::Result<(wgpu::TextureFormat, Vec<Vec<u8>>)> {
+    let header = reader.header();
+    let global_data = reader.supercompression_global_data();
+    let invalid = || anyhow::anyhow!("Invalid BasisLZ global data");
+
+    // Counts and sizes, then a description of every image (here a level) and the data
+    let field = |offset: usize, size: usize| -> anyhow::Result<usize> {
+        let bytes = global_data.get(offset..offset + size).ok_or_else(invalid)?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0, |value, &byte| value << 8 | byte as usize))
+    };
+    let endpoint_count = field(0, 2)?;
+    let selector_count = field(2, 2)?;
+    let sizes = [field(4, 4)?, field(8, 4)?, field(12, 4)?];
+    let level_count = header.level_count.max(1) as usize;
+    let mut images = Vec::with_capacity(level_count);
+    for level in 0..level_count {
+        let start = 20 + level * 20;
+        let flags = field(start, 4)?;
+        // P-frames of videos are predicted from the previous image
+        if flags & 2 != 0 {
+            anyhow::bail!("Basis Universal videos aren't supported");
+        }
+        // Offsets followed by lengths
+        let range = |offset| -> anyhow::Result<Range<usize>> {
+            let start = field(offset, 4)?;
+            Ok(start..start + field(offset + 4, 4)?)
+        };
+        images.push(ImageDesc {
+            rgb: range(start + 4)?,
+            alpha: range(start + 12)?,
+        });
+    }
+    let mut data = global_data
+        .get(20 + level_count * 20..)
+        .ok_or_else(invalid)?;
+    let [endpoints, selectors, tables] = sizes.map(|size| {
+        let (section, rest) = data.split_at(size.min(data.len()));
+        data = rest;
+        section
+    });
+    let codebooks =
+        Etc1sCodebooks::read(endpoints, endpoint_count, selectors, selector_count, tables)?;
+
+    let has_alpha = images.iter().any(|image| !image.alpha.is_empty());
+    let mut levels = Vec::with_capacity(level_count);
+    for (level, (level_data, image)) in reader.levels().zip(&images).enumerate() {
+        let width = crate::mipmap::mip_level_size(header.pixel_width, level as u32);
+        let height = crate::mipmap::mip_level_size(header.pixel_height.max(1), level as u32);
+        let (blocks_wide, blocks_high) = (width.div_ceil(4) as usize, height.div_ceil(4) as usize);
+        let slice = |range: &Range<usize>| -> anyhow::Result<_> {
+            let data = level_data.data.get(range.clone()).ok_or_else(invalid)?;
+            codebooks.decode_slice(data, blocks_wide, blocks_high)
+        };
+
+        let colors = slice(&image.rgb)?;
+        let mut blocks = Vec::with_capacity(colors.len() * if has_alpha { 16 } else { 8 });
+        if has_alpha {
+            let alphas = slice(&image.alpha)?;
+            for (color, alpha) in colors.iter().zip(alphas) {
+                // Alpha slices are grayscale
+                let values = etc1s_texels(&alpha).map(|texel| texel[1]);
+                blocks.extend_from_slice(&block_encode::eac(values));
+                blocks.extend_from_slice(&etc1_block(color));
+            }
+        } else {
+            for color in &colors {
+                blocks.extend_from_slice(&etc1_block(color));
+            }
+        }
+        levels.push(blocks);
+    }
+
+    let format = if has_alpha {
+        wgpu::TextureFormat::Etc2Rgba8Unorm
+    } else {
+        wgpu::TextureFormat::Etc2Rgb8Unorm
+    };
+    Ok((format, levels))
+}
+
+// The ETC1 index of each ETC1S selector: ETC1 has the positive modifiers first
+const ETC1_INDICES: [u64; 4] = [3, 2, 0, 1];
+
+// An ETC1 block in differential mode with both halves the same
+fn etc1_block((endpoint, selectors): &(Endpoint, Selectors)) -> [u8; 8] {
+    let [r, g, b] = endpoint.color.map(|c| c as u64);
+    let table = endpoint.table as u64;
+    let mut bits = r << 59 | g << 51 | b << 43 | table << 37 | table << 34 | 0b11 << 32;
+    for (y, row) in selectors.iter().enumerate() {
+        for x in 0..4 {
+            let index = ETC1_INDICES[((row >> (2 * x)) & 3) as usize];
+            // Indices are stored column by column, the top bits first
+            let p = x * 4 + y;
+            bits |= (index >> 1) << (16 + p) | (index & 1) << p;
+        }
+    }
+    bits.to_be_bytes()
+}
+
+fn etc1s_texels((endpoint, selectors): &(Endpoint, Selectors)) -> [[u8; 3]; 16] {
+    let [small, large] = block_decode::ETC_MODIFIERS[endpoint.table as usize];
+    let modifiers = [-large, -small, small, large];
+    std::array::from_fn(|i| {
+        let selector = (selectors[i / 4] >> (2 * (i % 4))) & 3;
+        let modifier = modifiers[selector as usize];
+        endpoint
+            .color
+            .map(|c| (block_decode::extend5(c) as i32 + modifier).clamp(0, 255) as u8)
+    })
+}
+
+// A UASTC mode: its code, how many channels and subsets its endpoints have, the precision
+// of its weights and endpoints, and how many bits of hints come before them
+struct UastcMode {
+    code: u128,
+    code_bits: u32,
+    channels: usize,
+    subsets: usize,
+    dual_plane: bool,
+    weight_bits: u32,
+    endpoint_levels: u32,
+    hint_bits: u32,
+}
+
+const fn uastc_mode(fields: [u32; 8]) -> UastcMode {
+    UastcMode {
+        code: fields[0] as u128,
+        code_bits: fields[1],
+        channels: fields[2] as usize,
+        subsets: fields[3] as usize,
+        dual_plane: fields[4] == 2,
+        weight_bits: fields[5],
+        endpoint_levels: fields[6],
+        hint_bits: fields[7],
+    }
+}
+
+// Mode 8 is a single color, its other fields are unused. The modes with 2 channels store
+// luminance and alpha.
+const UASTC_MODES: [UastcMode; 19] = [
+    uastc_mode([0x1, 4, 3, 1, 1, 4, 192, 15]),
+    uastc_mode([0x35, 6, 3, 1, 1, 2, 256, 15]),
+    uastc_mode([0x1d, 5, 3, 2, 1, 3, 16, 15]),
+    uastc_mode([0x3, 5, 3, 3, 1, 2, 12, 15]),
+    uastc_mode([0x13, 5, 3, 2, 1, 2, 40, 15]),
+    uastc_mode([0xb, 5, 3, 1, 1, 3, 256, 15]),
+    uastc_mode([0x1b, 5, 3, 1, 2, 2, 160, 15]),
+    uastc_mode([0x7, 5, 3, 2, 1, 2, 40, 15]),
+    uastc_mode([0x17, 5, 4, 1, 1, 0, 0, 0]),
+    uastc_mode([0xf, 5, 4, 2, 1, 2, 16, 15]),
+    uastc_mode([0x2, 3, 4, 1, 1, 4, 48, 15]),
+    uastc_mode([0x0, 2, 4, 1, 2, 2, 48, 15]),
+    uastc_mode([0x6, 3, 4, 1, 1, 3, 192, 15]),
+    uastc_mode([0x1f, 5, 4, 1, 2, 1, 256, 15]),
+    uastc_mode([0xd, 5, 4, 1, 1, 2, 256, 15]),
+    uastc_mode([0x5, 7, 2, 1, 1, 4, 256, 14]),
+    uastc_mode([0x15, 6, 2, 2, 1, 2, 256, 14]),
+    uastc_mode([0x25, 6, 2, 1, 2, 2, 256, 14]),
+    uastc_mode([0x9, 4, 3, 1, 1, 5, 32, 15]),
+];
+
+const UASTC_SOLID_COLOR: usize = 8;
+// The LA mode with two planes always has alpha on the second one
+const UASTC_LA_DUAL_PLANE: usize = 17;
+
+// The partitions of the modes with several subsets are the ASTC seeds of patterns BC7 has
+// too, mode 7 using the 2 subset ones close to 3 subset BC7 patterns
+const UASTC_SEEDS2: [u32; 30] = [
+    28, 20, 16, 29, 91, 9, 107, 72, 149, 204, 50, 114, 496, 17, 78, 39, 252, 828, 43, 156, 116,
+    210, 476, 273, 684, 359, 246, 195, 694, 524,
+];
+const UASTC_SEEDS3: [u32; 11] = [260, 74, 32, 156, 183, 15, 745, 0, 335, 902, 254];
+const UASTC_MODE7_SEEDS: [u32; 19] = [
+    36, 48, 61, 137, 161, 183, 226, 281, 302, 307, 479, 495, 593, 594, 605, 799, 812, 988, 993,
+];
+
+// UASTC levels as ASTC 4x4 blocks
+pub(crate) fn uastc_to_astc(levels: &[Vec<u8>]) -> anyhow::Result<Vec<Vec<u8>>> {
+    levels
+        .iter()
+        .map(|level| {
+            let mut blocks = Vec::with_capacity(level.len());
+            for block in level.chunks_exact(16) {
+                let block = u128::from_le_bytes(block.try_into().unwrap());
+                blocks.extend_from_slice(&uastc_block_to_astc(block)?.to_le_bytes());
+            }
+            Ok(blocks)
+        })
+        .collect()
+}
+
+fn uastc_block_to_astc(block: u128) -> anyhow::Result<u128> {
+    let invalid = || anyhow::anyhow!("Invalid UASTC block");
+    let (index, mode) = UASTC_MODES
+        .iter()
+        .enumerate()
+        .find(|(_, mode)| block & ((1 << mode.code_bits) - 1) == mode.code)
+        .ok_or_else(invalid)?;
+    let mut position = mode.code_bits;
+    let mut read = |count: u32| {
+        let value = (block >> position) as u32 & ((1u64 << count) - 1) as u32;
+        position += count;
+        value
+    };
+
+    if index == UASTC_SOLID_COLOR {
+        let color = [0; 4].map(|_| read(8));
+        return Ok(astc_solid_color(color));
+    }
+    read(mode.hint_bits);
+    let plane2_channel = match (mode.dual_plane, index) {
+        (false, _) => 0,
+        (true, UASTC_LA_DUAL_PLANE) => 3,
+        (true, _) => read(2),
+    };
+    let seed = match (mode.subsets, index) {
+        (1, _) => 0,
+        (2, 7) => *UASTC_MODE7_SEEDS
+            .get(read(5) as usize)
+            .ok_or_else(invalid)?,
+        (2, _) => *UASTC_SEEDS2.get(read(5) as usize).ok_or_else(invalid)?,
+        _ => *UASTC_SEEDS3.get(read(4) as usize).ok_or_else(invalid)?,
+    };
+
+    // The trits or quints of the endpoint values come first, as base 3 or 5 numbers of
+    // 5 or 3 digits, then the bits of every value
+    let value_count = mode.channels * 2 * mode.subsets;
+    let range = IseRange::new(mode.endpoint_levels);
+    let (digits_per_group, digit_base, group_bits): (usize, u32, _) = match range.packed {
+        3 => (5, 3, [0, 2, 4, 5, 7, 8]),
+        5 => (3, 5, [0, 3, 5, 7, 0, 0]),
+        _ => (1, 1, [0; 6]),
+    };
+    let mut groups = [0; 8];
+    if range.packed > 1 {
+        for (i, group) in groups[..value_count.div_ceil(digits_per_group)]
+            .iter_mut()
+            .enumerate()
+        {
+            let digits = (value_count - i * digits_per_group).min(digits_per_group);
+            *group = read(group_bits[digits]);
+        }
+    }
+    let mut endpoints = [0; 16];
+    for (i, value) in endpoints[..value_count].iter_mut().enumerate() {
+        let group = groups[i / digits_per_group];
+        let digit = group / digit_base.pow((i % digits_per_group) as u32) % digit_base;
+        *value = digit << range.bits | read(range.bits);
+    }
+
+    // The first weight of every subset has its top bit clear, and isn't stored
+    let mut anchors = [false; 16];
+    for subset in 0..mode.subsets {
+        let first = (0..16).find(|&i| astc_partition(seed, mode.subsets, i) == subset);
+        if let Some(first) = first {
+            anchors[first] = true;
+        }
+    }
+    let planes = if mode.dual_plane { 2 } else { 1 };
+    let mut weights = [0; 32];
+    for (i, weight) in weights[..16 * planes].iter_mut().enumerate() {
+        let texel = i / planes;
+        let anchor = if mode.dual_plane {
+            texel == 0
+        } else {
+            anchors[texel]
+        };
+        *weight = read(mode.weight_bits - anchor as u32);
+    }
+
+    Ok(astc_block(
+        mode,
+        seed,
+        plane2_channel,
+        &endpoints[..value_count],
+        &weights[..16 * planes],
+    ))
+}
+
+fn astc_partition(seed: u32, subsets: usize, texel: usize) -> usize {
+    if subsets == 1 {
+        return 0;
+    }
+    astc_decode::select_partition(seed, texel % 4, texel / 4, subsets, true)
+}
+
+// An ASTC void extent block: the same color everywhere in UNORM16, covering no area
+fn astc_solid_color(color: [u32; 4]) -> u128 {
+    let mut block = 0x1fc | 0b11 << 10 | ((1u128 << 52) - 1) << 12;
+    for (i, c) in color.into_iter().enumerate() {
+        block |= ((c * 257) as u128) << (64 + 16 * i);
+    }
+    block
+}
+
+// An ASTC 4x4 block with a 4x4 grid of weights and the same endpoint mode for every
+// partition. The endpoints get the precision UASTC gave them because each mode uses all
+// the bits ASTC has left for them.
+fn astc_block(
+    mode: &UastcMode,
+    seed: u32,
+    plane2_channel: u32,
+    endpoints: &[u32],
+    weights: &[u32],
+) -> u128 {
+    // The weight range is 3 bits and a high precision bit, 2 to 32 levels
+    let range = match mode.weight_bits {
+        1 => 2,
+        2 => 4,
+        3 => 7,
+        4 => 4 | 8,
+        _ => 7 | 8,
+    };
+    // Width = B + 4 with B = 0 in bits 7-8, height = A + 2 with A = 2 in bits 5-6
+    let block_mode = (mode.dual_plane as u128) << 10
+        | (range >> 3) << 9
+        | 2 << 5
+        | (range & 1) << 4
+        | (range & 7) >> 1;
+    let cem = [0, 0, 4, 8, 12][mode.channels];
+    let mut block = block_mode | ((mode.subsets - 1) as u128) << 11;
+    let color_start = if mode.subsets == 1 {
+        block |= cem << 13;
+        17
+    } else {
+        // The same endpoint mode for every partition
+        block |= (seed as u128) << 13 | cem << 25;
+        29
+    };
+    let (color_data, _) = encode_ise(IseRange::new(mode.endpoint_levels), endpoints);
+    block |= color_data << color_start;
+    let (weight_data, weight_bits) = encode_ise(IseRange::new(1 << mode.weight_bits), weights);
+    // Weights are stored from the top of the block down
+    block |= weight_data.reverse_bits();
+    if mode.dual_plane {
+        block |= (plane2_channel as u128) << (128 - weight_bits - 2);
+    }
+    block
+}
+
+// The levels of a bounded integer sequence: 2^bits times 1, 3 (trits) or 5 (quints)
+struct IseRange {
+    bits: u32,
+    packed: u32,
+}
+
+impl IseRange {
+    fn new(levels: u32) -> Self {
+        let packed = [3, 5]
+            .into_iter()
+            .find(|&p| levels.is_multiple_of(p))
+            .unwrap_or(1);
+        Self {
+            bits: (levels / packed).trailing_zeros(),
+            packed,
+        }
+    }
+}
+
+// The ASTC encoding of `values`: groups of 5 values with trits or 3 with quints, the
+// packed trits or quints interleaved with the low bits of the values. Returns the bits and
+// their count, the unused part of the last group left out.
+fn encode_ise(range: IseRange, values: &[u32]) -> (u128, u32) {
+    let mut data = 0u128;
+    let mut position = 0;
+    let mut write = |value: u32, count: u32| {
+        data |= ((value & ((1 << count) - 1)) as u128) << position;
+        position += count;
+    };
+    let low = |value: u32| value & ((1 << range.bits) - 1);
+    match range.packed {
+        3 => {
+            for group in values.chunks(5) {
+                let mut trits = [0; 5];
+                for (trit, value) in trits.iter_mut().zip(group) {
+                    *trit = value >> range.bits;
+                }
+                // The smallest encoding, whose bits for the missing trits are 0
+                let packed = (0..256)
+                    .find(|&t| astc_decode::decode_trits(t) == trits)
+                    .unwrap();
+                let mut shift = 0;
+                for (i, count) in [2, 2, 1, 2, 1].into_iter().enumerate() {
+                    write(group.get(i).map_or(0, |&v| low(v)), range.bits);
+                    write(packed >> shift, count);
+                    shift += count;
+                }
+            }
+        }
+        5 => {
+            for group in values.chunks(3) {
+                let mut quints = [0; 3];
+                for (quint, value) in quints.iter_mut().zip(group) {
+                    *quint = value >> range.bits;
+                }
+                let packed = (0..128)
+                    .find(|&q| astc_decode::decode_quints(q) == quints)
+                    .unwrap();
+                for (i, (count, shift)) in [(3, 0), (2, 3), (2, 5)].into_iter().enumerate() {
+                    write(group.get(i).map_or(0, |&v| low(v)), range.bits);
+                    write(packed >> shift, count);
+                }
+            }
+        }
+        _ => {
+            for &value in values {
+                write(value, range.bits);
+            }
+        }
+    }
+    // The bits of the missing values of the last group
+    let count = values.len() as u32;
+    let used = count * range.bits
+        + match range.packed {
+            3 => (8 * count).div_ceil(5),
+            5 => (7 * count).div_ceil(3),
+            _ => 0,
+        };
+    (data & ((1u128 << used) - 1), used)
+}
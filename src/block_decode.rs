@@ -0,0 +1,554 @@
+// Software decoders for block-compressed texture formats.
+// Used when the GPU can't sample a compressed format: the blocks are expanded to RGBA8
+// on the CPU, which costs 4 to 8 times the memory but looks the same. Signed formats are
+// expanded to RGBA8 snorm, and BC6H (HDR) and the 11 bit EAC formats to RGBA16 float
+// (see decoded_format).
+//
+// Every format here but ASTC stores the texture as 4x4 texel blocks, row by row. Each
+// decoder turns one block into 16 RGBA texels in row-major order. BC6H and BC7 are in
+// bptc_decode.rs, ASTC (whose blocks go from 4x4 to 12x12) in astc_decode.rs.
+
+use crate::{astc_decode, bptc_decode};
+
+pub(crate) type Block = [[u8; 4]; 16];
+
+// How the blocks of a format are decoded
+enum Decoder {
+    // 4x4 blocks to RGBA8 texels
+    Rgba8(fn(&[u8]) -> Block),
+    // 4x4 blocks to RGBA16 float texels, as the bits of the half floats
+    Rgba16Float(fn(&[u8]) -> [[u16; 4]; 16]),
+    // ASTC blocks of any size to RGBA8 texels
+    Astc {
+        width: usize,
+        height: usize,
+        srgb: bool,
+    },
+}
+
+impl Decoder {
+    fn new(format: wgpu::TextureFormat) -> anyhow::Result<Self> {
+        use wgpu::TextureFormat as F;
+
+        // sRGB only changes how the GPU interprets the decoded values, not the blocks
+        // (except for ASTC, which rounds differently)
+        Ok(Self::Rgba8(match format.remove_srgb_suffix() {
+            F::Bc1RgbaUnorm => bc1,
+            F::Bc2RgbaUnorm => bc2,
+            F::Bc3RgbaUnorm => bc3,
+            F::Bc4RUnorm => bc4,
+            F::Bc4RSnorm => bc4_snorm,
+            F::Bc5RgUnorm => bc5,
+            F::Bc5RgSnorm => bc5_snorm,
+            F::Bc6hRgbUfloat => return Ok(Self::Rgba16Float(bptc_decode::bc6h_unsigned)),
+            F::Bc6hRgbFloat => return Ok(Self::Rgba16Float(bptc_decode::bc6h_signed)),
+            F::Bc7RgbaUnorm => bptc_decode::bc7,
+            F::Etc2Rgb8Unorm => etc2_rgb8,
+            F::Etc2Rgb8A1Unorm => etc2_rgb8a1,
+            F::Etc2Rgba8Unorm => etc2_rgba8,
+            F::EacR11Unorm => return Ok(Self::Rgba16Float(eac_r11)),
+            F::EacR11Snorm => return Ok(Self::Rgba16Float(eac_r11_snorm)),
+            F::EacRg11Unorm => return Ok(Self::Rgba16Float(eac_rg11)),
+            F::EacRg11Snorm => return Ok(Self::Rgba16Float(eac_rg11_snorm)),
+            F::Astc { channel, .. } => {
+                if channel == wgpu::AstcChannel::Hdr {
+                    anyhow::bail!("No software decoder for {:?}, only for LDR ASTC", format);
+                }
+                let (width, height) = format.block_dimensions();
+                return Ok(Self::Astc {
+                    width: width as usize,
+                    height: height as usize,
+                    srgb: channel == wgpu::AstcChannel::UnormSrgb,
+                });
+            }
+            _ => anyhow::bail!("No software decoder for {:?}", format),
+        }))
+    }
+
+    // Decodes one block into `texels`, rows of decoded_format texels
+    fn decode_block(&self, block: &[u8], texels: &mut [u8]) {
+        match self {
+            Self::Rgba8(decode) => texels.copy_from_slice(decode(block).as_flattened()),
+            Self::Rgba16Float(decode) => {
+                for (bytes, half) in texels.chunks_exact_mut(2).zip(decode(block).as_flattened()) {
+                    bytes.copy_from_slice(&half.to_le_bytes());
+                }
+            }
+            Self::Astc {
+                width,
+                height,
+                srgb,
+            } => astc_decode::decode_block(block, *width, *height, *srgb, texels),
+        }
+    }
+}
+
+// The format `format` is decoded to
+pub(crate) fn decoded_format(format: wgpu::TextureFormat) -> wgpu::TextureFormat {
+    use wgpu::TextureFormat as F;
+
+    match format {
+        F::Bc4RSnorm | F::Bc5RgSnorm => F::Rgba8Snorm,
+        F::Bc6hRgbUfloat
+        | F::Bc6hRgbFloat
+        | F::EacR11Unorm
+        | F::EacR11Snorm
+        | F::EacRg11Unorm
+        | F::EacRg11Snorm => F::Rgba16Float,
+        _ if format.is_srgb() => F::Rgba8UnormSrgb,
+        _ => F::Rgba8Unorm,
+    }
+}
+
+// Decodes one level of `format` into tightly packed rows of decoded_format(format) texels
+pub(crate) fn decode(
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let decoder = Decoder::new(format)?;
+    let texel_size = decoded_format(format).block_copy_size(None).unwrap_or(4) as usize;
+
+    let block_size = format.block_copy_size(None).unwrap_or(0) as usize;
+    let (block_width, block_height) = format.block_dimensions();
+    let (block_width, block_height) = (block_width as usize, block_height as usize);
+    let blocks_wide = (width as usize).div_ceil(block_width);
+    let blocks_high = (height as usize).div_ceil(block_height);
+    if data.len() < blocks_wide * blocks_high * block_size {
+        anyhow::bail!(
+            "Not enough data for a {}x{} {:?} level",
+            width,
+            height,
+            format
+        );
+    }
+
+    let (width, height) = (width as usize, height as usize);
+    let mut decoded = vec![0; width * height * texel_size];
+    let mut texels = vec![0; block_width * block_height * texel_size];
+    for (i, block) in data
+        .chunks_exact(block_size)
+        .take(blocks_wide * blocks_high)
+        .enumerate()
+    {
+        decoder.decode_block(block, &mut texels);
+        let (bx, by) = (
+            i % blocks_wide * block_width,
+            i / blocks_wide * block_height,
+        );
+        // Blocks on the right and bottom edges can stick out of the texture
+        let row_size = block_width.min(width - bx) * texel_size;
+        for (row, y) in (by..height.min(by + block_height)).enumerate() {
+            let offset = (y * width + bx) * texel_size;
+            let start = row * block_width * texel_size;
+            decoded[offset..offset + row_size].copy_from_slice(&texels[start..start + row_size]);
+        }
+    }
+    Ok(decoded)
+}
+
+// BC1 (DXT1): two RGB565 endpoints and a 2 bit index per texel choosing between them
+// and two colors in between. 8 bytes per block.
+fn bc1(block: &[u8]) -> Block {
+    bc1_color(block, true)
+}
+
+// BC2 (DXT3): 4 bit alpha per texel followed by a BC1 color block
+fn bc2(block: &[u8]) -> Block {
+    let mut texels = bc1_color(&block[8..], false);
+    let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[3] = ((alpha >> (4 * i)) & 0xf) as u8 * 17;
+    }
+    texels
+}
+
+// BC3 (DXT5): a BC4 block for alpha followed by a BC1 color block
+fn bc3(block: &[u8]) -> Block {
+    let mut texels = bc1_color(&block[8..], false);
+    for (texel, alpha) in texels.iter_mut().zip(bc4_channel(&block[..8])) {
+        texel[3] = alpha;
+    }
+    texels
+}
+
+// BC4: a single channel, stored in red
+fn bc4(block: &[u8]) -> Block {
+    bc4_channel(block).map(|r| [r, 0, 0, 255])
+}
+
+// BC5: two BC4 blocks, red then green (typically a normal map)
+fn bc5(block: &[u8]) -> Block {
+    let red = bc4_channel(&block[..8]);
+    let green = bc4_channel(&block[8..]);
+    std::array::from_fn(|i| [red[i], green[i], 0, 255])
+}
+
+// BC4 and BC5 signed: the same with signed endpoints, decoded to snorm (-127 is -1.0)
+fn bc4_snorm(block: &[u8]) -> Block {
+    bc4_snorm_channel(block).map(|r| [r, 0, 0, 127])
+}
+
+fn bc5_snorm(block: &[u8]) -> Block {
+    let red = bc4_snorm_channel(&block[..8]);
+    let green = bc4_snorm_channel(&block[8..]);
+    std::array::from_fn(|i| [red[i], green[i], 0, 127])
+}
+
+fn bc1_color(block: &[u8], allow_alpha: bool) -> Block {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (e0, e1) = (rgb565(c0), rgb565(c1));
+    let mix = |a: u8, b: u8, wa: u32, wb: u32| ((a as u32 * wa + b as u32 * wb) / (wa + wb)) as u8;
+    let lerp = |wa, wb| -> [u8; 4] {
+        [
+            mix(e0[0], e1[0], wa, wb),
+            mix(e0[1], e1[1], wa, wb),
+            mix(e0[2], e1[2], wa, wb),
+            255,
+        ]
+    };
+
+    // The endpoint order selects the mode. BC2 and BC3 always use 4 colors.
+    let palette = if c0 > c1 || !allow_alpha {
+        [e0, e1, lerp(2, 1), lerp(1, 2)]
+    } else {
+        [e0, e1, lerp(1, 1), [0, 0, 0, 0]]
+    };
+    let indices = u32::from_le_bytes(block[4..8].try_into().unwrap());
+    std::array::from_fn(|i| palette[((indices >> (2 * i)) & 3) as usize])
+}
+
+fn rgb565(c: u16) -> [u8; 4] {
+    let r = ((c >> 11) & 0x1f) as u8;
+    let g = ((c >> 5) & 0x3f) as u8;
+    let b = (c & 0x1f) as u8;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+        255,
+    ]
+}
+
+// Two 8 bit endpoints and a 3 bit index per texel. Like BC1 the endpoint order
+// selects between 8 interpolated values or 6 plus 0 and 255.
+fn bc4_channel(block: &[u8]) -> [u8; 16] {
+    bc4_palette_channel(block, block[0] as i32, block[1] as i32, 0, 255).map(|r| r as u8)
+}
+
+// Signed endpoints select between 6 values plus -1.0 and 1.0. -128 is also -1.0, once
+// interpolated.
+fn bc4_snorm_channel(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as i8 as i32, block[1] as i8 as i32);
+    bc4_palette_channel(block, a0, a1, -127, 127).map(|r| r.max(-127) as i8 as u8)
+}
+
+fn bc4_palette_channel(block: &[u8], a0: i32, a1: i32, min: i32, max: i32) -> [i32; 16] {
+    // Values in between are interpolated with weights in 1/256 (rounded down), like GPUs
+    let mix = |weight: i32, steps: i32| a0 + (((a1 - a0) * (weight * 255 / steps)) >> 8);
+    let palette: [i32; 8] = if a0 > a1 {
+        std::array::from_fn(|i| match i {
+            0 => a0,
+            1 => a1,
+            _ => mix(i as i32 - 1, 7),
+        })
+    } else {
+        std::array::from_fn(|i| match i {
+            0 => a0,
+            1 => a1,
+            6 => min,
+            7 => max,
+            _ => mix(i as i32 - 1, 5),
+        })
+    };
+    let mut bits = [0; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+    std::array::from_fn(|i| palette[((indices >> (3 * i)) & 7) as usize])
+}
+
+// ETC2 RGB: 64 bits, read big-endian. The two 'diff' and 'flip' bits and overflowing
+// base colors select one of 5 modes, all sharing the same 2 bit per texel indices.
+// Texel indices are stored column by column.
+fn etc2_rgb8(block: &[u8]) -> Block {
+    etc2_color(block, false)
+}
+
+// ETC2 RGB8A1 (punchthrough alpha): the 'diff' bit says whether the block is opaque
+// instead, and there is no individual mode. In transparent blocks index 2 is transparent
+// black, and the differential mode replaces its small modifiers with 0.
+fn etc2_rgb8a1(block: &[u8]) -> Block {
+    etc2_color(block, true)
+}
+
+fn etc2_color(block: &[u8], punchthrough: bool) -> Block {
+    let bits = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let b = [block[0], block[1], block[2], block[3]];
+    let index = |i: usize| {
+        let p = (i % 4) * 4 + i / 4;
+        (((bits >> (16 + p)) & 1) << 1 | ((bits >> p) & 1)) as usize
+    };
+
+    let diff = b[3] & 2 != 0;
+    if !diff && !punchthrough {
+        // Individual mode: two 4 bit colors
+        let c1 = [b[0] >> 4, b[1] >> 4, b[2] >> 4].map(extend4);
+        let c2 = [b[0] & 0xf, b[1] & 0xf, b[2] & 0xf].map(extend4);
+        return etc_subblocks(b, c1, c2, index, true);
+    }
+    let opaque = diff || !punchthrough;
+
+    // Differential mode: a 5 bit color and a 3 bit signed offset for the second one.
+    // An offset that overflows the 5 bits is impossible in ETC1 and selects another mode.
+    let base = [b[0] >> 3, b[1] >> 3, b[2] >> 3].map(|c| c as i32);
+    let delta = [b[0], b[1], b[2]].map(|c| ((c as i32 & 7) << 29) >> 29);
+    let second: [i32; 3] = std::array::from_fn(|i| base[i] + delta[i]);
+    let overflows = |i: usize| !(0..32).contains(&second[i]);
+    let texels = if overflows(0) {
+        etc2_t_mode(b, index)
+    } else if overflows(1) {
+        etc2_h_mode(b, index)
+    } else if overflows(2) {
+        // Always opaque
+        return etc2_planar(bits);
+    } else {
+        let c1 = base.map(|c| extend5(c as u8));
+        let c2 = second.map(|c| extend5(c as u8));
+        etc_subblocks(b, c1, c2, index, opaque)
+    };
+    if opaque {
+        return texels;
+    }
+    std::array::from_fn(|i| if index(i) == 2 { [0; 4] } else { texels[i] })
+}
+
+pub(crate) const ETC_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+// Individual and differential modes: the block is split in two 2x4 (or 4x2 when flipped)
+// halves, each with its base color and a table of brightness modifiers
+fn etc_subblocks(
+    b: [u8; 4],
+    c1: [u8; 3],
+    c2: [u8; 3],
+    index: impl Fn(usize) -> usize,
+    opaque: bool,
+) -> Block {
+    let flip = b[3] & 1 != 0;
+    let tables = [(b[3] >> 5) as usize, ((b[3] >> 2) & 7) as usize];
+    std::array::from_fn(|i| {
+        let (x, y) = (i % 4, i / 4);
+        let second = if flip { y >= 2 } else { x >= 2 };
+        let (color, table) = if second {
+            (c2, tables[1])
+        } else {
+            (c1, tables[0])
+        };
+        let [small, large] = ETC_MODIFIERS[table];
+        let modifiers = if opaque {
+            [small, large, -small, -large]
+        } else {
+            [0, large, 0, -large]
+        };
+        let modifier = modifiers[index(i)];
+        let [r, g, b] = color.map(|c| (c as i32 + modifier).clamp(0, 255) as u8);
+        [r, g, b, 255]
+    })
+}
+
+const ETC2_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+// T mode: one color, and a second one with a distance added or subtracted
+fn etc2_t_mode(b: [u8; 4], index: impl Fn(usize) -> usize) -> Block {
+    let c1 = [((b[0] >> 1) & 0xc) | (b[0] & 3), b[1] >> 4, b[1] & 0xf].map(extend4);
+    let c2 = [b[2] >> 4, b[2] & 0xf, b[3] >> 4].map(extend4);
+    let d = ETC2_DISTANCES[(((b[3] >> 1) & 6) | (b[3] & 1)) as usize];
+    let palette = [c1, offset(c2, d), c2, offset(c2, -d)];
+    std::array::from_fn(|i| rgba(palette[index(i)]))
+}
+
+// H mode: two colors, each with the distance added and subtracted
+fn etc2_h_mode(b: [u8; 4], index: impl Fn(usize) -> usize) -> Block {
+    let c1 = [
+        (b[0] >> 3) & 0xf,
+        ((b[0] & 7) << 1) | ((b[1] >> 4) & 1),
+        (b[1] & 8) | ((b[1] & 3) << 1) | (b[2] >> 7),
+    ];
+    let c2 = [
+        (b[2] >> 3) & 0xf,
+        ((b[2] & 7) << 1) | (b[3] >> 7),
+        (b[3] >> 3) & 0xf,
+    ];
+    // The last bit of the distance is whether the first color is the larger one
+    let value = |c: [u8; 3]| (c[0] as u32) << 8 | (c[1] as u32) << 4 | c[2] as u32;
+    let larger = (value(c1) >= value(c2)) as u8;
+    let d = ETC2_DISTANCES[(((b[3] >> 2) & 1) << 2 | (b[3] & 1) << 1 | larger) as usize];
+    let (c1, c2) = (c1.map(extend4), c2.map(extend4));
+    let palette = [offset(c1, d), offset(c1, -d), offset(c2, d), offset(c2, -d)];
+    std::array::from_fn(|i| rgba(palette[index(i)]))
+}
+
+// Planar mode: a gradient between the colors at the origin, right (H) and bottom (V)
+fn etc2_planar(bits: u64) -> Block {
+    let field = |shift: u32, len: u32| ((bits >> shift) & ((1 << len) - 1)) as u8;
+    let origin = [
+        extend6(field(57, 6)),
+        extend7(field(56, 1) << 6 | field(49, 6)),
+        extend6(field(48, 1) << 5 | field(43, 2) << 3 | field(39, 3)),
+    ];
+    let horizontal = [
+        extend6(field(34, 5) << 1 | field(32, 1)),
+        extend7(field(25, 7)),
+        extend6(field(19, 6)),
+    ];
+    let vertical = [
+        extend6(field(13, 6)),
+        extend7(field(6, 7)),
+        extend6(field(0, 6)),
+    ];
+    std::array::from_fn(|i| {
+        let (x, y) = ((i % 4) as i32, (i / 4) as i32);
+        let channel = |c: usize| {
+            let (o, h, v) = (origin[c] as i32, horizontal[c] as i32, vertical[c] as i32);
+            ((x * (h - o) + y * (v - o) + 4 * o + 2) >> 2).clamp(0, 255) as u8
+        };
+        [channel(0), channel(1), channel(2), 255]
+    })
+}
+
+// ETC2 RGBA: an EAC block for alpha followed by an ETC2 RGB block
+fn etc2_rgba8(block: &[u8]) -> Block {
+    let mut texels = etc2_rgb8(&block[8..]);
+    for (texel, alpha) in texels.iter_mut().zip(eac_channel(&block[..8])) {
+        texel[3] = alpha;
+    }
+    texels
+}
+
+pub(crate) const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+// EAC: an 8 bit base value, a multiplier and a table of offsets picked with 3 bit indices
+fn eac_channel(block: &[u8]) -> [u8; 16] {
+    let base = block[0] as i32;
+    let multiplier = (block[1] >> 4) as i32;
+    eac_indices(block).map(|modifier| (base + modifier * multiplier).clamp(0, 255) as u8)
+}
+
+// The table offset of each texel, in row-major order
+fn eac_indices(block: &[u8]) -> [i32; 16] {
+    let bits = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let table = EAC_MODIFIERS[(block[1] & 0xf) as usize];
+    std::array::from_fn(|i| {
+        let p = (i % 4) * 4 + i / 4;
+        table[((bits >> (45 - 3 * p)) & 7) as usize]
+    })
+}
+
+// EAC R11 and RG11: the same blocks for 11 bit red (and green) channels, with 8 times
+// the precision. Half floats keep it.
+fn eac_r11(block: &[u8]) -> [[u16; 4]; 16] {
+    eac_r11_channel(block).map(|r| [r, 0, 0, HALF_ONE])
+}
+
+fn eac_rg11(block: &[u8]) -> [[u16; 4]; 16] {
+    let red = eac_r11_channel(&block[..8]);
+    let green = eac_r11_channel(&block[8..]);
+    std::array::from_fn(|i| [red[i], green[i], 0, HALF_ONE])
+}
+
+fn eac_r11_snorm(block: &[u8]) -> [[u16; 4]; 16] {
+    eac_r11_snorm_channel(block).map(|r| [r, 0, 0, HALF_ONE])
+}
+
+fn eac_rg11_snorm(block: &[u8]) -> [[u16; 4]; 16] {
+    let red = eac_r11_snorm_channel(&block[..8]);
+    let green = eac_r11_snorm_channel(&block[8..]);
+    std::array::from_fn(|i| [red[i], green[i], 0, HALF_ONE])
+}
+
+// 1.0 as a half float, the alpha of the RGBA16 float texels
+pub(crate) const HALF_ONE: u16 = half::f16::ONE.to_bits();
+
+fn to_half(value: f32) -> u16 {
+    half::f16::from_f32(value).to_bits()
+}
+
+// 0..=2047, with the base in the middle of its 8 values
+fn eac_r11_channel(block: &[u8]) -> [u16; 16] {
+    let base = block[0] as i32 * 8 + 4;
+    let multiplier = eac_r11_multiplier(block);
+    eac_indices(block).map(|modifier| {
+        let value = (base + modifier * multiplier).clamp(0, 2047);
+        to_half(value as f32 / 2047.0)
+    })
+}
+
+// -1023..=1023 from a signed base, -128 being the same as -127
+fn eac_r11_snorm_channel(block: &[u8]) -> [u16; 16] {
+    let base = (block[0] as i8).max(-127) as i32 * 8;
+    let multiplier = eac_r11_multiplier(block);
+    eac_indices(block).map(|modifier| {
+        let value = (base + modifier * multiplier).clamp(-1023, 1023);
+        to_half(value as f32 / 1023.0)
+    })
+}
+
+// A multiplier of 0 doesn't scale the offsets, so the values stay close to the base
+fn eac_r11_multiplier(block: &[u8]) -> i32 {
+    match block[1] >> 4 {
+        0 => 1,
+        multiplier => multiplier as i32 * 8,
+    }
+}
+
+fn offset(color: [u8; 3], d: i32) -> [u8; 3] {
+    color.map(|c| (c as i32 + d).clamp(0, 255) as u8)
+}
+
+fn rgba([r, g, b]: [u8; 3]) -> [u8; 4] {
+    [r, g, b, 255]
+}
+
+// Expand n bit values to 8 bits by repeating the high bits, so the maximum stays 255
+fn extend4(c: u8) -> u8 {
+    (c << 4) | c
+}
+
+pub(crate) fn extend5(c: u8) -> u8 {
+    (c << 3) | (c >> 2)
+}
+
+fn extend6(c: u8) -> u8 {
+    (c << 2) | (c >> 4)
+}
+
+fn extend7(c: u8) -> u8 {
+    (c << 1) | (c >> 6)
+}
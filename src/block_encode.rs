@@ -0,0 +1,172 @@
+// Software encoders for the formats Basis Universal textures are transcoded to when the
+// device has no format their blocks can be copied to (see basis.rs). They make one
+// quick pass per block: good enough for data that was already compressed once, far from
+// what an offline encoder does.
+
+use crate::{
+    block_decode::{Block, EAC_MODIFIERS},
+    bptc_decode::WEIGHTS4,
+};
+
+// BC7 mode 6: one subset of RGBA endpoints (7 bits and a low bit shared by the channels
+// of each endpoint), with 4 bit indices. The endpoints are the ends of the line that
+// fits the texels best, the indices the closest points on it.
+pub(crate) fn bc7(texels: &Block) -> [u8; 16] {
+    let texels = texels.map(|texel| texel.map(|c| c as f32));
+    let mean: [f32; 4] =
+        std::array::from_fn(|c| texels.iter().map(|texel| texel[c]).sum::<f32>() / 16.0);
+    let axis = principal_axis(&texels, mean);
+    let project = |texel: &[f32; 4]| (0..4).map(|c| (texel[c] - mean[c]) * axis[c]).sum::<f32>();
+    let (low, high) = texels
+        .iter()
+        .map(project)
+        .fold((f32::MAX, f32::MIN), |(low, high), t| {
+            (low.min(t), high.max(t))
+        });
+    let endpoint = |t: f32| quantize_bc7_endpoint(std::array::from_fn(|c| mean[c] + axis[c] * t));
+    let mut endpoints = [endpoint(low), endpoint(high)];
+
+    let colors: [[i32; 4]; 16] = std::array::from_fn(|i| {
+        let [e0, e1] = endpoints.map(|(value, p)| value.map(|c| ((c << 1) | p) as i32));
+        let weight = WEIGHTS4[i];
+        std::array::from_fn(|c| ((64 - weight) * e0[c] + weight * e1[c] + 32) >> 6)
+    });
+    let mut indices = texels.map(|texel| {
+        (0..16)
+            .min_by_key(|&i| {
+                (0..4)
+                    .map(|c| (texel[c] as i32 - colors[i][c]).pow(2))
+                    .sum::<i32>()
+            })
+            .unwrap()
+    });
+    // The top bit of the first index isn't stored: swapping the endpoints clears it
+    if indices[0] >= 8 {
+        endpoints.swap(0, 1);
+        indices = indices.map(|index| 15 - index);
+    }
+
+    let mut bits = 1u128 << 6;
+    let mut position = 7;
+    let mut write = |value: u32, count: u32| {
+        bits |= (value as u128) << position;
+        position += count;
+    };
+    for c in 0..4 {
+        for (value, _) in endpoints {
+            write(value[c] as u32, 7);
+        }
+    }
+    for (_, p) in endpoints {
+        write(p as u32, 1);
+    }
+    for (i, index) in indices.into_iter().enumerate() {
+        write(index as u32, if i == 0 { 3 } else { 4 });
+    }
+    bits.to_le_bytes()
+}
+
+// The direction along which the texels vary the most, by power iteration on their
+// covariance, starting from the channel that varies the most. Blocks of a single color
+// give that channel's axis.
+fn principal_axis(texels: &[[f32; 4]; 16], mean: [f32; 4]) -> [f32; 4] {
+    let mut covariance = [[0.0f32; 4]; 4];
+    for texel in texels {
+        for (i, row) in covariance.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value += (texel[i] - mean[i]) * (texel[j] - mean[j]);
+            }
+        }
+    }
+    let widest = (0..4)
+        .max_by(|&a, &b| covariance[a][a].total_cmp(&covariance[b][b]))
+        .unwrap();
+    let mut axis: [f32; 4] = std::array::from_fn(|c| (c == widest) as u32 as f32);
+    for _ in 0..8 {
+        let next: [f32; 4] =
+            std::array::from_fn(|i| (0..4).map(|j| covariance[i][j] * axis[j]).sum());
+        let length = next.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if length < 1e-6 {
+            break;
+        }
+        axis = next.map(|v| v / length);
+    }
+    axis
+}
+
+// 7 bits per channel and the shared low bit giving the closest 8 bit color
+fn quantize_bc7_endpoint(color: [f32; 4]) -> ([u8; 4], u8) {
+    [0, 1]
+        .map(|p| {
+            let value = color.map(|c| {
+                ((c.clamp(0.0, 255.0) - p as f32) / 2.0)
+                    .round()
+                    .clamp(0.0, 127.0) as u8
+            });
+            let error: f32 = (0..4)
+                .map(|c| (color[c] - ((value[c] << 1) | p) as f32).powi(2))
+                .sum();
+            (value, p, error)
+        })
+        .into_iter()
+        .min_by(|a, b| a.2.total_cmp(&b.2))
+        .map(|(value, p, _)| (value, p))
+        .unwrap()
+}
+
+// EAC (the alpha of ETC2 RGBA8): the base value, table and multiplier whose 8 values
+// cover the texels best
+pub(crate) fn eac(values: [u8; 16]) -> [u8; 8] {
+    let min = *values.iter().min().unwrap() as i32;
+    let max = *values.iter().max().unwrap() as i32;
+    let mut best = (i32::MAX, [0u8; 8]);
+    for (table, modifiers) in EAC_MODIFIERS.iter().enumerate() {
+        let (low, high) = (modifiers[3], modifiers[7]);
+        // The multiplier stretching the table over the values, and its neighbours
+        let multiplier = ((max - min) as f32 / (high - low) as f32).round() as i32;
+        for multiplier in (multiplier - 1).max(1)..=(multiplier + 1).min(15) {
+            let center = (min + max) as f32 / 2.0 - (low + high) as f32 * multiplier as f32 / 2.0;
+            for base in [-1, 0, 1].map(|d| (center.round() as i32 + d).clamp(0, 255)) {
+                let decoded = modifiers.map(|m| (base + m * multiplier).clamp(0, 255));
+                let mut error = 0;
+                let mut bits = 0u64;
+                for (i, &value) in values.iter().enumerate() {
+                    let (index, index_error) = decoded
+                        .iter()
+                        .map(|&d| (d - value as i32).pow(2))
+                        .enumerate()
+                        .min_by_key(|&(_, e)| e)
+                        .unwrap();
+                    error += index_error;
+                    // Indices are stored column by column, from the top bits down
+                    let p = (i % 4) * 4 + i / 4;
+                    bits |= (index as u64) << (45 - 3 * p);
+                }
+                if error < best.0 {
+                    bits |= (base as u64) << 56 | (multiplier as u64) << 52 | (table as u64) << 48;
+                    best = (error, bits.to_be_bytes());
+                }
+            }
+        }
+    }
+    best.1
+}
+
+// An RGBA8 image as BC7 blocks, rows of blocks top to bottom. Blocks past the right or
+// bottom edge repeat the last texels.
+pub(crate) fn bc7_image(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let mut blocks = Vec::with_capacity(width.div_ceil(4) * height.div_ceil(4) * 16);
+    for block_y in (0..height).step_by(4) {
+        for block_x in (0..width).step_by(4) {
+            let texels: Block = std::array::from_fn(|i| {
+                let x = (block_x + i % 4).min(width - 1);
+                let y = (block_y + i / 4).min(height - 1);
+                let start = (y * width + x) * 4;
+                rgba[start..start + 4].try_into().unwrap()
+            });
+            blocks.extend_from_slice(&bc7(&texels));
+        }
+    }
+    blocks
+}
@@ -0,0 +1,831 @@
+// Software decoders for BC6H and BC7 (BPTC), the high quality formats of desktop GPUs.
+// Both use 128 bit blocks of 4x4 texels, read as a little-endian bitstream. A block picks
+// one of several modes, which set how many subsets (groups of texels sharing two
+// endpoints) it has, the precision of the endpoints and of the per-texel indices
+// interpolating between them. The texels of a subset are given by one of the partitions
+// of the tables below.
+
+use crate::block_decode::{Block, HALF_ONE};
+
+// Reads fields of a block from the lowest bit up
+struct Bits {
+    bits: u128,
+    position: u32,
+}
+
+impl Bits {
+    fn new(block: &[u8]) -> Self {
+        Self {
+            bits: u128::from_le_bytes(block[..16].try_into().unwrap()),
+            position: 0,
+        }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let value = self.bits.checked_shr(self.position).unwrap_or(0) & ((1 << count) - 1);
+        self.position += count;
+        value as u32
+    }
+}
+
+// Interpolation weights out of 64 for 2, 3 and 4 bit indices
+const WEIGHTS2: [i32; 4] = [0, 21, 43, 64];
+const WEIGHTS3: [i32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+pub(crate) const WEIGHTS4: [i32; 16] =
+    [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn weight(index_bits: u32, index: u32) -> i32 {
+    match index_bits {
+        2 => WEIGHTS2[index as usize],
+        3 => WEIGHTS3[index as usize],
+        _ => WEIGHTS4[index as usize],
+    }
+}
+
+// Which subset each texel is in for the 64 two-subset partitions, one bit per texel
+#[rustfmt::skip]
+const PARTITIONS2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80,
+    0xc800, 0xffec, 0xfe80, 0xe800, 0xffe8, 0xff00, 0xfff0, 0xf000,
+    0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c,
+    0xaaaa, 0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a,
+    0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c,
+    0x9336, 0x9cc6, 0x817e, 0xe718, 0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+// The same for the 64 three-subset partitions of BC7
+#[rustfmt::skip]
+const PARTITIONS3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2],
+    [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0],
+    [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0],
+    [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2],
+    [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0],
+    [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0],
+    [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1],
+    [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1],
+    [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2],
+    [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2],
+    [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1],
+    [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+// The first texel of every subset is its anchor, whose index has one bit less (the top bit
+// is always 0). Subset 0 starts at texel 0, these are the anchors of the other subsets.
+#[rustfmt::skip]
+const ANCHORS2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+#[rustfmt::skip]
+const ANCHORS3: [[u8; 2]; 64] = [
+    [3, 15], [3, 8], [15, 8], [15, 3], [8, 15], [3, 15], [15, 3], [15, 8],
+    [8, 15], [8, 15], [6, 15], [6, 15], [6, 15], [5, 15], [3, 15], [3, 8],
+    [3, 15], [3, 8], [8, 15], [15, 3], [3, 15], [3, 8], [6, 15], [10, 8],
+    [5, 3], [8, 15], [8, 6], [6, 10], [8, 15], [5, 15], [15, 10], [15, 8],
+    [8, 15], [15, 3], [3, 15], [5, 10], [6, 10], [10, 8], [8, 9], [15, 10],
+    [15, 6], [3, 15], [15, 8], [5, 15], [15, 3], [15, 6], [15, 6], [15, 8],
+    [3, 15], [15, 3], [5, 15], [5, 15], [5, 15], [8, 15], [5, 15], [10, 15],
+    [5, 15], [10, 15], [8, 15], [13, 15], [15, 3], [12, 15], [3, 15], [3, 8],
+];
+
+// The subset of texel `texel` and whether it is the anchor of its subset
+fn subset(subsets: u32, partition: usize, texel: usize) -> (usize, bool) {
+    match subsets {
+        1 => (0, texel == 0),
+        2 => {
+            let subset = (PARTITIONS2[partition] >> texel) as usize & 1;
+            let anchor = [0, ANCHORS2[partition] as usize][subset];
+            (subset, texel == anchor)
+        }
+        _ => {
+            let subset = PARTITIONS3[partition][texel] as usize;
+            let [second, third] = ANCHORS3[partition];
+            let anchor = [0, second as usize, third as usize][subset];
+            (subset, texel == anchor)
+        }
+    }
+}
+
+struct Bc7Mode {
+    subsets: u32,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    // A low bit shared by all the channels of each endpoint, or by both endpoints
+    // of each subset
+    endpoint_p_bits: bool,
+    shared_p_bits: bool,
+    index_bits: u32,
+    // Modes 4 and 5 have a second set of indices, for alpha
+    index2_bits: u32,
+}
+
+const fn bc7_mode(fields: [u32; 10]) -> Bc7Mode {
+    Bc7Mode {
+        subsets: fields[0],
+        partition_bits: fields[1],
+        rotation_bits: fields[2],
+        index_selection_bits: fields[3],
+        color_bits: fields[4],
+        alpha_bits: fields[5],
+        endpoint_p_bits: fields[6] == 1,
+        shared_p_bits: fields[7] == 1,
+        index_bits: fields[8],
+        index2_bits: fields[9],
+    }
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    bc7_mode([3, 4, 0, 0, 4, 0, 1, 0, 3, 0]),
+    bc7_mode([2, 6, 0, 0, 6, 0, 0, 1, 3, 0]),
+    bc7_mode([3, 6, 0, 0, 5, 0, 0, 0, 2, 0]),
+    bc7_mode([2, 6, 0, 0, 7, 0, 1, 0, 2, 0]),
+    bc7_mode([1, 0, 2, 1, 5, 6, 0, 0, 2, 3]),
+    bc7_mode([1, 0, 2, 0, 7, 8, 0, 0, 2, 2]),
+    bc7_mode([1, 0, 0, 0, 7, 7, 1, 0, 4, 0]),
+    bc7_mode([2, 6, 0, 0, 5, 5, 1, 0, 2, 0]),
+];
+
+// BC7: RGBA with up to 3 subsets. The mode is the number of 0 bits before the first 1.
+pub(crate) fn bc7(block: &[u8]) -> Block {
+    let mut bits = Bits::new(block);
+    // A block without a mode is invalid and decodes to transparent black
+    let Some(mode) = (0..8).find(|_| bits.read(1) == 1) else {
+        return [[0; 4]; 16];
+    };
+    let mode = &BC7_MODES[mode];
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    // Each channel of every endpoint, then the alpha of every endpoint
+    let endpoint_count = mode.subsets as usize * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+    for channel in 0..3 {
+        for endpoint in &mut endpoints[..endpoint_count] {
+            endpoint[channel] = bits.read(mode.color_bits);
+        }
+    }
+    for endpoint in &mut endpoints[..endpoint_count] {
+        endpoint[3] = bits.read(mode.alpha_bits);
+    }
+
+    let (mut color_bits, mut alpha_bits) = (mode.color_bits, mode.alpha_bits);
+    if mode.endpoint_p_bits || mode.shared_p_bits {
+        let p_bits: Vec<u32> = if mode.endpoint_p_bits {
+            (0..endpoint_count).map(|_| bits.read(1)).collect()
+        } else {
+            (0..mode.subsets)
+                .map(|_| bits.read(1))
+                .flat_map(|p| [p, p])
+                .collect()
+        };
+        for (endpoint, p) in endpoints.iter_mut().zip(p_bits) {
+            for value in endpoint.iter_mut() {
+                *value = (*value << 1) | p;
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+    // Replicate the high bits to fill 8 bits
+    let expand = |value: u32, bits: u32| {
+        let value = value << (8 - bits);
+        value | (value >> bits)
+    };
+    for endpoint in &mut endpoints {
+        for value in &mut endpoint[..3] {
+            *value = expand(*value, color_bits);
+        }
+        endpoint[3] = if alpha_bits > 0 {
+            expand(endpoint[3], alpha_bits)
+        } else {
+            255
+        };
+    }
+
+    let texels: [(usize, bool); 16] = std::array::from_fn(|i| subset(mode.subsets, partition, i));
+    let indices: [u32; 16] =
+        std::array::from_fn(|i| bits.read(mode.index_bits - texels[i].1 as u32));
+    let indices2: [u32; 16] =
+        std::array::from_fn(|i| bits.read(mode.index2_bits.saturating_sub((i == 0) as u32)));
+
+    std::array::from_fn(|i| {
+        let subset = texels[i].0;
+        let (e0, e1) = (endpoints[subset * 2], endpoints[subset * 2 + 1]);
+        // The index selection bit swaps which set of indices is used for the colors
+        let (color_weight, alpha_weight) = match (mode.index2_bits, index_selection) {
+            (0, _) => {
+                let weight = weight(mode.index_bits, indices[i]);
+                (weight, weight)
+            }
+            (_, 0) => (
+                weight(mode.index_bits, indices[i]),
+                weight(mode.index2_bits, indices2[i]),
+            ),
+            _ => (
+                weight(mode.index2_bits, indices2[i]),
+                weight(mode.index_bits, indices[i]),
+            ),
+        };
+        let interpolate = |channel: usize, weight: i32| {
+            let (a, b) = (e0[channel] as i32, e1[channel] as i32);
+            ((64 - weight) * a + weight * b + 32) >> 6
+        };
+        let mut texel = [0, 1, 2, 3].map(|channel| {
+            let weight = if channel == 3 {
+                alpha_weight
+            } else {
+                color_weight
+            };
+            interpolate(channel, weight) as u8
+        });
+        // The rotation swaps alpha with one of the colors
+        if rotation > 0 {
+            texel.swap(3, rotation as usize - 1);
+        }
+        texel
+    })
+}
+
+// The endpoint values of BC6H blocks: endpoints w and x of subset 0, then y and z of
+// subset 1, each with red, green and blue
+const RW: u8 = 0;
+const GW: u8 = 1;
+const BW: u8 = 2;
+const RX: u8 = 3;
+const GX: u8 = 4;
+const BX: u8 = 5;
+const RY: u8 = 6;
+const GY: u8 = 7;
+const BY: u8 = 8;
+const RZ: u8 = 9;
+const GZ: u8 = 10;
+const BZ: u8 = 11;
+
+struct Bc6hMode {
+    // Value of the 2 or 5 mode bits
+    code: u32,
+    subsets: u32,
+    endpoint_bits: u32,
+    // Whether the other endpoints are stored as deltas from w, with fewer bits
+    transformed: bool,
+    delta_bits: [u32; 3],
+    // The fields after the mode bits in order: (value, lowest bit, bit count). The high bits
+    // of some modes are stored in reverse, they are listed one by one.
+    fields: &'static [(u8, u8, u8)],
+}
+
+// The bit layouts of the 14 modes are not regular at all, they are listed as in the
+// Direct3D documentation
+const BC6H_MODES: [Bc6hMode; 14] = [
+    Bc6hMode {
+        code: 0b00,
+        subsets: 2,
+        endpoint_bits: 10,
+        transformed: true,
+        delta_bits: [5, 5, 5],
+        fields: &[
+            (GY, 4, 1),
+            (BY, 4, 1),
+            (BZ, 4, 1),
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 5),
+            (GZ, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 5),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 5),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 5),
+            (BZ, 2, 1),
+            (RZ, 0, 5),
+            (BZ, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        code: 0b01,
+        subsets: 2,
+        endpoint_bits: 7,
+        transformed: true,
+        delta_bits: [6, 6, 6],
+        fields: &[
+            (GY, 5, 1),
+            (GZ, 4, 1),
+            (GZ, 5, 1),
+            (RW, 0, 7),
+            (BZ, 0, 1),
+            (BZ, 1, 1),
+            (BY, 4, 1),
+            (GW, 0, 7),
+            (BY, 5, 1),
+            (BZ, 2, 1),
+            (GY, 4, 1),
+            (BW, 0, 7),
+            (BZ, 3, 1),
+            (BZ, 5, 1),
+            (BZ, 4, 1),
+            (RX, 0, 6),
+            (GY, 0, 4),
+            (GX, 0, 6),
+            (GZ, 0, 4),
+            (BX, 0, 6),
+            (BY, 0, 4),
+            (RY, 0, 6),
+            (RZ, 0, 6),
+        ],
+    },
+    Bc6hMode {
+        code: 0b00010,
+        subsets: 2,
+        endpoint_bits: 11,
+        transformed: true,
+        delta_bits: [5, 4, 4],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 5),
+            (RW, 10, 1),
+            (GY, 0, 4),
+            (GX, 0, 4),
+            (GW, 10, 1),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 4),
+            (BW, 10, 1),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 5),
+            (BZ, 2, 1),
+            (RZ, 0, 5),
+            (BZ, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        code: 0b00110,
+        subsets: 2,
+        endpoint_bits: 11,
+        transformed: true,
+        delta_bits: [4, 5, 4],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 4),
+            (RW, 10, 1),
+            (GZ, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 5),
+            (GW, 10, 1),
+            (GZ, 0, 4),
+            (BX, 0, 4),
+            (BW, 10, 1),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 4),
+            (BZ, 0, 1),
+            (BZ, 2, 1),
+            (RZ, 0, 4),
+            (GY, 4, 1),
+            (BZ, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        code: 0b01010,
+        subsets: 2,
+        endpoint_bits: 11,
+        transformed: true,
+        delta_bits: [4, 4, 5],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 4),
+            (RW, 10, 1),
+            (BY, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 4),
+            (GW, 10, 1),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 5),
+            (BW, 10, 1),
+            (BY, 0, 4),
+            (RY, 0, 4),
+            (BZ, 1, 1),
+            (BZ, 2, 1),
+            (RZ, 0, 4),
+            (BZ, 4, 1),
+            (BZ, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        code: 0b01110,
+        subsets: 2,
+        endpoint_bits: 9,
+        transformed: true,
+        delta_bits: [5, 5, 5],
+        fields: &[
+            (RW, 0, 9),
+            (BY, 4, 1),
+            (GW, 0, 9),
+            (GY, 4, 1),
+            (BW, 0, 9),
+            (BZ, 4, 1),
+            (RX, 0, 5),
+            (GZ, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 5),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 5),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 5),
+            (BZ, 2, 1),
+            (RZ, 0, 5),
+            (BZ, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        code: 0b10010,
+        subsets: 2,
+        endpoint_bits: 8,
+        transformed: true,
+        delta_bits: [6, 5, 5],
+        fields: &[
+            (RW, 0, 8),
+            (GZ, 4, 1),
+            (BY, 4, 1),
+            (GW, 0, 8),
+            (BZ, 2, 1),
+            (GY, 4, 1),
+            (BW, 0, 8),
+            (BZ, 3, 1),
+            (BZ, 4, 1),
+            (RX, 0, 6),
+            (GY, 0, 4),
+            (GX, 0, 5),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 5),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 6),
+            (RZ, 0, 6),
+        ],
+    },
+    Bc6hMode {
+        code: 0b10110,
+        subsets: 2,
+        endpoint_bits: 8,
+        transformed: true,
+        delta_bits: [5, 6, 5],
+        fields: &[
+            (RW, 0, 8),
+            (BZ, 0, 1),
+            (BY, 4, 1),
+            (GW, 0, 8),
+            (GY, 5, 1),
+            (GY, 4, 1),
+            (BW, 0, 8),
+            (GZ, 5, 1),
+            (BZ, 4, 1),
+            (RX, 0, 5),
+            (GZ, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 6),
+            (GZ, 0, 4),
+            (BX, 0, 5),
+            (BZ, 1, 1),
+            (BY, 0, 4),
+            (RY, 0, 5),
+            (BZ, 2, 1),
+            (RZ, 0, 5),
+            (BZ, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        code: 0b11010,
+        subsets: 2,
+        endpoint_bits: 8,
+        transformed: true,
+        delta_bits: [5, 5, 6],
+        fields: &[
+            (RW, 0, 8),
+            (BZ, 1, 1),
+            (BY, 4, 1),
+            (GW, 0, 8),
+            (BY, 5, 1),
+            (GY, 4, 1),
+            (BW, 0, 8),
+            (BZ, 5, 1),
+            (BZ, 4, 1),
+            (RX, 0, 5),
+            (GZ, 4, 1),
+            (GY, 0, 4),
+            (GX, 0, 5),
+            (BZ, 0, 1),
+            (GZ, 0, 4),
+            (BX, 0, 6),
+            (BY, 0, 4),
+            (RY, 0, 5),
+            (BZ, 2, 1),
+            (RZ, 0, 5),
+            (BZ, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        code: 0b11110,
+        subsets: 2,
+        endpoint_bits: 6,
+        transformed: false,
+        delta_bits: [6, 6, 6],
+        fields: &[
+            (RW, 0, 6),
+            (GZ, 4, 1),
+            (BZ, 0, 1),
+            (BZ, 1, 1),
+            (BY, 4, 1),
+            (GW, 0, 6),
+            (GY, 5, 1),
+            (BY, 5, 1),
+            (BZ, 2, 1),
+            (GY, 4, 1),
+            (BW, 0, 6),
+            (GZ, 5, 1),
+            (BZ, 3, 1),
+            (BZ, 5, 1),
+            (BZ, 4, 1),
+            (RX, 0, 6),
+            (GY, 0, 4),
+            (GX, 0, 6),
+            (GZ, 0, 4),
+            (BX, 0, 6),
+            (BY, 0, 4),
+            (RY, 0, 6),
+            (RZ, 0, 6),
+        ],
+    },
+    Bc6hMode {
+        code: 0b00011,
+        subsets: 1,
+        endpoint_bits: 10,
+        transformed: false,
+        delta_bits: [10, 10, 10],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 10),
+            (GX, 0, 10),
+            (BX, 0, 10),
+        ],
+    },
+    Bc6hMode {
+        code: 0b00111,
+        subsets: 1,
+        endpoint_bits: 11,
+        transformed: true,
+        delta_bits: [9, 9, 9],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 9),
+            (RW, 10, 1),
+            (GX, 0, 9),
+            (GW, 10, 1),
+            (BX, 0, 9),
+            (BW, 10, 1),
+        ],
+    },
+    Bc6hMode {
+        code: 0b01011,
+        subsets: 1,
+        endpoint_bits: 12,
+        transformed: true,
+        delta_bits: [8, 8, 8],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 8),
+            (RW, 11, 1),
+            (RW, 10, 1),
+            (GX, 0, 8),
+            (GW, 11, 1),
+            (GW, 10, 1),
+            (BX, 0, 8),
+            (BW, 11, 1),
+            (BW, 10, 1),
+        ],
+    },
+    Bc6hMode {
+        code: 0b01111,
+        subsets: 1,
+        endpoint_bits: 16,
+        transformed: true,
+        delta_bits: [4, 4, 4],
+        fields: &[
+            (RW, 0, 10),
+            (GW, 0, 10),
+            (BW, 0, 10),
+            (RX, 0, 4),
+            (RW, 15, 1),
+            (RW, 14, 1),
+            (RW, 13, 1),
+            (RW, 12, 1),
+            (RW, 11, 1),
+            (RW, 10, 1),
+            (GX, 0, 4),
+            (GW, 15, 1),
+            (GW, 14, 1),
+            (GW, 13, 1),
+            (GW, 12, 1),
+            (GW, 11, 1),
+            (GW, 10, 1),
+            (BX, 0, 4),
+            (BW, 15, 1),
+            (BW, 14, 1),
+            (BW, 13, 1),
+            (BW, 12, 1),
+            (BW, 11, 1),
+            (BW, 10, 1),
+        ],
+    },
+];
+
+// BC6H: RGB half floats with up to 2 subsets, unsigned or signed. The endpoints are
+// integers scaled to the range of half floats, whose bits are then interpolated.
+pub(crate) fn bc6h_unsigned(block: &[u8]) -> [[u16; 4]; 16] {
+    bc6h(block, false)
+}
+
+pub(crate) fn bc6h_signed(block: &[u8]) -> [[u16; 4]; 16] {
+    bc6h(block, true)
+}
+
+fn bc6h(block: &[u8], signed: bool) -> [[u16; 4]; 16] {
+    let mut bits = Bits::new(block);
+    let mut code = bits.read(2);
+    if code > 1 {
+        code |= bits.read(3) << 2;
+    }
+    // Reserved modes decode to black
+    let Some(mode) = BC6H_MODES.iter().find(|mode| mode.code == code) else {
+        return [[0, 0, 0, HALF_ONE]; 16];
+    };
+
+    let mut values = [0i32; 12];
+    for &(value, lowest_bit, count) in mode.fields {
+        values[value as usize] |= (bits.read(count as u32) as i32) << lowest_bit;
+    }
+    let partition = if mode.subsets == 2 {
+        bits.read(5) as usize
+    } else {
+        0
+    };
+
+    let sign_extend = |value: i32, bits: u32| (value << (32 - bits)) >> (32 - bits);
+    let endpoint_count = mode.subsets as usize * 2;
+    let mut endpoints = [[0i32; 3]; 4];
+    for (e, endpoint) in endpoints[..endpoint_count].iter_mut().enumerate() {
+        for (c, value) in endpoint.iter_mut().enumerate() {
+            *value = values[e * 3 + c];
+            if e == 0 {
+                if signed {
+                    *value = sign_extend(*value, mode.endpoint_bits);
+                }
+                continue;
+            }
+            if mode.transformed || signed {
+                *value = sign_extend(*value, mode.delta_bits[c]);
+            }
+            if mode.transformed {
+                // Deltas wrap around at the precision of the endpoints
+                *value = (values[c] + *value) & ((1 << mode.endpoint_bits) - 1);
+                if signed {
+                    *value = sign_extend(*value, mode.endpoint_bits);
+                }
+            }
+        }
+    }
+    for endpoint in &mut endpoints {
+        *endpoint = endpoint.map(|value| bc6h_unquantize(value, mode.endpoint_bits, signed));
+    }
+
+    let index_bits = if mode.subsets == 2 { 3 } else { 4 };
+    let texels: [(usize, bool); 16] = std::array::from_fn(|i| subset(mode.subsets, partition, i));
+    std::array::from_fn(|i| {
+        let (subset, anchor) = texels[i];
+        let weight = weight(index_bits, bits.read(index_bits - anchor as u32));
+        let (e0, e1) = (endpoints[subset * 2], endpoints[subset * 2 + 1]);
+        let [r, g, b] = std::array::from_fn(|c| {
+            let value = ((64 - weight) * e0[c] + weight * e1[c] + 32) >> 6;
+            bc6h_finish(value, signed)
+        });
+        [r, g, b, HALF_ONE]
+    })
+}
+
+// Scales an endpoint of `bits` bits to 16 bits (15 bits and a sign when signed)
+fn bc6h_unquantize(value: i32, bits: u32, signed: bool) -> i32 {
+    if !signed {
+        if bits >= 15 || value == 0 {
+            value
+        } else if value == (1 << bits) - 1 {
+            0xffff
+        } else {
+            ((value << 16) + 0x8000) >> bits
+        }
+    } else if bits >= 16 || value == 0 {
+        value
+    } else {
+        let magnitude = value.abs();
+        let magnitude = if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7fff
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+        if value < 0 { -magnitude } else { magnitude }
+    }
+}
+
+// Scales the interpolated value to the largest finite half float, 0x7bff, giving its bits
+fn bc6h_finish(value: i32, signed: bool) -> u16 {
+    if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        0x8000 | ((-value * 31) >> 5) as u16
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
@@ -0,0 +1,275 @@
+// The camera decides what part of the scene ends up on screen.
+// The view matrix moves the world so the camera sits at the origin looking down -Z, then
+// the projection matrix maps what the camera sees to clip space, where wgpu expects x and
+// y in -1..1 and the depth in 0..1 (OpenGL uses -1..1, so cgmath's own projection
+// functions can't be used as is).
+//
+// The combined matrix is uploaded to a uniform buffer every frame and bound at @group(0)
+// of the built-in pipelines.
+
+use cgmath::{Matrix4, Point3, Rad, Vector3, VectorSpace};
+use wgpu::util::DeviceExt;
+
+use crate::State;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    // Far objects look smaller. fovy is the vertical field of view.
+    Perspective {
+        fovy: Rad<f32>,
+        znear: f32,
+        zfar: f32,
+    },
+    // Objects keep their size whatever the distance, `height` world units fit vertically
+    // on the screen (the width follows the aspect ratio)
+    Orthographic {
+        height: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    // Where the camera is, what it looks at, and which way is up
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    // Width / height of the render target, kept up to date by State::resize
+    pub aspect: f32,
+    pub projection: Projection,
+    // Maps the near plane to depth 1 and the far plane to 0 instead of the other way
+    // around. Floats are more precise close to 0, which evens out the depth precision
+    // over the distance. The depth test then has to keep the greater values.
+    pub reversed_z: bool,
+}
+
+impl Camera {
+    pub fn perspective(eye: Point3<f32>, target: Point3<f32>, aspect: f32) -> Self {
+        Self {
+            eye,
+            target,
+            up: Vector3::unit_y(),
+            aspect,
+            projection: Projection::Perspective {
+                fovy: cgmath::Deg(45.0).into(),
+                znear: 0.1,
+                zfar: 100.0,
+            },
+            reversed_z: false,
+        }
+    }
+
+    pub fn orthographic(eye: Point3<f32>, target: Point3<f32>, aspect: f32, height: f32) -> Self {
+        Self {
+            eye,
+            target,
+            up: Vector3::unit_y(),
+            aspect,
+            projection: Projection::Orthographic {
+                height,
+                znear: 0.1,
+                zfar: 100.0,
+            },
+            reversed_z: false,
+        }
+    }
+
+    // Moves the world so the camera is at the origin looking down -Z (right-handed)
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    // Maps view space to clip space with depth in 0..1 (or 1..0 with reversed_z)
+    #[rustfmt::skip]
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        match self.projection {
+            Projection::Perspective { fovy, znear, zfar } => {
+                let f = 1.0 / (fovy.0 / 2.0).tan();
+                // Reversing Z is the same as swapping the near and far planes
+                let (near, far) = if self.reversed_z {
+                    (zfar, znear)
+                } else {
+                    (znear, zfar)
+                };
+                // Columns. The view space z (negative in front of the camera) becomes w,
+                // and the depth is far * (z + near) / (z * (far - near)) after the divide.
+                Matrix4::new(
+                    f / self.aspect, 0.0, 0.0, 0.0,
+                    0.0, f, 0.0, 0.0,
+                    0.0, 0.0, far / (near - far), -1.0,
+                    0.0, 0.0, near * far / (near - far), 0.0,
+                )
+            }
+            Projection::Orthographic {
+                height,
+                znear,
+                zfar,
+            } => {
+                let (near, far) = if self.reversed_z {
+                    (zfar, znear)
+                } else {
+                    (znear, zfar)
+                };
+                let width = height * self.aspect;
+                Matrix4::new(
+                    2.0 / width, 0.0, 0.0, 0.0,
+                    0.0, 2.0 / height, 0.0, 0.0,
+                    0.0, 0.0, 1.0 / (near - far), 0.0,
+                    0.0, 0.0, near / (near - far), 1.0,
+                )
+            }
+        }
+    }
+
+    pub fn build_view_projection_matrix(&self) -> Matrix4<f32> {
+        self.projection_matrix() * self.view_matrix()
+    }
+
+    // The camera `t` of the way from `self` to `other` (0 gives self, 1 gives other).
+    // Everything but the position, the direction and the zoom comes from `other`.
+    pub fn lerp(&self, other: &Camera, t: f32) -> Camera {
+        let projection = match (self.projection, other.projection) {
+            (
+                Projection::Perspective { fovy: from, .. },
+                Projection::Perspective { fovy, znear, zfar },
+            ) => Projection::Perspective {
+                fovy: from + (fovy - from) * t,
+                znear,
+                zfar,
+            },
+            (
+                Projection::Orthographic { height: from, .. },
+                Projection::Orthographic {
+                    height,
+                    znear,
+                    zfar,
+                },
+            ) => Projection::Orthographic {
+                height: from + (height - from) * t,
+                znear,
+                zfar,
+            },
+            (_, projection) => projection,
+        };
+        Camera {
+            eye: self.eye + (other.eye - self.eye) * t,
+            target: self.target + (other.target - self.target) * t,
+            up: self.up.lerp(other.up, t),
+            projection,
+            ..*other
+        }
+    }
+}
+
+impl Default for Camera {
+    // Looks at the z = 0 plane from the front with the -1..1 square filling the height of
+    // the screen, so 2D geometry in clip space coordinates is drawn where it used to be
+    fn default() -> Self {
+        Self::orthographic((0.0, 0.0, 1.0).into(), (0.0, 0.0, 0.0).into(), 1.0, 2.0)
+    }
+}
+
+// What the shaders get, matching CameraUniform in the WGSL code.
+// cgmath types aren't Pod, so the matrix is stored as plain arrays.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniform {
+    pub view_proj: [[f32; 4]; 4],
+}
+
+impl From<&Camera> for CameraUniform {
+    fn from(camera: &Camera) -> Self {
+        Self {
+            view_proj: camera.build_view_projection_matrix().into(),
+        }
+    }
+}
+
+// The uniform buffer holding the camera and the bind group pointing at it
+pub(crate) struct CameraBuffer {
+    buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl CameraBuffer {
+    pub(crate) fn new(device: &wgpu::Device, camera: &Camera) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::bytes_of(&CameraUniform::from(camera)),
+            // COPY_DST lets us update it with queue.write_buffer every frame
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Camera Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                // Only the vertex shader transforms positions
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Camera Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        Self {
+            buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    pub(crate) fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::bytes_of(&CameraUniform::from(camera)),
+        );
+    }
+
+    pub(crate) fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub(crate) fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+impl State {
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    // Changes are uploaded with the next frame, interpolated from the camera before the
+    // last update (see timestep.rs).
+    // reversed_z has to be changed with set_reversed_z, which also changes the depth test.
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    // Replaces the camera, keeping the aspect ratio of the render target.
+    // The next frame isn't interpolated from the old camera.
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.set_reversed_z(camera.reversed_z);
+        let aspect = self.camera.aspect;
+        self.camera = Camera { aspect, ..camera };
+        self.previous_camera = self.camera;
+    }
+
+    // Layout of @group(0) in the built-in pipelines, for custom pipelines that want the camera
+    pub fn camera_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        self.camera_buffer.bind_group_layout()
+    }
+}
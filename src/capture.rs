@@ -0,0 +1,154 @@
+// Textures live in GPU memory and can't be read directly by the CPU.
+// To get the pixels back, the texture is copied into a buffer that can be mapped,
+// then the buffer is mapped and copied into a Vec.
+
+use crate::State;
+
+// Copies a 2D texture with 4 bytes per pixel (e.g. Rgba8UnormSrgb) to the CPU.
+// The returned Vec is tightly packed: width * height * 4 bytes, rows top to bottom.
+pub(crate) async fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> anyhow::Result<Vec<u8>> {
+    if texture.format().block_copy_size(None) != Some(4) {
+        anyhow::bail!(
+            "Reading back {:?} textures is not supported, only 4 bytes per pixel",
+            texture.format()
+        );
+    }
+    let width = texture.width();
+    let height = texture.height();
+    let unpadded_bytes_per_row = width * 4;
+    // copy_texture_to_buffer requires every row to start at a multiple of 256 bytes,
+    // so the rows in the buffer are padded and the padding is removed after mapping
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+        // MAP_READ lets the CPU read the buffer, COPY_DST lets us copy the texture into it
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(height),
+            },
+        },
+        texture.size(),
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    // map_async only starts the mapping, the callback is called once the GPU is done.
+    // The oneshot channel turns that callback into something we can await.
+    let buffer_slice = buffer.slice(..);
+    let (tx, rx) = futures_intrusive::channel::shared::oneshot_channel();
+    buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+        // Nobody is waiting anymore if the future was dropped
+        let _ = tx.send(result);
+    });
+    // On native the callback only runs when the device is polled.
+    // On the web the browser polls the device for us, so this does nothing.
+    device.poll(wgpu::PollType::Wait)?;
+    rx.receive()
+        .await
+        .ok_or_else(|| anyhow::anyhow!("Readback buffer mapping was cancelled"))??;
+
+    // Drop the row padding while copying out of the mapped buffer
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    {
+        let data = buffer_slice.get_mapped_range();
+        for row in data.chunks(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+    }
+    // The mapped range has to be dropped before the buffer can be unmapped
+    buffer.unmap();
+
+    Ok(pixels)
+}
+
+impl State {
+    // Renders the current frame into a texture with the same format as the surface and
+    // returns it as an RGBA image. Works for both windowed and headless states.
+    // Fails while there is nothing to draw into, e.g. when the window is minimized.
+    pub async fn capture_frame(&mut self) -> anyhow::Result<image::RgbaImage> {
+        // A texture can't have a zero size, wgpu would panic
+        if !self.is_surface_configured || self.config.width == 0 || self.config.height == 0 {
+            anyhow::bail!(
+                "Nothing to capture, the surface is not configured ({}x{})",
+                self.config.width,
+                self.config.height
+            );
+        }
+        // Checked first, there is no point drawing a frame that can't be converted
+        let format = self.config.format;
+        let bgra = is_bgra(format)?;
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Capture Texture"),
+            size: wgpu::Extent3d {
+                width: self.config.width,
+                height: self.config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // Same drawing code as render(), only the target changes
+        self.draw(&view);
+
+        let mut pixels = read_texture(&self.device, &self.queue, &texture).await?;
+        if bgra {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        image::RgbaImage::from_raw(texture.width(), texture.height(), pixels)
+            .ok_or_else(|| anyhow::anyhow!("Captured frame has an unexpected size"))
+    }
+
+    // Captures the current frame and writes it to `path` as a PNG
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn save_screenshot(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> anyhow::Result<()> {
+        let frame = self.capture_frame().await?;
+        frame.save_with_format(path.as_ref(), image::ImageFormat::Png)?;
+        log::info!("Saved screenshot to {}", path.as_ref().display());
+        Ok(())
+    }
+}
+
+// Surfaces are often BGRA (e.g. Bgra8UnormSrgb on Windows and Linux), but PNG wants RGBA,
+// so the channels of those are swapped. The bytes of sRGB formats are already sRGB
+// encoded, which is what PNG expects. Other formats (e.g. Rgb10a2Unorm on some HDR
+// displays) can't be captured.
+fn is_bgra(format: wgpu::TextureFormat) -> anyhow::Result<bool> {
+    match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => Ok(false),
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => Ok(true),
+        _ => anyhow::bail!("Capturing frames in {:?} is not supported", format),
+    }
+}
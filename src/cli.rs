@@ -0,0 +1,164 @@
+// Command line options of the learn_wgpu binary, see USAGE.
+// Options take their value as the next argument or after an =, like --size 800x600 or
+// --size=800x600. The web and Android builds have no command line and use the defaults.
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use crate::{
+    adapter::{self, AdapterOptions, AdapterSelector},
+    present,
+};
+
+pub const USAGE: &str = "\
+Usage: learn_wgpu [OPTIONS]
+
+Options:
+  --backend <LIST>         Backends to use, comma separated: vulkan, gl, dx12, metal, webgpu
+  --adapter <INDEX|NAME>   Adapter by index or part of its name (see --list-adapters)
+  --power <PREFERENCE>     Adapter preference when none is named: low, high or none
+  --present-mode <MODE>    fifo, fifo-relaxed, mailbox, immediate, auto-vsync or auto-no-vsync
+  --size <WIDTHxHEIGHT>    Size of the window in logical pixels (scaled by the display's
+                           scale factor), or of the frames in pixels when headless
+  --scene <FILE>           Image (PNG, KTX2, DDS) shown instead of the triangle
+  --headless               Render without a window
+  --frames <COUNT>         Frames rendered when headless, not with --replay [default: 60]
+  --output <DIR>           Where headless frames are saved, one PNG per frame
+  --record <FILE>          Record the input to a file
+  --replay <FILE>          Replay a recording, headless with --headless
+  --x11                    Use X11 on Linux
+  --wayland                Use Wayland on Linux
+  --list-adapters          Print the adapters of the backends and what they support
+  -h, --help               Print this help
+";
+
+// Frames rendered by --headless without --frames
+pub const DEFAULT_HEADLESS_FRAMES: u64 = 60;
+
+// The windowing system on Linux. winit picks Wayland when it is available, then X11.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayServer {
+    X11,
+    Wayland,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    pub adapter: AdapterOptions,
+    pub present_mode: Option<wgpu::PresentMode>,
+    // Logical pixels for the window, pixels of the frames when headless
+    pub size: Option<(u32, u32)>,
+    pub scene: Option<PathBuf>,
+    pub headless: bool,
+    pub frames: Option<u64>,
+    pub output: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub display_server: Option<DisplayServer>,
+    pub list_adapters: bool,
+    pub help: bool,
+}
+
+impl Options {
+    // Parses the arguments, without the program name
+    pub fn parse<I>(args: I) -> anyhow::Result<Self>
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString>,
+    {
+        let mut options = Self::default();
+        let mut args = args.into_iter().map(Into::into);
+        while let Some(arg) = args.next() {
+            let arg = arg
+                .into_string()
+                .map_err(|arg| anyhow::anyhow!("Invalid argument {:?}", arg))?;
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = || -> anyhow::Result<String> {
+                match inline_value.clone() {
+                    Some(value) => Ok(value),
+                    None => args
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("{} needs a value", name))?
+                        .into_string()
+                        .map_err(|value| anyhow::anyhow!("Invalid value {:?} for {}", value, name)),
+                }
+            };
+            match name {
+                "--backend" => {
+                    let mut backends = wgpu::Backends::empty();
+                    for name in value()?.split(',') {
+                        backends |= adapter::parse_backend(name)
+                            .ok_or_else(|| anyhow::anyhow!("Unknown backend {}", name))?;
+                    }
+                    options.adapter.backends = Some(backends);
+                }
+                "--adapter" => {
+                    let Ok(selector) = value()?.parse::<AdapterSelector>();
+                    options.adapter.adapter = Some(selector);
+                }
+                "--power" => {
+                    let value = value()?;
+                    options.adapter.power_preference = adapter::parse_power_preference(&value)
+                        .ok_or_else(|| anyhow::anyhow!("Unknown power preference {}", value))?;
+                }
+                "--present-mode" => {
+                    let value = value()?;
+                    options.present_mode = Some(
+                        present::parse_present_mode(&value)
+                            .ok_or_else(|| anyhow::anyhow!("Unknown present mode {}", value))?,
+                    );
+                }
+                "--size" => options.size = Some(parse_size(&value()?)?),
+                "--scene" => options.scene = Some(value()?.into()),
+                "--headless" => options.headless = true,
+                "--frames" => {
+                    let value = value()?;
+                    options.frames = Some(
+                        value
+                            .parse()
+                            .map_err(|_| anyhow::anyhow!("Invalid frame count {}", value))?,
+                    );
+                }
+                "--output" => options.output = Some(value()?.into()),
+                "--record" => options.record = Some(value()?.into()),
+                "--replay" => options.replay = Some(value()?.into()),
+                "--x11" => options.display_server = Some(DisplayServer::X11),
+                "--wayland" => options.display_server = Some(DisplayServer::Wayland),
+                "--list-adapters" => options.list_adapters = true,
+                "-h" | "--help" => options.help = true,
+                _ => anyhow::bail!("Unknown option {}, see --help", arg),
+            }
+        }
+
+        if !options.headless && (options.frames.is_some() || options.output.is_some()) {
+            anyhow::bail!("--frames and --output need --headless");
+        }
+        if options.headless && options.record.is_some() {
+            anyhow::bail!("There is no input to record with --headless");
+        }
+        if options.replay.is_some() && options.frames.is_some() {
+            anyhow::bail!("--replay renders the recorded frames, --frames can't be used with it");
+        }
+        Ok(options)
+    }
+
+    // Frames rendered with --headless, unless replaying
+    pub fn headless_frames(&self) -> u64 {
+        self.frames.unwrap_or(DEFAULT_HEADLESS_FRAMES)
+    }
+}
+
+// WIDTHxHEIGHT, e.g. 1280x720
+fn parse_size(s: &str) -> anyhow::Result<(u32, u32)> {
+    let invalid = || anyhow::anyhow!("Invalid size {}, expected WIDTHxHEIGHT", s);
+    let (width, height) = s.split_once(['x', 'X']).ok_or_else(invalid)?;
+    let width: u32 = width.trim().parse().map_err(|_| invalid())?;
+    let height: u32 = height.trim().parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok((width, height))
+}
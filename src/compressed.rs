@@ -0,0 +1,566 @@
+// Block-compressed textures loaded from KTX2 and DDS files.
+// Compressed formats stay compressed in GPU memory (4 to 8 times smaller than RGBA8), but
+// each GPU family only samples some of them: desktop GPUs use BC, mobile GPUs ETC2 and/or
+// ASTC. Assets are usually shipped once per family and the best one is picked from the
+// device's Features (see select). Formats the device can't sample are decoded to RGBA8
+// on the CPU: every BC, ETC2 and EAC format, and ASTC with LDR endpoints. The BC
+// snorm formats are decoded to RGBA8 snorm, BC6H and EAC to RGBA16 float, to keep their
+// range and precision.
+//
+// Basis Universal files (KTX2 with ETC1S or UASTC data) are transcoded when they are
+// loaded (see basis.rs): ETC1S to ETC2 and UASTC to ASTC 4x4, which is a repacking of their
+// blocks. Devices without that family get BC7, re-encoded on the CPU, or RGBA8.
+
+use std::sync::Arc;
+
+use crate::{
+    State, basis, block_decode, block_encode,
+    texture::{ColorSpace, SamplerOptions, Texture, TextureSource},
+};
+
+// Every compression feature wgpu knows about, State requests the ones the adapter has
+pub const COMPRESSION_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_COMPRESSION_BC
+    .union(wgpu::Features::TEXTURE_COMPRESSION_ETC2)
+    .union(wgpu::Features::TEXTURE_COMPRESSION_ASTC);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionFamily {
+    // BC1-BC7 (DXT), supported by desktop GPUs
+    Bc,
+    // ETC2 and EAC, supported by OpenGL ES 3 / Vulkan mobile GPUs
+    Etc2,
+    // ASTC, supported by most recent mobile GPUs
+    Astc,
+}
+
+impl CompressionFamily {
+    pub fn feature(self) -> wgpu::Features {
+        match self {
+            Self::Bc => wgpu::Features::TEXTURE_COMPRESSION_BC,
+            Self::Etc2 => wgpu::Features::TEXTURE_COMPRESSION_ETC2,
+            Self::Astc => wgpu::Features::TEXTURE_COMPRESSION_ASTC,
+        }
+    }
+
+    // The family of a texture format, None for uncompressed formats
+    pub fn of(format: wgpu::TextureFormat) -> Option<Self> {
+        [Self::Bc, Self::Etc2, Self::Astc]
+            .into_iter()
+            .find(|family| format.required_features().contains(family.feature()))
+    }
+}
+
+// The families the device can sample, best first. BC is what desktop GPUs are built for,
+// and ASTC has better quality than ETC2 for the same size.
+pub fn supported_families(features: wgpu::Features) -> Vec<CompressionFamily> {
+    [
+        CompressionFamily::Bc,
+        CompressionFamily::Astc,
+        CompressionFamily::Etc2,
+    ]
+    .into_iter()
+    .filter(|family| features.contains(family.feature()))
+    .collect()
+}
+
+// Picks the variant of an asset for the best family the device supports,
+// e.g. select(features, &[(Bc, "tree.bc7.ktx2"), (Astc, "tree.astc.ktx2")])
+pub fn select<T>(features: wgpu::Features, variants: &[(CompressionFamily, T)]) -> Option<&T> {
+    supported_families(features).into_iter().find_map(|family| {
+        variants
+            .iter()
+            .find(|(variant_family, _)| *variant_family == family)
+            .map(|(_, variant)| variant)
+    })
+}
+
+const KTX2_MAGIC: [u8; 12] = [
+    0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n',
+];
+const DDS_MAGIC: [u8; 4] = *b"DDS ";
+
+// Whether `bytes` look like a KTX2 or DDS file
+pub fn is_container(bytes: &[u8]) -> bool {
+    bytes.starts_with(&KTX2_MAGIC) || bytes.starts_with(&DDS_MAGIC)
+}
+
+// A 2D texture with its mip levels, still in the format stored in the file
+#[derive(Debug, Clone)]
+pub struct CompressedImage {
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    // Level 0 (full size) first. Each level is made of rows of blocks, top to bottom.
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl CompressedImage {
+    // Parses a KTX2 or DDS file. The color space is only used by old DDS files,
+    // the other formats say whether they are sRGB. Basis Universal files are transcoded to
+    // the family they map to best (ETC2 or ASTC).
+    pub fn from_bytes(bytes: &[u8], color_space: ColorSpace) -> anyhow::Result<Self> {
+        Self::from_bytes_for(bytes, color_space, COMPRESSION_FEATURES)
+    }
+
+    // Same as from_bytes, Basis Universal files being transcoded to a format a device with
+    // `features` can sample
+    pub fn from_bytes_for(
+        bytes: &[u8],
+        color_space: ColorSpace,
+        features: wgpu::Features,
+    ) -> anyhow::Result<Self> {
+        if bytes.starts_with(&KTX2_MAGIC) {
+            Self::from_ktx2_for(bytes, features)
+        } else if bytes.starts_with(&DDS_MAGIC) {
+            Self::from_dds(bytes, color_space)
+        } else {
+            anyhow::bail!("Not a KTX2 or DDS file")
+        }
+    }
+
+    pub fn from_ktx2(bytes: &[u8]) -> anyhow::Result<Self> {
+        Self::from_ktx2_for(bytes, COMPRESSION_FEATURES)
+    }
+
+    pub fn from_ktx2_for(bytes: &[u8], features: wgpu::Features) -> anyhow::Result<Self> {
+        use ktx2::SupercompressionScheme;
+
+        let reader =
+            ktx2::Reader::new(bytes).map_err(|e| anyhow::anyhow!("Invalid KTX2: {}", e))?;
+        let header = reader.header();
+        if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+            anyhow::bail!("Only 2D KTX2 textures are supported (no arrays, cube maps or 3D)");
+        }
+        let (width, height) = (header.pixel_width, header.pixel_height.max(1));
+        if let Some((color_model, srgb)) = basis_universal(&reader) {
+            let (format, levels) = if color_model == ktx2::ColorModel::ETC1S {
+                if header.supercompression_scheme != Some(SupercompressionScheme::BasisLZ) {
+                    anyhow::bail!("ETC1S KTX2 file without BasisLZ supercompression");
+                }
+                basis::etc1s_to_etc2(&reader)?
+            } else {
+                let levels = ktx2_levels(&reader)?;
+                let format = wgpu::TextureFormat::Astc {
+                    block: wgpu::AstcBlock::B4x4,
+                    channel: wgpu::AstcChannel::Unorm,
+                };
+                (format, basis::uastc_to_astc(&levels)?)
+            };
+            let format = if srgb {
+                format.add_srgb_suffix()
+            } else {
+                format
+            };
+            return Self::new(format, width, height, levels)?.transcode_for(features);
+        }
+        let format = header
+            .format
+            .ok_or_else(|| anyhow::anyhow!("KTX2 file without a format"))?;
+        let format = ktx2_format(format)?;
+
+        Self::new(format, width, height, ktx2_levels(&reader)?)
+    }
+
+    pub fn from_dds(bytes: &[u8], color_space: ColorSpace) -> anyhow::Result<Self> {
+        let dds = ddsfile::Dds::read(bytes)?;
+        // Files with the DX10 header have no D3D format
+        let format = match (dds.get_d3d_format(), dds.get_dxgi_format()) {
+            (Some(format), _) => d3d_format(format, color_space)?,
+            (None, Some(format)) => dxgi_format(format)?,
+            (None, None) => anyhow::bail!("DDS file without a known format"),
+        };
+        if dds.get_depth() > 1 || dds.get_num_array_layers() > 1 {
+            anyhow::bail!("Only 2D DDS textures are supported (no arrays, cube maps or 3D)");
+        }
+
+        // All the levels are stored one after the other
+        let (width, height) = (dds.get_width(), dds.get_height());
+        let mut data = dds.get_data(0)?;
+        let mut levels = Vec::new();
+        for level in 0..dds.get_num_mipmap_levels() {
+            let size = level_byte_size(format, width, height, level);
+            if data.len() < size {
+                anyhow::bail!("DDS file is missing data for level {}", level);
+            }
+            let (level_data, rest) = data.split_at(size);
+            levels.push(level_data.to_vec());
+            data = rest;
+        }
+
+        Self::new(format, width, height, levels)
+    }
+
+    fn new(
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        levels: Vec<Vec<u8>>,
+    ) -> anyhow::Result<Self> {
+        if width == 0 || levels.is_empty() {
+            anyhow::bail!("Empty {:?} texture", format);
+        }
+        // Creating a texture with more levels than down to 1x1 would fail
+        let max_levels = crate::mipmap::mip_level_count(width, height) as usize;
+        if levels.len() > max_levels {
+            anyhow::bail!(
+                "{} mip levels for a {}x{} texture, which has at most {}",
+                levels.len(),
+                width,
+                height,
+                max_levels
+            );
+        }
+        for (level, data) in levels.iter().enumerate() {
+            if data.len() < level_byte_size(format, width, height, level as u32) {
+                anyhow::bail!(
+                    "Not enough data for level {} of a {:?} texture",
+                    level,
+                    format
+                );
+            }
+        }
+        Ok(Self {
+            format,
+            width,
+            height,
+            levels,
+        })
+    }
+
+    // Whether a device with `features` can sample the format directly
+    pub fn is_supported(&self, features: wgpu::Features) -> bool {
+        features.contains(self.format.required_features())
+    }
+
+    // Transcoded Basis Universal images in a format the device can sample: their own
+    // family if it has it, else BC7 (as good as ETC2 or ASTC 4x4 at the same size), else
+    // RGBA8
+    fn transcode_for(self, features: wgpu::Features) -> anyhow::Result<Self> {
+        if self.is_supported(features) {
+            return Ok(self);
+        }
+        let decoded = self.decode()?;
+        if !features.contains(wgpu::Features::TEXTURE_COMPRESSION_BC) {
+            return Ok(decoded);
+        }
+        let levels = decoded
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(level, data)| {
+                let width = crate::mipmap::mip_level_size(self.width, level as u32);
+                let height = crate::mipmap::mip_level_size(self.height, level as u32);
+                block_encode::bc7_image(width, height, data)
+            })
+            .collect();
+        let format = if self.format.is_srgb() {
+            wgpu::TextureFormat::Bc7RgbaUnormSrgb
+        } else {
+            wgpu::TextureFormat::Bc7RgbaUnorm
+        };
+        Self::new(format, self.width, self.height, levels)
+    }
+
+    // Converts every level to RGBA8 (snorm for signed formats, RGBA16 float for BC6H and
+    // EAC), keeping the color space
+    pub fn decode(&self) -> anyhow::Result<Self> {
+        if self.format.block_dimensions() == (1, 1) {
+            // Already uncompressed
+            return Ok(self.clone());
+        }
+        let levels = self
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(level, data)| {
+                let width = crate::mipmap::mip_level_size(self.width, level as u32);
+                let height = crate::mipmap::mip_level_size(self.height, level as u32);
+                block_decode::decode(self.format, width, height, data)
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            format: block_decode::decoded_format(self.format),
+            width: self.width,
+            height: self.height,
+            levels,
+        })
+    }
+}
+
+impl Texture {
+    // Uploads a compressed image as is when the device supports its format, otherwise
+    // decodes it on the CPU first. The mip levels stored in the file are used.
+    pub fn from_compressed(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &CompressedImage,
+        label: &str,
+        sampler: SamplerOptions,
+    ) -> anyhow::Result<Self> {
+        let mut texture = Self::upload_compressed(device, queue, image, label, sampler)?;
+        texture.source = Some(TextureSource::Compressed {
+            image: Arc::new(image.clone()),
+            label: label.to_string(),
+            sampler,
+        });
+        Ok(texture)
+    }
+
+    pub(crate) fn upload_compressed(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &CompressedImage,
+        label: &str,
+        sampler: SamplerOptions,
+    ) -> anyhow::Result<Self> {
+        let (block_width, block_height) = image.format.block_dimensions();
+        // wgpu only creates compressed textures made of whole blocks
+        let whole_blocks =
+            image.width.is_multiple_of(block_width) && image.height.is_multiple_of(block_height);
+        if image.is_supported(device.features()) && whole_blocks {
+            return Ok(upload(device, queue, image, label, sampler));
+        }
+
+        log::info!(
+            "Decoding {} ({:?}, {}x{}) to RGBA8 on the CPU",
+            label,
+            image.format,
+            image.width,
+            image.height
+        );
+        let decoded = image.decode()?;
+        Ok(upload(device, queue, &decoded, label, sampler))
+    }
+}
+
+impl State {
+    // Parses a KTX2 or DDS file and uploads it with State's device and queue
+    pub fn create_compressed_texture(
+        &self,
+        bytes: &[u8],
+        label: &str,
+        color_space: ColorSpace,
+        sampler: SamplerOptions,
+    ) -> anyhow::Result<Texture> {
+        let image = CompressedImage::from_bytes_for(bytes, color_space, self.device.features())?;
+        Texture::from_compressed(&self.device, &self.queue, &image, label, sampler)
+    }
+}
+
+fn upload(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &CompressedImage,
+    label: &str,
+    sampler: SamplerOptions,
+) -> Texture {
+    let size = wgpu::Extent3d {
+        width: image.width,
+        height: image.height,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size,
+        mip_level_count: image.levels.len() as u32,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: image.format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    let (block_width, block_height) = image.format.block_dimensions();
+    let block_size = image.format.block_copy_size(None).unwrap_or(4);
+    for (level, data) in image.levels.iter().enumerate() {
+        let level_size = size.mip_level_size(level as u32, wgpu::TextureDimension::D2);
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: level as u32,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            data,
+            // Compressed data is copied a row of blocks at a time
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(level_size.width.div_ceil(block_width) * block_size),
+                rows_per_image: Some(level_size.height.div_ceil(block_height)),
+            },
+            // Small levels are still made of whole blocks (a 2x2 level uses a 4x4 block)
+            level_size.physical_size(image.format),
+        );
+    }
+
+    Texture::from_texture(device, texture, sampler)
+}
+
+// Size in bytes of mip level `level`, which is a whole number of blocks
+fn level_byte_size(format: wgpu::TextureFormat, width: u32, height: u32, level: u32) -> usize {
+    let (block_width, block_height) = format.block_dimensions();
+    let blocks_wide = crate::mipmap::mip_level_size(width, level).div_ceil(block_width);
+    let blocks_high = crate::mipmap::mip_level_size(height, level).div_ceil(block_height);
+    let block_size = format.block_copy_size(None).unwrap_or(0);
+    (blocks_wide * blocks_high * block_size) as usize
+}
+
+// The levels of a KTX2 file, Zstandard supercompressed ones decompressed
+fn ktx2_levels(reader: &ktx2::Reader<&[u8]>) -> anyhow::Result<Vec<Vec<u8>>> {
+    use ktx2::SupercompressionScheme;
+
+    reader
+        .levels()
+        .map(|level| match reader.header().supercompression_scheme {
+            None => Ok(level.data.to_vec()),
+            Some(SupercompressionScheme::Zstandard) => {
+                let mut data = Vec::with_capacity(level.uncompressed_byte_length as usize);
+                let mut decoder = ruzstd::decoding::StreamingDecoder::new(level.data)?;
+                std::io::Read::read_to_end(&mut decoder, &mut data)?;
+                Ok(data)
+            }
+            Some(scheme) => anyhow::bail!("Unsupported KTX2 supercompression {:?}", scheme),
+        })
+        .collect()
+}
+
+// Basis Universal data is described by the color model of the data format descriptor,
+// which also says whether the colors are sRGB
+fn basis_universal(reader: &ktx2::Reader<&[u8]>) -> Option<(ktx2::ColorModel, bool)> {
+    reader.dfd_blocks().find_map(|block| {
+        if block.header != ktx2::DfdHeader::BASIC {
+            return None;
+        }
+        let basic = ktx2::DfdBlockBasic::parse(block.data).ok()?;
+        let color_model = basic.header.color_model?;
+        let srgb = basic.header.transfer_function == Some(ktx2::TransferFunction::SRGB);
+        matches!(
+            color_model,
+            ktx2::ColorModel::ETC1S | ktx2::ColorModel::UASTC
+        )
+        .then_some((color_model, srgb))
+    })
+}
+
+// KTX2 uses Vulkan format numbers
+fn ktx2_format(format: ktx2::Format) -> anyhow::Result<wgpu::TextureFormat> {
+    use ktx2::Format as K;
+    use wgpu::TextureFormat as F;
+
+    Ok(match format {
+        K::R8G8B8A8_UNORM => F::Rgba8Unorm,
+        K::R8G8B8A8_SRGB => F::Rgba8UnormSrgb,
+        K::B8G8R8A8_UNORM => F::Bgra8Unorm,
+        K::B8G8R8A8_SRGB => F::Bgra8UnormSrgb,
+        K::BC1_RGB_UNORM_BLOCK | K::BC1_RGBA_UNORM_BLOCK => F::Bc1RgbaUnorm,
+        K::BC1_RGB_SRGB_BLOCK | K::BC1_RGBA_SRGB_BLOCK => F::Bc1RgbaUnormSrgb,
+        K::BC2_UNORM_BLOCK => F::Bc2RgbaUnorm,
+        K::BC2_SRGB_BLOCK => F::Bc2RgbaUnormSrgb,
+        K::BC3_UNORM_BLOCK => F::Bc3RgbaUnorm,
+        K::BC3_SRGB_BLOCK => F::Bc3RgbaUnormSrgb,
+        K::BC4_UNORM_BLOCK => F::Bc4RUnorm,
+        K::BC4_SNORM_BLOCK => F::Bc4RSnorm,
+        K::BC5_UNORM_BLOCK => F::Bc5RgUnorm,
+        K::BC5_SNORM_BLOCK => F::Bc5RgSnorm,
+        K::BC6H_UFLOAT_BLOCK => F::Bc6hRgbUfloat,
+        K::BC6H_SFLOAT_BLOCK => F::Bc6hRgbFloat,
+        K::BC7_UNORM_BLOCK => F::Bc7RgbaUnorm,
+        K::BC7_SRGB_BLOCK => F::Bc7RgbaUnormSrgb,
+        K::ETC2_R8G8B8_UNORM_BLOCK => F::Etc2Rgb8Unorm,
+        K::ETC2_R8G8B8_SRGB_BLOCK => F::Etc2Rgb8UnormSrgb,
+        K::ETC2_R8G8B8A1_UNORM_BLOCK => F::Etc2Rgb8A1Unorm,
+        K::ETC2_R8G8B8A1_SRGB_BLOCK => F::Etc2Rgb8A1UnormSrgb,
+        K::ETC2_R8G8B8A8_UNORM_BLOCK => F::Etc2Rgba8Unorm,
+        K::ETC2_R8G8B8A8_SRGB_BLOCK => F::Etc2Rgba8UnormSrgb,
+        K::EAC_R11_UNORM_BLOCK => F::EacR11Unorm,
+        K::EAC_R11_SNORM_BLOCK => F::EacR11Snorm,
+        K::EAC_R11G11_UNORM_BLOCK => F::EacRg11Unorm,
+        K::EAC_R11G11_SNORM_BLOCK => F::EacRg11Snorm,
+        _ => return astc_format(format.value()),
+    })
+}
+
+// The ASTC formats come in UNORM/SRGB pairs for each block size, starting at 4x4 = 157
+fn astc_format(vk_format: u32) -> anyhow::Result<wgpu::TextureFormat> {
+    use wgpu::AstcBlock as B;
+
+    const BLOCKS: [wgpu::AstcBlock; 14] = [
+        B::B4x4,
+        B::B5x4,
+        B::B5x5,
+        B::B6x5,
+        B::B6x6,
+        B::B8x5,
+        B::B8x6,
+        B::B8x8,
+        B::B10x5,
+        B::B10x6,
+        B::B10x8,
+        B::B10x10,
+        B::B12x10,
+        B::B12x12,
+    ];
+    let i = vk_format.wrapping_sub(157) as usize;
+    let block = BLOCKS
+        .get(i / 2)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported KTX2 format {}", vk_format))?;
+    let channel = if i.is_multiple_of(2) {
+        wgpu::AstcChannel::Unorm
+    } else {
+        wgpu::AstcChannel::UnormSrgb
+    };
+    Ok(wgpu::TextureFormat::Astc {
+        block: *block,
+        channel,
+    })
+}
+
+fn dxgi_format(format: ddsfile::DxgiFormat) -> anyhow::Result<wgpu::TextureFormat> {
+    use ddsfile::DxgiFormat as D;
+    use wgpu::TextureFormat as F;
+
+    Ok(match format {
+        D::R8G8B8A8_UNorm => F::Rgba8Unorm,
+        D::R8G8B8A8_UNorm_sRGB => F::Rgba8UnormSrgb,
+        D::B8G8R8A8_UNorm => F::Bgra8Unorm,
+        D::B8G8R8A8_UNorm_sRGB => F::Bgra8UnormSrgb,
+        D::BC1_UNorm => F::Bc1RgbaUnorm,
+        D::BC1_UNorm_sRGB => F::Bc1RgbaUnormSrgb,
+        D::BC2_UNorm => F::Bc2RgbaUnorm,
+        D::BC2_UNorm_sRGB => F::Bc2RgbaUnormSrgb,
+        D::BC3_UNorm => F::Bc3RgbaUnorm,
+        D::BC3_UNorm_sRGB => F::Bc3RgbaUnormSrgb,
+        D::BC4_UNorm => F::Bc4RUnorm,
+        D::BC4_SNorm => F::Bc4RSnorm,
+        D::BC5_UNorm => F::Bc5RgUnorm,
+        D::BC5_SNorm => F::Bc5RgSnorm,
+        D::BC6H_UF16 => F::Bc6hRgbUfloat,
+        D::BC6H_SF16 => F::Bc6hRgbFloat,
+        D::BC7_UNorm => F::Bc7RgbaUnorm,
+        D::BC7_UNorm_sRGB => F::Bc7RgbaUnormSrgb,
+        _ => anyhow::bail!("Unsupported DDS format {:?}", format),
+    })
+}
+
+// Old DDS files don't say whether the colors are sRGB
+fn d3d_format(
+    format: ddsfile::D3DFormat,
+    color_space: ColorSpace,
+) -> anyhow::Result<wgpu::TextureFormat> {
+    use ddsfile::D3DFormat as D;
+    use wgpu::TextureFormat as F;
+
+    let format = match format {
+        D::A8B8G8R8 => F::Rgba8Unorm,
+        D::A8R8G8B8 => F::Bgra8Unorm,
+        D::DXT1 => F::Bc1RgbaUnorm,
+        // DXT2 and DXT4 have premultiplied alpha, the blocks are the same
+        D::DXT2 | D::DXT3 => F::Bc2RgbaUnorm,
+        D::DXT4 | D::DXT5 => F::Bc3RgbaUnorm,
+        _ => anyhow::bail!("Unsupported DDS format {:?}", format),
+    };
+    Ok(match color_space {
+        ColorSpace::Srgb => format.add_srgb_suffix(),
+        ColorSpace::Linear => format,
+    })
+}
@@ -0,0 +1,458 @@
+// Camera controllers turn user input into camera movement.
+// Input events only record what the user is doing (which keys are held, how far the
+// mouse moved), and update_camera applies it once per frame. Movement is multiplied by
+// the frame time, so the camera moves at the same speed whatever the frame rate.
+// Mouse movement is already a distance, so it is applied as is.
+//
+// Mouse look uses DeviceEvent::MouseMotion instead of the cursor position: it keeps
+// reporting movement when the cursor is at the edge of the screen or grabbed.
+
+use std::f32::consts::FRAC_PI_2;
+use std::time::Duration;
+
+use cgmath::{InnerSpace, Vector3, Zero};
+use winit::{
+    event::{MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::KeyCode,
+};
+
+use crate::{
+    State,
+    camera::{Camera, Projection},
+    recording::AppEvent,
+};
+
+// Looking straight up or down makes the up vector parallel to the view direction,
+// so the pitch stops just before
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+pub trait CameraController {
+    // Each process_* method returns true when the input was used, so the app doesn't
+    // handle it a second time
+    fn process_keyboard(&mut self, _key: KeyCode, _pressed: bool) -> bool {
+        false
+    }
+
+    fn process_mouse_button(&mut self, _button: MouseButton, _pressed: bool) -> bool {
+        false
+    }
+
+    fn process_scroll(&mut self, _delta: MouseScrollDelta) -> bool {
+        false
+    }
+
+    // Raw mouse movement in pixels (DeviceEvent::MouseMotion)
+    fn process_mouse_motion(&mut self, _dx: f64, _dy: f64) {}
+
+    // Forgets held keys and buttons, e.g. when the window loses focus and won't
+    // receive the release events
+    fn reset(&mut self);
+
+    // Applies the input received since the last call. `dt` is the time since then.
+    fn update_camera(&mut self, camera: &mut Camera, dt: Duration);
+
+    // Whether the cursor should be hidden and kept in the window (mouse look)
+    fn cursor_grabbed(&self) -> bool {
+        false
+    }
+}
+
+// Dispatches an event to the matching process_* method
+pub fn process_event(controller: &mut dyn CameraController, event: &AppEvent) -> bool {
+    match *event {
+        // Holding a key sends repeated presses, they don't change anything here
+        AppEvent::Keyboard {
+            key,
+            pressed,
+            repeat: false,
+        } => controller.process_keyboard(key, pressed),
+        AppEvent::MouseButton { button, pressed } => {
+            controller.process_mouse_button(button, pressed)
+        }
+        AppEvent::ScrollLines { .. } | AppEvent::ScrollPixels { .. } => event
+            .scroll_delta()
+            .is_some_and(|delta| controller.process_scroll(delta)),
+        AppEvent::MouseMotion { dx, dy } => {
+            controller.process_mouse_motion(dx, dy);
+            false
+        }
+        AppEvent::Focused { focused: false } => {
+            controller.reset();
+            false
+        }
+        _ => false,
+    }
+}
+
+pub fn process_window_event(controller: &mut dyn CameraController, event: &WindowEvent) -> bool {
+    AppEvent::from_window_event(event).is_some_and(|event| process_event(controller, &event))
+}
+
+// Scroll distance in lines. Touchpads report pixels, roughly 20 per line.
+fn scroll_lines(delta: MouseScrollDelta) -> f32 {
+    match delta {
+        MouseScrollDelta::LineDelta(_, y) => y,
+        MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+    }
+}
+
+// Direction of the camera as angles: yaw around the Y axis (0 looks down -Z) and pitch
+// above the horizon
+fn yaw_pitch(direction: Vector3<f32>) -> (f32, f32) {
+    let direction = direction.normalize();
+    (direction.x.atan2(-direction.z), direction.y.asin())
+}
+
+fn direction(yaw: f32, pitch: f32) -> Vector3<f32> {
+    Vector3::new(
+        yaw.sin() * pitch.cos(),
+        pitch.sin(),
+        -yaw.cos() * pitch.cos(),
+    )
+}
+
+// Keys held to move the camera, each direction is 0 or 1
+#[derive(Debug, Default, Clone, Copy)]
+struct Movement {
+    forward: f32,
+    backward: f32,
+    left: f32,
+    right: f32,
+    up: f32,
+    down: f32,
+}
+
+impl Movement {
+    fn process_keyboard(&mut self, key: KeyCode, pressed: bool) -> bool {
+        let amount = if pressed { 1.0 } else { 0.0 };
+        match key {
+            KeyCode::KeyW | KeyCode::ArrowUp => self.forward = amount,
+            KeyCode::KeyS | KeyCode::ArrowDown => self.backward = amount,
+            KeyCode::KeyA | KeyCode::ArrowLeft => self.left = amount,
+            KeyCode::KeyD | KeyCode::ArrowRight => self.right = amount,
+            KeyCode::KeyE => self.up = amount,
+            KeyCode::KeyQ => self.down = amount,
+            _ => return false,
+        }
+        true
+    }
+}
+
+// Rotates around the target with the left mouse button held (or the arrow keys),
+// the wheel moves closer or further away
+#[derive(Debug, Clone)]
+pub struct OrbitController {
+    // Radians per second when rotating with the keyboard
+    pub speed: f32,
+    // Radians per pixel of mouse movement
+    pub sensitivity: f32,
+    // How much one line of scrolling changes the distance (0.1 = 10%)
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    dragging: bool,
+    movement: Movement,
+    rotate: (f32, f32),
+    zoom: f32,
+}
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        Self {
+            speed: 1.5,
+            sensitivity: 0.005,
+            zoom_speed: 0.1,
+            min_distance: 0.1,
+            dragging: false,
+            movement: Movement::default(),
+            rotate: (0.0, 0.0),
+            zoom: 0.0,
+        }
+    }
+}
+
+impl CameraController for OrbitController {
+    fn process_keyboard(&mut self, key: KeyCode, pressed: bool) -> bool {
+        match key {
+            KeyCode::ArrowUp | KeyCode::ArrowDown | KeyCode::ArrowLeft | KeyCode::ArrowRight => {
+                self.movement.process_keyboard(key, pressed)
+            }
+            _ => false,
+        }
+    }
+
+    fn process_mouse_button(&mut self, button: MouseButton, pressed: bool) -> bool {
+        if button != MouseButton::Left {
+            return false;
+        }
+        self.dragging = pressed;
+        true
+    }
+
+    fn process_scroll(&mut self, delta: MouseScrollDelta) -> bool {
+        self.zoom += scroll_lines(delta);
+        true
+    }
+
+    fn process_mouse_motion(&mut self, dx: f64, dy: f64) {
+        if self.dragging {
+            self.rotate.0 += dx as f32;
+            self.rotate.1 += dy as f32;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.dragging = false;
+        self.movement = Movement::default();
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+        let dt = dt.as_secs_f32();
+        let offset = camera.eye - camera.target;
+        let distance = offset.magnitude();
+        if distance == 0.0 {
+            return;
+        }
+
+        // The scene follows the mouse: dragging right turns it to the right, so the camera
+        // goes left, and dragging down brings its top towards the camera
+        let m = self.movement;
+        let (mut yaw, mut pitch) = yaw_pitch(-offset);
+        yaw += self.rotate.0 * self.sensitivity + (m.left - m.right) * self.speed * dt;
+        pitch -= self.rotate.1 * self.sensitivity + (m.forward - m.backward) * self.speed * dt;
+        pitch = pitch.clamp(-MAX_PITCH, MAX_PITCH);
+
+        // Scrolling up zooms in
+        let scale = (1.0 - self.zoom_speed).powf(self.zoom);
+        let distance = (distance * scale).max(self.min_distance);
+        camera.eye = camera.target - direction(yaw, pitch) * distance;
+        // Distance doesn't change the size of things with an orthographic projection
+        if let Projection::Orthographic { height, .. } = &mut camera.projection {
+            *height *= scale;
+        }
+
+        self.rotate = (0.0, 0.0);
+        self.zoom = 0.0;
+    }
+}
+
+// Mouse look (shared by the fly and FPS controllers)
+#[derive(Debug, Clone, Copy, Default)]
+struct Look {
+    delta: (f32, f32),
+}
+
+impl Look {
+    // Turns the camera by the accumulated mouse movement, returns the new direction
+    fn apply(&mut self, camera: &Camera, sensitivity: f32) -> (f32, f32) {
+        let (yaw, pitch) = yaw_pitch(camera.target - camera.eye);
+        let yaw = yaw + self.delta.0 * sensitivity;
+        let pitch = (pitch - self.delta.1 * sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        self.delta = (0.0, 0.0);
+        (yaw, pitch)
+    }
+}
+
+// Moves in the view direction with WASD, down and up with Q and E.
+// Looks around while the right mouse button is held.
+#[derive(Debug, Clone)]
+pub struct FlyController {
+    // Units per second
+    pub speed: f32,
+    // Radians per pixel of mouse movement
+    pub sensitivity: f32,
+    looking: bool,
+    movement: Movement,
+    look: Look,
+}
+
+impl Default for FlyController {
+    fn default() -> Self {
+        Self {
+            speed: 2.0,
+            sensitivity: 0.003,
+            looking: false,
+            movement: Movement::default(),
+            look: Look::default(),
+        }
+    }
+}
+
+impl CameraController for FlyController {
+    fn process_keyboard(&mut self, key: KeyCode, pressed: bool) -> bool {
+        self.movement.process_keyboard(key, pressed)
+    }
+
+    fn process_mouse_button(&mut self, button: MouseButton, pressed: bool) -> bool {
+        if button != MouseButton::Right {
+            return false;
+        }
+        self.looking = pressed;
+        true
+    }
+
+    fn process_mouse_motion(&mut self, dx: f64, dy: f64) {
+        if self.looking {
+            self.look.delta.0 += dx as f32;
+            self.look.delta.1 += dy as f32;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.looking = false;
+        self.movement = Movement::default();
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+        let (yaw, pitch) = self.look.apply(camera, self.sensitivity);
+        let forward = direction(yaw, pitch);
+        let right = forward.cross(Vector3::unit_y()).normalize();
+
+        let m = self.movement;
+        let velocity = forward * (m.forward - m.backward)
+            + right * (m.right - m.left)
+            + Vector3::unit_y() * (m.up - m.down);
+        // Normalized so moving diagonally isn't faster
+        let step = if velocity.is_zero() {
+            Vector3::zero()
+        } else {
+            velocity.normalize() * self.speed * dt.as_secs_f32()
+        };
+        camera.eye += step;
+        camera.target = camera.eye + forward;
+    }
+
+    fn cursor_grabbed(&self) -> bool {
+        self.looking
+    }
+}
+
+// First person: WASD moves on the ground whatever the pitch, and the mouse always looks
+// around. A click grabs the cursor, Escape releases it.
+#[derive(Debug, Clone)]
+pub struct FpsController {
+    // Units per second
+    pub speed: f32,
+    // Radians per pixel of mouse movement
+    pub sensitivity: f32,
+    grabbed: bool,
+    movement: Movement,
+    look: Look,
+}
+
+impl Default for FpsController {
+    fn default() -> Self {
+        Self {
+            speed: 2.0,
+            sensitivity: 0.003,
+            grabbed: false,
+            movement: Movement::default(),
+            look: Look::default(),
+        }
+    }
+}
+
+impl CameraController for FpsController {
+    fn process_keyboard(&mut self, key: KeyCode, pressed: bool) -> bool {
+        match key {
+            KeyCode::Escape if self.grabbed => {
+                self.grabbed = false;
+                true
+            }
+            // No flying
+            KeyCode::KeyQ | KeyCode::KeyE => false,
+            _ => self.movement.process_keyboard(key, pressed),
+        }
+    }
+
+    fn process_mouse_button(&mut self, button: MouseButton, pressed: bool) -> bool {
+        if button == MouseButton::Left && pressed && !self.grabbed {
+            self.grabbed = true;
+            return true;
+        }
+        false
+    }
+
+    fn process_mouse_motion(&mut self, dx: f64, dy: f64) {
+        if self.grabbed {
+            self.look.delta.0 += dx as f32;
+            self.look.delta.1 += dy as f32;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.grabbed = false;
+        self.movement = Movement::default();
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+        let (yaw, pitch) = self.look.apply(camera, self.sensitivity);
+        // Walking only uses the yaw, looking down doesn't slow you down
+        let forward = direction(yaw, 0.0);
+        let right = forward.cross(Vector3::unit_y());
+
+        let m = self.movement;
+        let velocity = forward * (m.forward - m.backward) + right * (m.right - m.left);
+        if !velocity.is_zero() {
+            camera.eye += velocity.normalize() * self.speed * dt.as_secs_f32();
+        }
+        camera.target = camera.eye + direction(yaw, pitch);
+    }
+
+    fn cursor_grabbed(&self) -> bool {
+        self.grabbed
+    }
+}
+
+impl State {
+    // Replaces the controller moving the camera, None leaves the camera alone
+    pub fn set_camera_controller(&mut self, controller: Option<Box<dyn CameraController>>) {
+        self.camera_controller = controller;
+        self.sync_cursor_grab();
+    }
+
+    // Passes input to the controller, returns true if it used it
+    pub fn process_event(&mut self, event: &AppEvent) -> bool {
+        let Some(controller) = &mut self.camera_controller else {
+            return false;
+        };
+        let used = process_event(controller.as_mut(), event);
+        self.sync_cursor_grab();
+        used
+    }
+
+    // Moves the camera according to the input received during the last `dt`
+    pub fn update_camera(&mut self, dt: Duration) {
+        if let Some(controller) = &mut self.camera_controller {
+            controller.update_camera(&mut self.camera, dt);
+        }
+    }
+
+    // Hides and locks the cursor while the controller does mouse look
+    fn sync_cursor_grab(&mut self) {
+        let grabbed = self
+            .camera_controller
+            .as_ref()
+            .is_some_and(|controller| controller.cursor_grabbed());
+        if grabbed == self.cursor_grabbed {
+            return;
+        }
+        self.cursor_grabbed = grabbed;
+        let Some(window) = &self.window else {
+            return;
+        };
+
+        use winit::window::CursorGrabMode;
+        let result = if grabbed {
+            // Locked isn't supported on X11 and Windows, Confined isn't on macOS
+            window
+                .set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined))
+        } else {
+            window.set_cursor_grab(CursorGrabMode::None)
+        };
+        if let Err(e) = result {
+            log::warn!("Unable to grab the cursor: {}", e);
+        }
+        window.set_cursor_visible(!grabbed);
+    }
+}
@@ -0,0 +1,126 @@
+// The depth buffer stores, for every pixel, the depth of the closest fragment drawn so far.
+// Pipelines with a depth test compare each new fragment against it and drop the ones that
+// are hidden, so meshes can be drawn in any order.
+//
+// The depth texture is an attachment (see attachment.rs): it has to be exactly as big as
+// the surface, so State::resize recreates it along with the others.
+
+use crate::{State, attachment::AttachmentDescriptor, msaa};
+
+// Candidates in order of preference: 32 bits float is the most precise (and works best with
+// reversed-Z), Depth24Plus lets the driver pick, and the stencil variant is the last resort
+pub const DEPTH_FORMATS: [wgpu::TextureFormat; 3] = [
+    wgpu::TextureFormat::Depth32Float,
+    wgpu::TextureFormat::Depth24Plus,
+    wgpu::TextureFormat::Depth24PlusStencil8,
+];
+
+// The first of `candidates` the adapter can render to
+pub fn select_format(
+    adapter: &wgpu::Adapter,
+    candidates: &[wgpu::TextureFormat],
+) -> Option<wgpu::TextureFormat> {
+    candidates.iter().copied().find(|&format| {
+        format.is_depth_stencil_format()
+            && adapter
+                .get_texture_format_features(format)
+                .allowed_usages
+                .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+    })
+}
+
+// Keeps fragments closer to the camera than what is already drawn. With reversed-Z closer
+// means a greater depth.
+pub fn compare_function(reversed_z: bool) -> wgpu::CompareFunction {
+    if reversed_z {
+        wgpu::CompareFunction::Greater
+    } else {
+        wgpu::CompareFunction::Less
+    }
+}
+
+// What the depth buffer is cleared to: the far plane
+pub fn clear_value(reversed_z: bool) -> f32 {
+    if reversed_z { 0.0 } else { 1.0 }
+}
+
+impl State {
+    pub fn depth_format(&self) -> Option<wgpu::TextureFormat> {
+        self.depth_attachment
+            .map(|id| self.attachment(id).descriptor().format)
+    }
+
+    // Switches the depth buffer to another format, e.g. one with a stencil.
+    // The built-in pipelines are rebuilt to match, custom ones have to be recreated.
+    pub fn set_depth_format(&mut self, format: wgpu::TextureFormat) -> anyhow::Result<()> {
+        if !format.is_depth_stencil_format() {
+            anyhow::bail!("{:?} is not a depth format", format);
+        }
+        let missing = format.required_features() - self.device.features();
+        if !missing.is_empty() {
+            anyhow::bail!("{:?} requires the device features {:?}", format, missing);
+        }
+        if select_format(&self.adapter, &[format]).is_none() {
+            anyhow::bail!("The adapter can't render to {:?}", format);
+        }
+        // The formats may not support the current number of samples
+        if !msaa::supported_sample_counts(
+            &self.adapter,
+            &self.device,
+            &[self.config.format, format],
+        )
+        .contains(&self.sample_count)
+        {
+            anyhow::bail!("{:?} doesn't support {}x MSAA", format, self.sample_count);
+        }
+
+        let desc = self.depth_descriptor(format);
+        match self.depth_attachment {
+            Some(id) => self.replace_attachment(id, desc),
+            None => self.depth_attachment = Some(self.add_attachment(desc)),
+        }
+        self.rebuild_pipelines();
+        Ok(())
+    }
+
+    // The depth texture has as many samples as the color target
+    pub(crate) fn depth_descriptor(&self, format: wgpu::TextureFormat) -> AttachmentDescriptor {
+        AttachmentDescriptor::new("Depth Texture", format).with_sample_count(self.sample_count)
+    }
+
+    // Switches between the usual and the reversed depth range. The camera projection, the
+    // depth test of the built-in pipelines and the depth clear value change together.
+    pub fn set_reversed_z(&mut self, reversed_z: bool) {
+        if self.camera.reversed_z == reversed_z {
+            return;
+        }
+        self.camera.reversed_z = reversed_z;
+        self.render_pipeline_builder = self.render_pipeline_builder.clone().reversed_z(reversed_z);
+        self.textured_pipeline_builder = self
+            .textured_pipeline_builder
+            .clone()
+            .reversed_z(reversed_z);
+        self.rebuild_pipelines();
+    }
+
+    // The depth texture and the depth operations of the render pass, if there is one
+    pub(crate) fn depth_stencil_attachment(
+        &self,
+    ) -> Option<wgpu::RenderPassDepthStencilAttachment<'_>> {
+        let id = self.depth_attachment?;
+        let attachment = self.attachment(id);
+        let format = attachment.descriptor().format;
+        Some(wgpu::RenderPassDepthStencilAttachment {
+            view: attachment.view(),
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(clear_value(self.camera.reversed_z)),
+                // Nothing reads the depth buffer after the pass
+                store: wgpu::StoreOp::Discard,
+            }),
+            stencil_ops: format.has_stencil_aspect().then_some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(0),
+                store: wgpu::StoreOp::Discard,
+            }),
+        })
+    }
+}
@@ -0,0 +1,36 @@
+// Errors that stop the app before it can draw anything.
+// They are returned by State::new and App, and end up in the anyhow::Result of run(), so
+// main can print them. The original error is kept as the source, which anyhow prints
+// after the message with `{:#}` or `{:?}`.
+
+#[derive(Debug, thiserror::Error)]
+pub enum InitError {
+    // No window on native, no <canvas> to draw into on the web
+    #[error("Unable to create the window")]
+    Window(#[from] winit::error::OsError),
+
+    #[error("No <canvas id=\"{0}\"> element found in the page")]
+    CanvasMissing(String),
+
+    // The backend can't present to the window at all (e.g. WebGL unavailable in the browser)
+    #[error("Unable to create a surface for the window")]
+    CreateSurface(#[from] wgpu::CreateSurfaceError),
+
+    #[error("No graphics adapter supports the window's surface")]
+    NoAdapter(#[from] wgpu::RequestAdapterError),
+
+    // The adapter asked for by index or name isn't in the list (see adapter.rs)
+    #[error("No graphics adapter matches {0}")]
+    AdapterNotFound(String),
+
+    // The adapter was found but reports no format or alpha mode for the surface
+    #[error("The surface is not supported by the adapter {0}")]
+    UnsupportedSurface(String),
+
+    // Windows opened next to the main one share its device, see windows.rs
+    #[error("A headless State has no instance to create the surface of another window")]
+    SharingHeadless,
+
+    #[error("Unable to request a device from the adapter")]
+    RequestDevice(#[from] wgpu::RequestDeviceError),
+}
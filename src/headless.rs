@@ -0,0 +1,147 @@
+// Headless rendering: State without a winit Window.
+// Instead of a surface, frames are drawn into an offscreen texture that can be read back,
+// so frames can be rendered and checked in CI or on a server without a display or GPU.
+
+use crate::{State, adapter::AdapterOptions, capture};
+
+// Every headless frame is rendered in this format, so the pixels read back are always RGBA8
+pub const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+// The texture that replaces the surface texture when there is no window
+pub(crate) struct OffscreenTarget {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl OffscreenTarget {
+    fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Texture"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            // COPY_SRC is needed to copy the rendered frame into a buffer we can read
+            usage: config.usage,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { texture, view }
+    }
+
+    pub(crate) fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        *self = Self::new(device, config);
+    }
+
+    pub(crate) fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub(crate) fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+}
+
+// Finds an adapter without needing a surface.
+// Real GPUs are tried first, then software renderers (llvmpipe, WARP...), and finally
+// wgpu's noop backend if the `noop` feature is enabled. The noop backend doesn't draw
+// anything, but it lets the rest of the code run on machines without any renderer.
+// An adapter asked for by index or name is used as is.
+async fn request_headless_adapter(options: &AdapterOptions) -> anyhow::Result<wgpu::Adapter> {
+    let instance = options.create_instance();
+    if options.adapter.is_some() {
+        return Ok(options.request_adapter(&instance, None, false).await?);
+    }
+
+    for force_fallback_adapter in [false, true] {
+        let adapter = options
+            .request_adapter(&instance, None, force_fallback_adapter)
+            .await;
+        if let Ok(adapter) = adapter {
+            return Ok(adapter);
+        }
+    }
+
+    request_noop_adapter().await
+}
+
+#[cfg(feature = "noop")]
+async fn request_noop_adapter() -> anyhow::Result<wgpu::Adapter> {
+    log::warn!("No GPU or software adapter found, falling back to the noop backend");
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::NOOP,
+        backend_options: wgpu::BackendOptions {
+            noop: wgpu::NoopBackendOptions { enable: true },
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    Ok(instance
+        .request_adapter(&wgpu::RequestAdapterOptions::default())
+        .await?)
+}
+
+#[cfg(not(feature = "noop"))]
+async fn request_noop_adapter() -> anyhow::Result<wgpu::Adapter> {
+    Err(anyhow::anyhow!(
+        "No adapter available for headless rendering (the `noop` feature allows running without one)"
+    ))
+}
+
+impl State {
+    // Creates a State that renders into a width x height offscreen texture
+    pub async fn new_headless(width: u32, height: u32) -> anyhow::Result<Self> {
+        Self::new_headless_with_options(width, height, &AdapterOptions::default()).await
+    }
+
+    // Like new_headless, with the backends and the adapter picked by `options`
+    pub async fn new_headless_with_options(
+        width: u32,
+        height: u32,
+        options: &AdapterOptions,
+    ) -> anyhow::Result<Self> {
+        let adapter = request_headless_adapter(options).await?;
+
+        let (device, queue) = Self::request_device(&adapter).await?;
+
+        // There is no surface to configure, but the configuration still describes the
+        // format and size of what we render to, just like with a window
+        let max_dimension = device.limits().max_texture_dimension_2d;
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: OFFSCREEN_FORMAT,
+            width: width.clamp(1, max_dimension),
+            height: height.clamp(1, max_dimension),
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        let offscreen = OffscreenTarget::new(&device, &config);
+
+        let mut state =
+            Self::from_parts(adapter, device, queue, config, None, Some(offscreen), None);
+        state.device_lost = crate::recovery::watch_device_loss(&state.device);
+        Ok(state)
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.window.is_none()
+    }
+
+    // Renders a frame and returns its pixels as tightly packed RGBA8 rows, top to bottom.
+    // Only available for states created with new_headless.
+    pub async fn render_to_pixels(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.render()?;
+        let offscreen = self
+            .offscreen
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("render_to_pixels requires a headless State"))?;
+        capture::read_texture(&self.device, &self.queue, offscreen.texture()).await
+    }
+}
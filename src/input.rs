@@ -0,0 +1,410 @@
+// Action-based input.
+// Instead of checking for specific keys, the app asks about named actions ("exit",
+// "screenshot"...) and axes ("zoom"...). An InputMap says which keys, mouse buttons,
+// scroll directions or touches trigger each of them, and can be loaded from a TOML file
+// so users can rebind them without recompiling:
+//
+//     [actions]
+//     exit = [{ key = "Escape" }, { mouse = "Middle" }]
+//     screenshot = [{ key = "F12" }]
+//     toggle_fullscreen = [{ key = "F11" }, { key_with = { key = "Enter", modifier = "alt" } }]
+//
+//     [axes]
+//     zoom = [{ buttons = { negative = { key = "Minus" }, positive = { key = "Equal" } } }, "scroll"]
+//
+// Input keeps track of what is held down, and of what was pressed or released since the
+// last call to end_frame, which the app calls once per frame.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use winit::{
+    event::{MouseButton, MouseScrollDelta, TouchPhase, WindowEvent},
+    keyboard::KeyCode,
+};
+
+use crate::recording::AppEvent;
+
+// Names of the actions used by App
+pub mod action {
+    pub const EXIT: &str = "exit";
+    pub const SCREENSHOT: &str = "screenshot";
+    pub const ORBIT_CAMERA: &str = "orbit_camera";
+    pub const FLY_CAMERA: &str = "fly_camera";
+    pub const FPS_CAMERA: &str = "fps_camera";
+    pub const CYCLE_MSAA: &str = "cycle_msaa";
+    pub const TOGGLE_VSYNC: &str = "toggle_vsync";
+    pub const OPEN_WINDOW: &str = "open_window";
+    pub const TOGGLE_FULLSCREEN: &str = "toggle_fullscreen";
+}
+
+// Where the bindings are read from on native, next to the working directory
+pub const BINDINGS_FILE: &str = "bindings.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrollDirection {
+    Up,
+    Down,
+}
+
+// A modifier key, the left or the right one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Modifier {
+    Shift,
+    Control,
+    Alt,
+    Super,
+}
+
+impl Modifier {
+    fn keys(self) -> [KeyCode; 2] {
+        match self {
+            Self::Shift => [KeyCode::ShiftLeft, KeyCode::ShiftRight],
+            Self::Control => [KeyCode::ControlLeft, KeyCode::ControlRight],
+            Self::Alt => [KeyCode::AltLeft, KeyCode::AltRight],
+            Self::Super => [KeyCode::SuperLeft, KeyCode::SuperRight],
+        }
+    }
+}
+
+// Something the user can press
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Binding {
+    // A key, by position on the keyboard (KeyW is Z on an AZERTY keyboard)
+    Key(KeyCode),
+    // A key while a modifier is held down, e.g. Alt+Enter. The modifier is tracked from
+    // its own key events, so it works the same in recordings.
+    KeyWith { key: KeyCode, modifier: Modifier },
+    Mouse(MouseButton),
+    // The wheel is "pressed" for the frame it was scrolled in
+    Scroll(ScrollDirection),
+    // At least one finger on the screen
+    Touch,
+}
+
+// Something that gives a value, added up over all the bindings of an axis
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AxisBinding {
+    // -1 while `negative` is held, 1 while `positive` is held
+    Buttons {
+        negative: Binding,
+        positive: Binding,
+    },
+    // Lines scrolled this frame, positive up
+    Scroll,
+    // Raw mouse movement this frame, in pixels
+    MouseX,
+    MouseY,
+    // Movement of the first finger on the screen this frame, in pixels
+    TouchX,
+    TouchY,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputMap {
+    pub actions: BTreeMap<String, Vec<Binding>>,
+    pub axes: BTreeMap<String, Vec<AxisBinding>>,
+}
+
+impl Default for InputMap {
+    // The bindings of App
+    fn default() -> Self {
+        let key = |code| vec![Binding::Key(code)];
+        let alt = |key| Binding::KeyWith {
+            key,
+            modifier: Modifier::Alt,
+        };
+        let actions = [
+            (action::EXIT, key(KeyCode::Escape)),
+            (action::SCREENSHOT, key(KeyCode::F12)),
+            (action::ORBIT_CAMERA, key(KeyCode::Digit1)),
+            (action::FLY_CAMERA, key(KeyCode::Digit2)),
+            (action::FPS_CAMERA, key(KeyCode::Digit3)),
+            (action::CYCLE_MSAA, key(KeyCode::KeyM)),
+            (action::TOGGLE_VSYNC, key(KeyCode::KeyV)),
+            (action::OPEN_WINDOW, key(KeyCode::KeyN)),
+            (
+                action::TOGGLE_FULLSCREEN,
+                vec![
+                    Binding::Key(KeyCode::F11),
+                    alt(KeyCode::Enter),
+                    alt(KeyCode::NumpadEnter),
+                ],
+            ),
+        ]
+        .into_iter()
+        .map(|(name, bindings)| (name.to_string(), bindings))
+        .collect();
+        Self {
+            actions,
+            axes: BTreeMap::new(),
+        }
+    }
+}
+
+impl InputMap {
+    // An empty map, with no actions or axes
+    pub fn empty() -> Self {
+        Self {
+            actions: BTreeMap::new(),
+            axes: BTreeMap::new(),
+        }
+    }
+
+    pub fn from_toml(source: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(source)?)
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    // Reads the bindings from `path`, or returns the defaults when the file doesn't exist.
+    // Actions missing from the file keep their default bindings.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_or_default(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let mut map = Self::default();
+        match std::fs::read_to_string(path) {
+            Ok(source) => {
+                let file = Self::from_toml(&source).map_err(|e| {
+                    anyhow::anyhow!("Invalid bindings in {}: {}", path.display(), e)
+                })?;
+                map.actions.extend(file.actions);
+                map.axes.extend(file.axes);
+                log::info!("Loaded input bindings from {}", path.display());
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => anyhow::bail!("Unable to read {}: {}", path.display(), e),
+        }
+        Ok(map)
+    }
+
+    // Adds a binding to an action, creating the action if needed
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        self.actions
+            .entry(action.to_string())
+            .or_default()
+            .push(binding);
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) {
+        self.axes.entry(axis.to_string()).or_default().push(binding);
+    }
+}
+
+// The state of the input devices, queried through the actions and axes of an InputMap
+#[derive(Debug, Clone)]
+pub struct Input {
+    map: InputMap,
+    // Held down right now
+    down: HashSet<Binding>,
+    // Changes since the last end_frame
+    pressed: HashSet<Binding>,
+    released: HashSet<Binding>,
+    scroll: f32,
+    mouse_delta: (f32, f32),
+    touch_delta: (f32, f32),
+    // Position of every finger on the screen, by touch id
+    touches: HashMap<u64, (f64, f64)>,
+    // The finger moving the TouchX/TouchY axes
+    first_touch: Option<u64>,
+}
+
+impl Input {
+    pub fn new(map: InputMap) -> Self {
+        Self {
+            map,
+            down: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            scroll: 0.0,
+            mouse_delta: (0.0, 0.0),
+            touch_delta: (0.0, 0.0),
+            touches: HashMap::new(),
+            first_touch: None,
+        }
+    }
+
+    pub fn map(&self) -> &InputMap {
+        &self.map
+    }
+
+    // Changes the bindings, what is held down stays held down
+    pub fn set_map(&mut self, map: InputMap) {
+        self.map = map;
+    }
+
+    pub fn process_event(&mut self, event: &AppEvent) {
+        match *event {
+            AppEvent::Keyboard { key, pressed, .. } => {
+                self.process_binding(Binding::Key(key), pressed)
+            }
+            AppEvent::MouseButton { button, pressed } => {
+                self.process_binding(Binding::Mouse(button), pressed)
+            }
+            AppEvent::ScrollLines { .. } | AppEvent::ScrollPixels { .. } => {
+                if let Some(delta) = event.scroll_delta() {
+                    self.process_scroll(delta);
+                }
+            }
+            AppEvent::MouseMotion { dx, dy } => self.process_mouse_motion(dx, dy),
+            AppEvent::Touch { id, phase, x, y } => {
+                match phase {
+                    TouchPhase::Started => {
+                        self.touches.insert(id, (x, y));
+                        self.first_touch.get_or_insert(id);
+                    }
+                    TouchPhase::Moved => {
+                        if let Some(last) = self.touches.insert(id, (x, y))
+                            && self.first_touch == Some(id)
+                        {
+                            self.touch_delta.0 += (x - last.0) as f32;
+                            self.touch_delta.1 += (y - last.1) as f32;
+                        }
+                    }
+                    TouchPhase::Ended | TouchPhase::Cancelled => {
+                        self.touches.remove(&id);
+                        if self.first_touch == Some(id) {
+                            self.first_touch = None;
+                        }
+                    }
+                }
+                self.process_binding(Binding::Touch, !self.touches.is_empty());
+            }
+            // Release events are lost while another window has the focus
+            AppEvent::Focused { focused: false } => {
+                for binding in std::mem::take(&mut self.down) {
+                    self.released.insert(binding);
+                }
+                self.touches.clear();
+                self.first_touch = None;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn process_window_event(&mut self, event: &WindowEvent) {
+        if let Some(event) = AppEvent::from_window_event(event) {
+            self.process_event(&event);
+        }
+    }
+
+    // A binding going down or up. process_event ends up here for keys, mouse
+    // buttons and touches; it's also how input can be simulated.
+    pub fn process_binding(&mut self, binding: Binding, pressed: bool) {
+        if pressed {
+            // Key repeats don't count as new presses
+            if self.down.insert(binding) {
+                self.pressed.insert(binding);
+            }
+        } else if self.down.remove(&binding) {
+            self.released.insert(binding);
+        }
+    }
+
+    pub fn process_scroll(&mut self, delta: MouseScrollDelta) {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, y) => y,
+            // Touchpads report pixels, roughly 20 per line
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+        };
+        self.scroll += lines;
+        // The wheel is pressed and released in the same frame
+        if lines != 0.0 {
+            let direction = if lines > 0.0 {
+                ScrollDirection::Up
+            } else {
+                ScrollDirection::Down
+            };
+            self.pressed.insert(Binding::Scroll(direction));
+            self.released.insert(Binding::Scroll(direction));
+        }
+    }
+
+    // Raw mouse movement from DeviceEvent::MouseMotion
+    pub fn process_mouse_motion(&mut self, dx: f64, dy: f64) {
+        self.mouse_delta.0 += dx as f32;
+        self.mouse_delta.1 += dy as f32;
+    }
+
+    // Starts a new frame: nothing is just pressed or released anymore, and the
+    // scroll and movement axes go back to 0
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.scroll = 0.0;
+        self.mouse_delta = (0.0, 0.0);
+        self.touch_delta = (0.0, 0.0);
+    }
+
+    fn bindings(&self, action: &str) -> &[Binding] {
+        self.map.actions.get(action).map_or(&[], Vec::as_slice)
+    }
+
+    // Whether `binding` is in one of the sets above. A key with a modifier is when the
+    // key is and the modifier is held down.
+    fn contains(&self, set: &HashSet<Binding>, binding: &Binding) -> bool {
+        match *binding {
+            Binding::KeyWith { key, modifier } => {
+                set.contains(&Binding::Key(key))
+                    && modifier
+                        .keys()
+                        .iter()
+                        .any(|&key| self.down.contains(&Binding::Key(key)))
+            }
+            _ => set.contains(binding),
+        }
+    }
+
+    // Whether a binding of the action is held down
+    pub fn pressed(&self, action: &str) -> bool {
+        self.bindings(action).iter().any(|binding| {
+            self.contains(&self.down, binding) || self.contains(&self.pressed, binding)
+        })
+    }
+
+    // Whether a binding of the action was pressed this frame
+    pub fn just_pressed(&self, action: &str) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| self.contains(&self.pressed, binding))
+    }
+
+    // Whether a binding of the action was released this frame
+    pub fn just_released(&self, action: &str) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| self.contains(&self.released, binding))
+    }
+
+    // Sum of all the bindings of the axis, 0 for unknown axes
+    pub fn axis(&self, axis: &str) -> f32 {
+        let Some(bindings) = self.map.axes.get(axis) else {
+            return 0.0;
+        };
+        let held = |binding| self.contains(&self.down, binding) as u8 as f32;
+        bindings
+            .iter()
+            .map(|binding| match binding {
+                AxisBinding::Buttons { negative, positive } => held(positive) - held(negative),
+                AxisBinding::Scroll => self.scroll,
+                AxisBinding::MouseX => self.mouse_delta.0,
+                AxisBinding::MouseY => self.mouse_delta.1,
+                AxisBinding::TouchX => self.touch_delta.0,
+                AxisBinding::TouchY => self.touch_delta.1,
+            })
+            .sum()
+    }
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self::new(InputMap::default())
+    }
+}
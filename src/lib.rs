@@ -1,13 +1,136 @@
 // Arc: Atomic Reference Counted (similar to a smart pointer)
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+// Size dependent textures (depth buffers, MSAA targets...) recreated by State::resize
+pub mod attachment;
+use attachment::{Attachment, AttachmentDescriptor, AttachmentId};
+
+// Reading rendered frames back from the GPU
+pub mod capture;
+
+// Rendering without a window (CI, servers without a GPU...)
+pub mod headless;
+
+// Depth buffer and depth test settings
+pub mod depth;
+
+// Multisample anti-aliasing, resolved into the surface
+pub mod msaa;
+
+// Fixed-timestep updates and frame rate limiting
+pub mod timestep;
+use timestep::{FixedTimestep, FrameLimiter};
+
+// Errors preventing the app from starting
+pub mod error;
+pub use error::InitError;
+
+// Picking the backends and the adapter
+pub mod adapter;
+use adapter::AdapterOptions;
+
+// Command line options of the learn_wgpu binary
+pub mod cli;
+
+// Present mode (vsync) and frame latency of the surface
+pub mod present;
+
+// Recovering from a lost surface or device
+pub mod recovery;
+
+// Dropping and recreating the surface when the app is suspended (Android)
+pub mod lifecycle;
+use recovery::SurfaceErrorAction;
+
+// Builder for render pipelines
+pub mod pipeline;
+use pipeline::PipelineBuilder;
+
+// Vertex/index buffers and the Vertex trait describing their layout
+pub mod mesh;
+use mesh::{ColorVertex, Mesh, TexturedVertex, Vertex};
+
+// The view and projection used by the built-in pipelines
+pub mod camera;
+use camera::{Camera, CameraBuffer};
+
+// Orbit, fly and FPS camera controllers driven by winit events
+pub mod controller;
+use controller::{CameraController, FlyController, FpsController, OrbitController};
+
+// Named actions and axes bound to keys, mouse buttons, scrolling and touches
+pub mod input;
+use input::{Input, InputMap, action};
+
+// Textures loaded from images, with their samplers and bind groups
+pub mod texture;
+use texture::{Texture, TexturedMesh};
+
+// Mip chain generation for textures, on the GPU or the CPU
+pub mod mipmap;
+
+// KTX2 and DDS textures in BC, ETC2 or ASTC, picked from the device's features
+pub mod compressed;
+// CPU decoders for the compressed formats the device can't sample
+mod astc_decode;
+mod block_decode;
+mod bptc_decode;
+// Transcoders for Basis Universal textures (ETC1S and UASTC)
+mod basis;
+// CPU encoders for the formats Basis Universal textures are transcoded to
+mod block_encode;
+
+// Recording the events reaching the app to a file and replaying them
+pub mod recording;
+use recording::AppEvent;
+
+// Windows opened next to the main one, sharing its device
+pub mod windows;
+// Title, size, icon and fullscreen mode of the main window
+pub mod window_config;
+use window_config::WindowConfig;
+
+// Loading asset files, from disk on native and over HTTP on the web
+pub mod resources;
+
+// The triangle drawn by default, one primary color per corner.
+// Vertices are in counter-clockwise order so the triangle faces the camera.
+const TRIANGLE_VERTICES: &[ColorVertex] = &[
+    ColorVertex {
+        position: [0.5, -0.5, 0.0],
+        color: [1.0, 0.0, 0.0],
+    },
+    ColorVertex {
+        position: [0.0, 0.5, 0.0],
+        color: [0.0, 1.0, 0.0],
+    },
+    ColorVertex {
+        position: [-0.5, -0.5, 0.0],
+        color: [0.0, 0.0, 1.0],
+    },
+];
+const TRIANGLE_INDICES: &[u16] = &[0, 1, 2];
+
+// The pipeline drawing the ColorVertex meshes, with the camera at @group(0)
+fn default_render_pipeline_builder(
+    camera_buffer: &CameraBuffer,
+    reversed_z: bool,
+) -> PipelineBuilder {
+    PipelineBuilder::new("Render Pipeline", include_str!("shader.wgsl"))
+        .vertex_layout(ColorVertex::desc())
+        .bind_group_layout(camera_buffer.bind_group_layout())
+        .reversed_z(reversed_z)
+}
 
 // winit is a cross-platform windowing and event loop library
 use winit::{
     application::ApplicationHandler,
     event::*,
-    event_loop::{ActiveEventLoop, EventLoop},
-    keyboard::{KeyCode, PhysicalKey},
-    window::Window,
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    window::{Window, WindowAttributes, WindowId},
 };
 
 // conditional compilation attribute
@@ -19,9 +142,70 @@ use wasm_bindgen::prelude::*;
 
 // This will store the state of our game
 pub struct State {
+    // Creates surfaces. Kept to create the surface again after a suspend (see
+    // lifecycle.rs), None when headless.
+    instance: Option<wgpu::Instance>,
+    // The GPU (or software renderer) the device was created from. Kept to check which
+    // formats and sample counts it supports.
+    adapter: wgpu::Adapter,
+    // The surface is the part of the window that we draw to.
+    // 'static lifetime is fine here because the surface holds its own Arc to the window
+    // There is no surface when rendering headless (see headless.rs)
+    surface: Option<wgpu::Surface<'static>>,
+    // The device is the open connection to the GPU, it is used to create resources
+    // (buffers, textures, pipelines...)
+    device: wgpu::Device,
+    // The queue is used to submit recorded command buffers and to write data to the GPU
+    queue: wgpu::Queue,
+    // Describes how the surface creates its underlying SurfaceTextures (format, size,
+    // present mode...)
+    config: wgpu::SurfaceConfiguration,
+    // The surface can't be configured with a zero size (e.g. the canvas has not been laid
+    // out yet on the web), so we remember whether it is safe to render
+    is_surface_configured: bool,
+    // Ratio between physical pixels and logical pixels of the window (e.g. 2.0 on HiDPI screens)
+    scale_factor: f64,
+    // Textures that must have the same size as the surface, recreated in resize().
+    // Removed attachments leave a None behind so the ids of the others stay valid.
+    attachments: Vec<Option<Attachment>>,
+    // Texture rendered to instead of the surface when there is no window
+    offscreen: Option<headless::OffscreenTarget>,
+    // Color the frame is cleared to before anything is drawn
+    clear_color: wgpu::Color,
+    // Where the scene is looked at from, uploaded to camera_buffer every frame
+    camera: Camera,
+    camera_buffer: CameraBuffer,
+    // The camera before the last update, and how far between the two the frame is drawn
+    previous_camera: Camera,
+    interpolation: f32,
+    // Moves the camera from user input, if any
+    camera_controller: Option<Box<dyn CameraController>>,
+    // Whether the cursor is currently grabbed for the controller's mouse look
+    cursor_grabbed: bool,
+    // Depth texture used by the render pass, None if the adapter has no usable depth format
+    depth_attachment: Option<AttachmentId>,
+    // Samples per pixel, and the multisampled texture drawn to when there is more than one
+    sample_count: u32,
+    msaa_attachment: Option<AttachmentId>,
+    // Describes how the GPU draws our meshes (shaders, vertex layout, blending...).
+    // The builders are kept to rebuild the pipelines when the render targets change.
+    render_pipeline: wgpu::RenderPipeline,
+    render_pipeline_builder: PipelineBuilder,
+    // Meshes drawn with render_pipeline every frame
+    meshes: Vec<Mesh>,
+    // Describes the texture and sampler bound at @group(1) of the textured pipeline
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    // Same as render_pipeline, but the color comes from a texture
+    textured_pipeline: wgpu::RenderPipeline,
+    textured_pipeline_builder: PipelineBuilder,
+    // Meshes drawn with textured_pipeline, each with the bind group of its texture
+    textured_meshes: Vec<TexturedMesh>,
+    // Set when the device is lost, see recovery.rs
+    device_lost: Arc<AtomicBool>,
     // Different parts of the application need to access the Window object,
     // Arc ensures that the Window is only dropped when all Arc pointers are out of scope
-    window: Arc<Window>,
+    // None when rendering headless
+    window: Option<Arc<Window>>,
 }
 
 impl State {
@@ -30,73 +214,772 @@ impl State {
     // For instance, requesting an Adapter or Device from wgpu typically uses async
     // because these operations might wait for GPU drivers or the OS
     //
-    // anhyhow::Result<T> is a popular and convenient type for error handling provided
-    // by the `anyhow` crate
-    // anyhow::Result<T> is a specialized Result where the error type E is automatically
-    // handled by `anyhow` to be a dynamic error type (anyhow::Error).
-    // It allow for easy propaagation by using ? operator.
-    pub async fn new(window: Arc<Window>) -> anyhow::Result<Self> {
+    // Creating the State is where most things can go wrong (no GPU, no support for the
+    // window...), so the error is an InitError saying which step failed (see error.rs).
+    // The ? operator converts the wgpu errors into it, and it converts into the
+    // anyhow::Error used everywhere else.
+    pub async fn new(window: Arc<Window>) -> Result<Self, InitError> {
+        Self::new_with_options(window, &AdapterOptions::default()).await
+    }
+
+    // Like new, with the backends and the adapter picked by `options` (see adapter.rs)
+    pub async fn new_with_options(
+        window: Arc<Window>,
+        options: &AdapterOptions,
+    ) -> Result<Self, InitError> {
+        // The instance is the first thing we create when using wgpu.
+        // Its main purpose is to create Adapters and Surfaces.
+        // On native, the backends can also be picked with the WGPU_BACKEND environment
+        // variable. On the web, we use the GL backend (WebGL2) since some browsers do not
+        // support WebGPU yet.
+        let instance = options.create_instance();
+
+        // The surface needs to live as long as the window that created it.
+        // Passing a clone of the Arc lets the surface keep the window alive.
+        let surface = instance.create_surface(window.clone())?;
+
+        // The adapter is a handle to the actual graphics card.
+        // It has to be able to present to our surface.
+        let adapter = options
+            .request_adapter(&instance, Some(&surface), false)
+            .await?;
+
+        let (device, queue) = Self::request_device(&adapter).await?;
+
+        let mut state = Self::from_surface(instance, surface, adapter, device, queue, window)?;
+        // The device is ours, so we are the ones told when it is lost
+        state.device_lost = recovery::watch_device_loss(&state.device);
+        Ok(state)
+    }
+
+    // Configures the surface of `window` and creates the rest of the State around it.
+    // Shared with the windows reusing the device of another State (see windows.rs).
+    fn from_surface(
+        instance: wgpu::Instance,
+        surface: wgpu::Surface<'static>,
+        adapter: wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        window: Arc<Window>,
+    ) -> Result<Self, InitError> {
+        let size = window.inner_size();
+        let surface_caps = surface.get_capabilities(&adapter);
+        // No format means the adapter can't present to this surface
+        let (Some(&first_format), Some(&alpha_mode)) = (
+            surface_caps.formats.first(),
+            surface_caps.alpha_modes.first(),
+        ) else {
+            return Err(InitError::UnsupportedSurface(adapter.get_info().name));
+        };
+        // The shaders assume an sRGB surface texture. Using a different one will result in
+        // all the colors coming out darker.
+        let surface_format = surface_caps
+            .formats
+            .iter()
+            .copied()
+            .find(|f| f.is_srgb())
+            .unwrap_or(first_format);
+
+        let config = wgpu::SurfaceConfiguration {
+            // RENDER_ATTACHMENT means the textures will be used to write to the screen
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: surface_format,
+            // Same clamping as in resize()
+            width: size.width.min(device.limits().max_texture_dimension_2d),
+            height: size.height.min(device.limits().max_texture_dimension_2d),
+            // Vsync with the best mode the surface supports, see present.rs
+            present_mode: present::select_present_mode(
+                wgpu::PresentMode::AutoVsync,
+                &surface_caps.present_modes,
+            ),
+            alpha_mode,
+            view_formats: vec![],
+            // Lets the GPU work on the next frame while the current one is displayed
+            desired_maximum_frame_latency: 2,
+        };
+
+        // Configuring a zero sized surface panics, so wait for the first resize in that case
+        let is_surface_configured = config.width > 0 && config.height > 0;
+        if is_surface_configured {
+            surface.configure(&device, &config);
+        }
+
+        let mut state = Self::from_parts(
+            adapter,
+            device,
+            queue,
+            config,
+            Some(surface),
+            None,
+            Some(window),
+        );
+        state.is_surface_configured = is_surface_configured;
+        state.instance = Some(instance);
+        Ok(state)
+    }
+
+    // Creates everything that doesn't depend on whether we render to a window or offscreen
+    fn from_parts(
+        adapter: wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        surface: Option<wgpu::Surface<'static>>,
+        offscreen: Option<headless::OffscreenTarget>,
+        window: Option<Arc<Window>>,
+    ) -> Self {
+        let scale_factor = window.as_ref().map_or(1.0, |window| window.scale_factor());
+
+        let camera = Camera {
+            aspect: config.width as f32 / config.height as f32,
+            ..Default::default()
+        };
+        let camera_buffer = CameraBuffer::new(&device, &camera);
+
+        // The depth buffer follows the surface size like any other attachment.
+        // MSAA starts disabled, see msaa.rs.
+        let depth_format = depth::select_format(&adapter, &depth::DEPTH_FORMATS);
+        let mut attachments = Vec::new();
+        let depth_attachment = depth_format.map(|format| {
+            let desc = AttachmentDescriptor::new("Depth Texture", format);
+            attachments.push(Some(Attachment::new(
+                &device,
+                desc,
+                config.width,
+                config.height,
+            )));
+            AttachmentId(attachments.len() - 1)
+        });
+        if depth_format.is_none() {
+            log::warn!("No depth format available, meshes are drawn without a depth test");
+        }
+
+        // include_str! embeds the shader source in the binary, so it also works on the web.
+        // Both pipelines get the camera at @group(0).
+        let render_pipeline_builder =
+            default_render_pipeline_builder(&camera_buffer, camera.reversed_z);
+        let render_pipeline =
+            render_pipeline_builder.build(&device, config.format, depth_format, 1);
+
+        let texture_bind_group_layout = Texture::bind_group_layout(&device);
+        let textured_pipeline_builder =
+            PipelineBuilder::new("Textured Pipeline", include_str!("texture.wgsl"))
+                .vertex_layout(TexturedVertex::desc())
+                .bind_group_layout(camera_buffer.bind_group_layout())
+                .bind_group_layout(&texture_bind_group_layout)
+                .reversed_z(camera.reversed_z);
+        let textured_pipeline =
+            textured_pipeline_builder.build(&device, config.format, depth_format, 1);
+
+        let triangle = Mesh::new_indexed(&device, "Triangle", TRIANGLE_VERTICES, TRIANGLE_INDICES);
+
         // 'Self' here refers to the State struct itself.
         // So, this is returning an instance of State
-        Ok(Self { window })
+        Self {
+            instance: None,
+            adapter,
+            surface,
+            device,
+            queue,
+            config,
+            is_surface_configured: true,
+            scale_factor,
+            attachments,
+            offscreen,
+            clear_color: wgpu::Color {
+                r: 0.1,
+                g: 0.2,
+                b: 0.3,
+                a: 1.0,
+            },
+            camera,
+            camera_buffer,
+            previous_camera: camera,
+            interpolation: 1.0,
+            camera_controller: None,
+            cursor_grabbed: false,
+            depth_attachment,
+            sample_count: 1,
+            msaa_attachment: None,
+            render_pipeline,
+            render_pipeline_builder,
+            meshes: vec![triangle],
+            texture_bind_group_layout,
+            textured_pipeline,
+            textured_pipeline_builder,
+            textured_meshes: Vec::new(),
+            // Only set once the constructor watches the device, see recovery.rs
+            device_lost: Arc::new(AtomicBool::new(false)),
+            window,
+        }
+    }
+
+    // The device and queue are requested from the adapter.
+    // Shared by the windowed and the headless constructors.
+    async fn request_device(
+        adapter: &wgpu::Adapter,
+    ) -> Result<(wgpu::Device, wgpu::Queue), InitError> {
+        // WebGL doesn't support all of wgpu's features, so the limits are lowered on the web.
+        // The adapter's downlevel defaults are used as well when it can't do better (e.g. a
+        // software GL renderer), otherwise the request fails.
+        let required_limits = if cfg!(target_arch = "wasm32") {
+            wgpu::Limits::downlevel_webgl2_defaults()
+        } else if adapter.get_downlevel_capabilities().is_webgpu_compliant() {
+            wgpu::Limits::default()
+        } else {
+            wgpu::Limits::downlevel_defaults()
+        };
+
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                label: None,
+                // Compressed textures can only be sampled if their feature is enabled, and
+                // sample counts other than 1 and 4 need the adapter's own format features
+                required_features: adapter.features()
+                    & (compressed::COMPRESSION_FEATURES
+                        | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES),
+                // Take the maximum texture sizes from the adapter so big windows still work
+                required_limits: required_limits.using_resolution(adapter.limits()),
+                memory_hints: Default::default(),
+                trace: wgpu::Trace::Off,
+            })
+            .await?;
+        Ok((device, queue))
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        // A minimized window reports a size of 0x0, and configuring a surface with a zero
+        // size panics. Just stop rendering until the window gets a real size again.
+        if width == 0 || height == 0 {
+            self.is_surface_configured = false;
+            return;
+        }
+
+        // Textures can't be bigger than the device allows, which can happen on very
+        // large (or multiple) monitors with a high scale factor
+        let max_dimension = self.device.limits().max_texture_dimension_2d;
+        if width > max_dimension || height > max_dimension {
+            log::warn!(
+                "Window size {}x{} exceeds the maximum texture size {}, clamping",
+                width,
+                height,
+                max_dimension
+            );
+        }
+
+        // The surface has to be reconfigured every time the window size changes
+        self.config.width = width.min(max_dimension);
+        self.config.height = height.min(max_dimension);
+        if let Some(surface) = &self.surface {
+            surface.configure(&self.device, &self.config);
+        }
+        // Without a window the offscreen texture plays the role of the surface
+        if let Some(offscreen) = &mut self.offscreen {
+            offscreen.resize(&self.device, &self.config);
+        }
+        // A suspended window has nothing to draw into until resume creates a new surface
+        self.is_surface_configured = self.surface.is_some() || self.offscreen.is_some();
+        // Keep the projection in proportion with the new size
+        self.camera.aspect = self.config.width as f32 / self.config.height as f32;
+
+        // Size dependent textures have to match the new surface size
+        for attachment in self.attachments.iter_mut().flatten() {
+            attachment.resize(&self.device, self.config.width, self.config.height);
+        }
+    }
+
+    // Called when the window moves to a monitor with a different DPI or the user changes
+    // the OS scaling. The physical size of the window changes with it, so resize as well.
+    pub fn scale_factor_changed(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+        if let Some(window) = &self.window {
+            let size = window.inner_size();
+            self.resize(size.width, size.height);
+        }
+    }
+
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    // Registers a texture that always has the same size as the surface.
+    // The returned id is used to get the texture view when rendering.
+    pub fn add_attachment(&mut self, desc: AttachmentDescriptor) -> AttachmentId {
+        let attachment = Attachment::new(&self.device, desc, self.config.width, self.config.height);
+        // Reuse the slot of a removed attachment if there is one
+        match self.attachments.iter().position(Option::is_none) {
+            Some(index) => {
+                self.attachments[index] = Some(attachment);
+                AttachmentId(index)
+            }
+            None => {
+                self.attachments.push(Some(attachment));
+                AttachmentId(self.attachments.len() - 1)
+            }
+        }
     }
 
-    pub fn resize(&mut self, _width: u32, _height: u32) {}
+    // Panics if the attachment was removed
+    pub fn attachment(&self, id: AttachmentId) -> &Attachment {
+        self.attachments[id.0]
+            .as_ref()
+            .expect("The attachment was removed")
+    }
+
+    // Drops the texture, the id must not be used anymore
+    pub fn remove_attachment(&mut self, id: AttachmentId) {
+        self.attachments[id.0] = None;
+    }
+
+    // Recreates an attachment with different settings, keeping its id
+    pub(crate) fn replace_attachment(&mut self, id: AttachmentId, desc: AttachmentDescriptor) {
+        let attachment = Attachment::new(&self.device, desc, self.config.width, self.config.height);
+        self.attachments[id.0] = Some(attachment);
+    }
+
+    pub fn window(&self) -> Option<&Arc<Window>> {
+        self.window.as_ref()
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    pub fn config(&self) -> &wgpu::SurfaceConfiguration {
+        &self.config
+    }
+
+    pub fn clear_color(&self) -> wgpu::Color {
+        self.clear_color
+    }
 
-    pub fn render(&mut self) {
-        // make the window draw another frame as soon as possible.
-        // winit only draws one frame unless the window is resized or receiving a request_redraw
-        self.window.request_redraw();
+    pub fn set_clear_color(&mut self, clear_color: wgpu::Color) {
+        self.clear_color = clear_color;
     }
+
+    // Draws the scene as of the last update
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        self.render_interpolated(1.0)
+    }
+
+    // Draws the scene `alpha` of the way between the previous update and the last one
+    // (see timestep.rs). Asking for the next frame is up to the caller.
+    pub fn render_interpolated(&mut self, alpha: f32) -> Result<(), wgpu::SurfaceError> {
+        self.interpolation = alpha.clamp(0.0, 1.0);
+
+        // We can't render unless the surface is configured
+        if !self.is_surface_configured {
+            return Ok(());
+        }
+
+        if let Some(surface) = &self.surface {
+            // get_current_texture waits for the surface to provide a new SurfaceTexture to render to
+            let output = surface.get_current_texture()?;
+            // A TextureView with default settings lets us control how the render code
+            // interacts with the texture
+            let view = output
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+            self.draw(&view);
+            output.present();
+        } else if let Some(offscreen) = &self.offscreen {
+            // Headless: the exact same drawing code targets the offscreen texture
+            self.draw(offscreen.view());
+        }
+
+        Ok(())
+    }
+
+    // Records and submits the commands drawing one frame into `view`
+    fn draw(&self, view: &wgpu::TextureView) {
+        // The camera may have moved since the last frame. write_buffer is executed before
+        // the command buffer submitted below.
+        let camera = self.previous_camera.lerp(&self.camera, self.interpolation);
+        self.camera_buffer.update(&self.queue, &camera);
+
+        // The CommandEncoder builds a command buffer that we can then send to the GPU
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+
+        // With MSAA we draw into the multisampled texture, which is resolved into `view`
+        let (color_view, resolve_target) = self.color_target(view);
+
+        // begin_render_pass borrows encoder mutably, so the pass is put in its own block
+        // to release that borrow before encoder.finish() is called
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: color_view,
+                    resolve_target,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.clear_color),
+                        // With MSAA only the resolved texture is needed after the pass
+                        store: if resolve_target.is_some() {
+                            wgpu::StoreOp::Discard
+                        } else {
+                            wgpu::StoreOp::Store
+                        },
+                    },
+                })],
+                depth_stencil_attachment: self.depth_stencil_attachment(),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            // The camera stays bound when switching pipelines since both have the same
+            // layout at @group(0)
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, self.camera_buffer.bind_group(), &[]);
+            for mesh in &self.meshes {
+                mesh.draw(&mut render_pass);
+            }
+
+            render_pass.set_pipeline(&self.textured_pipeline);
+            for textured in &self.textured_meshes {
+                // Bind the texture to @group(1) before drawing the mesh that uses it
+                render_pass.set_bind_group(1, &textured.bind_group, &[]);
+                textured.mesh.draw(&mut render_pass);
+            }
+        }
+
+        // submit accepts anything that implements IntoIterator
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+// Custom events sent to the event loop through the EventLoopProxy.
+// On the web, State is created asynchronously and arrives this way, or the error that
+// prevented creating it.
+pub enum UserEvent {
+    // Boxed since State is much bigger than an error
+    StateCreated(Box<State>),
+    Error(anyhow::Error),
 }
 
+// ID of the HTML <canvas> element that the wgpu app will draw onto
+#[cfg(target_arch = "wasm32")]
+const CANVAS_ID: &str = "canvas";
+
 // App struct tells winit how to use the State struct
 pub struct App {
     #[cfg(target_arch = "wasm32")]
     // proxy is only needed on the web since creating WGPU resources is a async process
-    proxy: Option<winit::event_loop::EventLoopProxy<State>>,
+    proxy: Option<winit::event_loop::EventLoopProxy<UserEvent>>,
 
     // state stores the State struct as an Option
     // Option is used since State::new() needs a window but window can't be created
     // until the application get to the `Resume` state
     state: Option<State>,
+    // The other windows, see windows.rs
+    windows: HashMap<WindowId, State>,
+    // Opened by the event loop once it is done with the current events
+    pending_windows: Vec<WindowAttributes>,
+    window_config: WindowConfig,
+    // How the State of the main window is created, see the setters
+    adapter_options: AdapterOptions,
+    present_mode: Option<wgpu::PresentMode>,
+    // Shown instead of the default triangle, with its name
+    scene: Option<(String, Vec<u8>)>,
+
+    // Keys, buttons... turned into the actions the app reacts to
+    input: Input,
+
+    // Event times are measured from here, and `frame` counts the frames drawn
+    start: web_time::Instant,
+    frame: u64,
+    // Time of the last frame, the simulation advances by the time elapsed since then
+    last_frame_time: Duration,
+    timestep: FixedTimestep,
+    // Makes the event loop wait between frames when the frame rate is capped
+    frame_limiter: Option<FrameLimiter>,
+    // Set by the exit action or by closing the window
+    exit_requested: bool,
+
+    // Saves the events to a file as they arrive (see recording.rs)
+    #[cfg(not(target_arch = "wasm32"))]
+    recorder: Option<recording::Recorder>,
+    // Events played back instead of the user's input
+    replay: Option<recording::Replay>,
+
+    // Told about the errors the app can't go on after, see set_error_callback
+    error_callback: Box<dyn FnMut(&anyhow::Error)>,
+    // The first of those errors, returned by run()
+    error: Option<anyhow::Error>,
 }
 
 impl App {
     // For WebAssembly builds:
-    // The new function will have a parameter named event_loop of type &EventLoop<State>.
+    // The new function will have a parameter named event_loop of type &EventLoop<UserEvent>.
     // This event_loop is necessary on the web to create the EventLoopProxy.
     // For Native builds:
     // The new function will not have an event_loop parameter at all.
     // Its signature will effectively be pub fn new() -> Self.
     // The compiler completely omits parameter event_loop for non-WASM builds.
-    pub fn new(#[cfg(target_arch = "wasm32")] event_loop: &EventLoop<State>) -> Self {
+    // A Default impl can't be provided since the wasm build needs the event_loop parameter
+    #[allow(clippy::new_without_default)]
+    pub fn new(#[cfg(target_arch = "wasm32")] event_loop: &EventLoop<UserEvent>) -> Self {
+        #[cfg(target_arch = "wasm32")]
+        let app = Self::with_input_map(event_loop, load_input_map());
+        #[cfg(not(target_arch = "wasm32"))]
+        let app = Self::with_input_map(load_input_map());
+        app
+    }
+
+    // Like new, with these bindings instead of the ones in bindings.toml
+    pub fn with_input_map(
+        #[cfg(target_arch = "wasm32")] event_loop: &EventLoop<UserEvent>,
+        input_map: InputMap,
+    ) -> Self {
         #[cfg(target_arch = "wasm32")]
         let proxy = Some(event_loop.create_proxy());
         Self {
             state: None,
+            windows: HashMap::new(),
+            pending_windows: Vec::new(),
+            window_config: WindowConfig::default(),
+            adapter_options: AdapterOptions::default(),
+            present_mode: None,
+            scene: None,
             #[cfg(target_arch = "wasm32")]
             proxy,
+            input: Input::new(input_map),
+            start: web_time::Instant::now(),
+            frame: 0,
+            last_frame_time: Duration::ZERO,
+            timestep: FixedTimestep::default(),
+            frame_limiter: None,
+            exit_requested: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            recorder: None,
+            replay: None,
+            error_callback: Box::new(default_error_callback),
+            error: None,
         }
     }
-}
 
-// implement ApplicationHandler trait for App
-// This allows App to get application events such as key press, mouse movements and various lifecycle events.
-impl ApplicationHandler<State> for App {
-    // resumed method is called by winit when the window becomes "resumed" or "active"
-    // resumed method is usually used for:
-    // 1. create the application window if it does not exist
-    // 2. initialize the application's state, including the wgpu rendering context
+    // The actions and axes of the input, with the bindings the app was made with
+    pub fn input(&self) -> &Input {
+        &self.input
+    }
 
-    // self is a mutable reference to App to modify it's state
-    // event_loop provides access to currently active winit event loop
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+    // Replaces what happens with an error the app can't recover from (no GPU, device lost
+    // for good...). By default it is logged, and on the web also shown in the page. The app
+    // exits after the callback, and run() returns the error.
+    pub fn set_error_callback(&mut self, callback: impl FnMut(&anyhow::Error) + 'static) {
+        self.error_callback = Box::new(callback);
+    }
+
+    // Reports an error the app can't recover from and asks the event loop to exit
+    fn fail(&mut self, error: anyhow::Error) {
+        (self.error_callback)(&error);
+        self.error.get_or_insert(error);
+        self.exit_requested = true;
+    }
+
+    // Everything the app does with an event, whether it comes from winit or from a
+    // recording. `time` is the time since the start of the app.
+    fn process_event(&mut self, event: AppEvent, time: Duration) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.record(event, time);
+
+        let Some(state) = &mut self.state else {
+            return;
+        };
+        match event {
+            AppEvent::CloseRequested => self.exit_requested = true,
+            AppEvent::Resized { width, height } => state.resize(width, height),
+            AppEvent::ScaleFactorChanged { scale_factor } => {
+                state.scale_factor_changed(scale_factor)
+            }
+            AppEvent::RedrawRequested => self.frame(time),
+            // The camera controller gets the input first, what it uses isn't handled again
+            _ => {
+                if !state.process_event(&event) {
+                    self.input.process_event(&event);
+                }
+            }
+        }
+    }
+
+    // One frame: the actions pressed since the previous one, the simulation steps that
+    // fit in the time elapsed, then drawing
+    fn frame(&mut self, time: Duration) {
+        if self.state.as_ref().is_some_and(State::is_device_lost) {
+            self.recreate_device();
+        }
+        if let Some(limiter) = &mut self.frame_limiter {
+            limiter.frame_started(web_time::Instant::now());
+        }
+        self.process_actions();
+        let steps = self
+            .timestep
+            .advance(time.saturating_sub(self.last_frame_time));
+        self.last_frame_time = time;
+        // The other windows draw on their own redraws, with the same simulation steps
+        for state in self.windows.values_mut() {
+            for _ in 0..steps {
+                state.update(self.timestep.step());
+            }
+        }
+        if let Some(state) = &mut self.state {
+            for _ in 0..steps {
+                state.update(self.timestep.step());
+            }
+            if let Err(e) = state.render_interpolated(self.timestep.alpha())
+                && state.handle_surface_error(&e) == SurfaceErrorAction::Exit
+            {
+                self.fail(anyhow::Error::new(e).context("Unable to render"));
+            }
+        }
+        self.input.end_frame();
+        self.frame += 1;
+
+        // winit only draws one frame unless the window is resized or receiving a
+        // request_redraw. With a frame rate cap, about_to_wait schedules it instead.
+        if self.frame_limiter.is_none() {
+            self.request_redraw();
+        }
+    }
+
+    // Rebuilds State on a new device, see recovery.rs
+    fn recreate_device(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(state) = &mut self.state
+            && let Err(e) = pollster::block_on(state.recreate_device())
+        {
+            self.fail(e.context("Unable to recreate the device"));
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        self.share_main_device();
+
+        // Requesting a device can't block on the web. State is handed back through the
+        // proxy like when it was first created, and frames are skipped in the meantime.
+        #[cfg(target_arch = "wasm32")]
+        if let (Some(proxy), Some(mut state)) = (self.proxy.clone(), self.state.take()) {
+            wasm_bindgen_futures::spawn_local(async move {
+                let event = match state.recreate_device().await {
+                    Ok(()) => UserEvent::StateCreated(Box::new(state)),
+                    Err(e) => UserEvent::Error(e.context("Unable to recreate the device")),
+                };
+                let _ = proxy.send_event(event);
+            });
+        }
+    }
+
+    fn request_redraw(&self) {
+        let states = self.state.iter().chain(self.windows.values());
+        for window in states.filter_map(State::window) {
+            window.request_redraw();
+        }
+    }
+
+    // The backends and the adapter the main window is drawn with, see adapter.rs.
+    // Like the ones below, it has no effect once the window exists.
+    pub fn set_adapter_options(&mut self, options: AdapterOptions) {
+        self.adapter_options = options;
+    }
+
+    // The present mode the main window starts with, instead of AutoVsync
+    pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) {
+        self.present_mode = Some(mode);
+    }
+
+    // Shows an image (PNG, KTX2 or DDS file) instead of the default triangle
+    pub fn set_scene(&mut self, name: impl Into<String>, bytes: Vec<u8>) {
+        self.scene = Some((name.into(), bytes));
+    }
+
+    // Runs the simulation `rate` times per second. At most `max_steps` updates run per
+    // frame, the simulation slows down when frames take longer than that.
+    // Fails when the rate isn't positive, the current rate is kept.
+    pub fn set_update_rate(&mut self, rate: f64, max_steps: u32) -> anyhow::Result<()> {
+        self.timestep = FixedTimestep::from_rate(rate, max_steps)?;
+        Ok(())
+    }
+
+    // Caps the frame rate, None draws frames as fast as the present mode allows.
+    // Fails when the limit isn't positive, the current limit is kept.
+    pub fn set_frame_rate_limit(&mut self, max_fps: Option<f64>) -> anyhow::Result<()> {
+        self.frame_limiter = max_fps.map(FrameLimiter::new).transpose()?;
+        self.request_redraw();
+        Ok(())
+    }
+
+    // Reacts to the actions triggered since the last frame
+    fn process_actions(&mut self) {
+        let Some(state) = &mut self.state else {
+            return;
+        };
+        let input = &self.input;
+        if input.just_pressed(action::EXIT) {
+            self.exit_requested = true;
+        }
+        if input.just_pressed(action::OPEN_WINDOW) {
+            self.pending_windows.push(windows::tool_window_attributes());
+        }
+        if input.just_pressed(action::TOGGLE_FULLSCREEN)
+            && let Some(window) = state.window()
+        {
+            self.window_config.toggle_fullscreen(window);
+        }
+        if input.just_pressed(action::ORBIT_CAMERA) {
+            log::info!("Orbit camera: drag with the left mouse button, scroll to zoom");
+            state.set_camera_controller(Some(Box::new(OrbitController::default())));
+        }
+        if input.just_pressed(action::FLY_CAMERA) {
+            log::info!("Fly camera: WASD to move, Q/E down/up, hold the right button to look");
+            state.set_camera_controller(Some(Box::new(FlyController::default())));
+        }
+        if input.just_pressed(action::FPS_CAMERA) {
+            log::info!("FPS camera: WASD to move, click to look around, Escape to release");
+            state.set_camera_controller(Some(Box::new(FpsController::default())));
+        }
+        if input.just_pressed(action::CYCLE_MSAA) {
+            match state.cycle_sample_count() {
+                Ok(1) => log::info!("MSAA off"),
+                Ok(count) => log::info!("{}x MSAA", count),
+                Err(e) => log::error!("Unable to change the MSAA sample count: {}", e),
+            }
+        }
+        if input.just_pressed(action::TOGGLE_VSYNC) {
+            let mode = if present::is_vsync(state.present_mode()) {
+                wgpu::PresentMode::AutoNoVsync
+            } else {
+                wgpu::PresentMode::AutoVsync
+            };
+            log::info!("Present mode {:?}", state.set_present_mode(mode));
+        }
+        // Save the current frame as a PNG in the working directory.
+        // There is no file system to write to on the web.
+        #[cfg(not(target_arch = "wasm32"))]
+        if input.just_pressed(action::SCREENSHOT) {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            let path = format!("screenshot-{}.png", timestamp);
+            if let Err(e) = pollster::block_on(state.save_screenshot(&path)) {
+                log::error!("Unable to save screenshot {}: {}", path, e);
+            }
+        }
+    }
+
+    // Creates the window and starts creating the State drawing into it.
+    // On the web, State is created asynchronously and arrives in user_event.
+    fn create_window(&mut self, event_loop: &ActiveEventLoop) -> Result<(), InitError> {
         #[allow(unused_mut)]
-        // initialize a mutable window_attributes with default values
+        // initialize a mutable window_attributes from the window configuration
         // WindowAttributes define properties of the window you want to create (e.g., title,
-        // size...)
-        let mut window_attributes = Window::default_attributes();
+        // size...), see window_config.rs
+        let mut window_attributes = self.window_config.attributes(event_loop);
 
         // wasm specific setup
         #[cfg(target_arch = "wasm32")]
@@ -106,28 +989,19 @@ impl ApplicationHandler<State> for App {
             // import WindowAttributesExtWebSys trait for wasm-specific methods
             use winit::platform::web::WindowAttributesExtWebSys;
 
-            // defines a  constant for the ID of theh HTML <canvas> element that the wgpu app will
-            // draw onto
-            const CANVAS_ID: &str = "canvas";
-
             // web_sys::window() is a function from the web-sys crate that
-            // gets a reference to the browser's global Window object.
+            // gets a reference to the browser's global Window object,
+            // and document() the `Document` object of the page.
+            // Both only fail outside of a page (e.g. in a worker).
             //
-            // .unwrap_throw() is a wasm-bindgen utility function.
-            // If the Option returned by window() is None (meaning no window context is available,
-            // which shouldn't happen in a browser), it will immediately throw a JavaScript error,
-            // halting execution. It's similar to Rust's unwrap(), but for wasm error handling.
-            let window = wgpu::web_sys::window().unwrap_throw();
-
-            // gets a reference to the browser's `Document` object from the `Window`
-            let document = window.document().unwrap_throw();
-            // finds the HTML element with the ID "canvas" in the document
-            let canvas = document.get_element_by_id(CANVAS_ID).unwrap_throw();
-            // This is an unsafe method from wasm-bindgen::JsCast that performs a type assertion.
-            // It casts the generic Element (returned by get_element_by_id) into a specific HtmlCanvasElement.
-            // This is necessary because winit's with_canvas expects a typed HtmlCanvasElement.
-            // (commonly used and often safe in practice when you know the element type)
-            let html_canvas_element = canvas.unchecked_into();
+            // get_element_by_id finds the HTML element with the ID "canvas" in the document.
+            // dyn_into() from wasm-bindgen::JsCast checks that the generic Element is a
+            // HtmlCanvasElement, since winit's with_canvas expects a typed HtmlCanvasElement.
+            let html_canvas_element = wgpu::web_sys::window()
+                .and_then(|window| window.document())
+                .and_then(|document| document.get_element_by_id(CANVAS_ID))
+                .and_then(|canvas| canvas.dyn_into::<wgpu::web_sys::HtmlCanvasElement>().ok())
+                .ok_or_else(|| InitError::CanvasMissing(CANVAS_ID.to_string()))?;
 
             // This is the critical part for WASM.
             // It modifies the window_attributes to tell `winit` that
@@ -137,9 +1011,8 @@ impl ApplicationHandler<State> for App {
         }
 
         // event_loop.create_window is the init function for creating a window
-        // wraps the created window in an Arc for shared ownership, or panics if window creating
-        // fails
-        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+        // wraps the created window in an Arc for shared ownership
+        let window = Arc::new(event_loop.create_window(window_attributes)?);
 
         // this block only runs on native desktop builds
         #[cfg(not(target_arch = "wasm32"))]
@@ -151,7 +1024,10 @@ impl ApplicationHandler<State> for App {
             // On native platforms, the resumed event itself is often called from a synchroonous
             // context (the main event loop thread). Since `State::new()` is async, it needs a
             // way to execute that async code in a blocking manner.
-            self.state = Some(pollster::block_on(State::new(window)).unwrap());
+            let mut state =
+                pollster::block_on(State::new_with_options(window, &self.adapter_options))?;
+            prepare_state(&mut state, self.present_mode, self.scene.as_ref());
+            self.set_state(state);
         }
 
         #[cfg(target_arch = "wasm32")]
@@ -159,88 +1035,235 @@ impl ApplicationHandler<State> for App {
             // Run the future asynchronously and use the
             // proxy to send the results to the event loop
             //
-            // take() replaces the Some(proxy) with None, ensuring that this initialization logic runs
-            // only once
-            if let Some(proxy) = self.proxy.take() {
+            // resumed is only called once on the web, when the page is loaded.
+            // The proxy is kept to hand State back after a device loss (see recovery.rs).
+            if let Some(proxy) = self.proxy.clone() {
+                let options = self.adapter_options.clone();
+                let present_mode = self.present_mode;
+                let scene = self.scene.clone();
                 // wasm_bindgen_futures::spawn_local is a crucial function for running async Rust
                 // code in a web browser.
                 // It takes an async block (a Future) and schedules it to run on the browser's event
                 // loop (the main JavaScript thread). It does not block the current thread.
                 //
-                // send_event() sends the newly initialized State instance as a custom event back to
-                // the winit event loop.
+                // send_event() sends the newly initialized State instance (or the reason it
+                // couldn't be created) as a custom event back to the winit event loop.
                 // This is how you communicate the result of the asynchronous State creation back to
                 // the main App logic.
                 //
-                // assert!(...).is_ok() Asserts that sending the event was successful.
-                // send_event can fail if the event loop has already been closed.
+                // send_event can only fail if the event loop has already been closed, in which
+                // case nobody is left to use the State.
                 wasm_bindgen_futures::spawn_local(async move {
-                    assert!(
-                        proxy
-                            .send_event(
-                                State::new(window)
-                                    .await // await pauses the execution of this async move block until State::new completes
-                                    .expect("Unable to create canvas!!!")
-                            )
-                            .is_ok()
-                    )
+                    // await pauses the execution of this async move block until State::new completes
+                    let event = match State::new_with_options(window, &options).await {
+                        Ok(mut state) => {
+                            prepare_state(&mut state, present_mode, scene.as_ref());
+                            UserEvent::StateCreated(Box::new(state))
+                        }
+                        Err(error) => UserEvent::Error(error.into()),
+                    };
+                    let _ = proxy.send_event(event);
                 });
             }
         }
+        Ok(())
+    }
+
+    // Called once the State is created (asynchronously on the web)
+    fn set_state(&mut self, mut state: State) {
+        // The camera starts orbiting the scene, keys 1 to 3 switch to another controller.
+        // A State coming back from a device loss keeps its controller.
+        if state.camera_controller.is_none() {
+            state.set_camera_controller(Some(Box::new(OrbitController::default())));
+        }
+        // A replay needs to know the size the recording started with
+        #[cfg(not(target_arch = "wasm32"))]
+        self.record(
+            AppEvent::Resized {
+                width: state.config.width,
+                height: state.config.height,
+            },
+            self.start.elapsed(),
+        );
+        self.state = Some(state);
+        // Back from a device loss on the web
+        self.share_main_device();
+    }
+}
+
+// The settings of App applied to a new State, before it draws its first frame.
+// A scene that can't be shown is logged, and the default scene stays.
+fn prepare_state(
+    state: &mut State,
+    present_mode: Option<wgpu::PresentMode>,
+    scene: Option<&(String, Vec<u8>)>,
+) {
+    if let Some(mode) = present_mode {
+        state.set_present_mode(mode);
+    }
+    if let Some((name, bytes)) = scene
+        && let Err(e) = state.show_image(bytes, name)
+    {
+        log::error!("Unable to show {}: {:#}", name, e);
+    }
+}
+
+// Logs the error with its causes. The console isn't visible to most users on the web, so
+// the error is also written in the page, after the canvas.
+fn default_error_callback(error: &anyhow::Error) {
+    log::error!("{:#}", error);
+    #[cfg(target_arch = "wasm32")]
+    show_error_in_page(error);
+}
+
+#[cfg(target_arch = "wasm32")]
+fn show_error_in_page(error: &anyhow::Error) {
+    let Some(document) = wgpu::web_sys::window().and_then(|window| window.document()) else {
+        return;
+    };
+    let Ok(message) = document.create_element("pre") else {
+        return;
+    };
+    message.set_text_content(Some(&format!("{:#}", error)));
+    let _ = message.set_attribute("style", "color: red; white-space: pre-wrap");
+    match document.get_element_by_id(CANVAS_ID) {
+        Some(canvas) => {
+            let _ = canvas.after_with_node_1(&message);
+        }
+        None => {
+            if let Some(root) = document.document_element() {
+                let _ = root.append_child(&message);
+            }
+        }
+    }
+}
+
+// The default bindings, overridden on native by the ones in bindings.toml if it exists
+fn load_input_map() -> InputMap {
+    #[cfg(not(target_arch = "wasm32"))]
+    match InputMap::load_or_default(input::BINDINGS_FILE) {
+        Ok(map) => return map,
+        Err(e) => log::error!("{}, using the default bindings", e),
+    }
+    InputMap::default()
+}
+
+// implement ApplicationHandler trait for App
+// This allows App to get application events such as key press, mouse movements and various lifecycle events.
+impl ApplicationHandler<UserEvent> for App {
+    // resumed method is called by winit when the window becomes "resumed" or "active"
+    // resumed method is usually used for:
+    // 1. create the application window if it does not exist
+    // 2. initialize the application's state, including the wgpu rendering context
+
+    // self is a mutable reference to App to modify it's state
+    // event_loop provides access to currently active winit event loop
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        // Coming back from the background (Android): the window, the device and every GPU
+        // resource are still there, only the surface has to be created again
+        if self.state.is_some() {
+            let mut states = self.state.iter_mut().chain(self.windows.values_mut());
+            if let Err(error) = states.try_for_each(State::resume) {
+                self.fail(error.context("Unable to resume"));
+                event_loop.exit();
+            } else {
+                self.request_redraw();
+            }
+            return;
+        }
+
+        // Without a window or a State there is nothing the app can do
+        if let Err(error) = self.create_window(event_loop) {
+            self.fail(error.into());
+            event_loop.exit();
+        }
+    }
+
+    // The app went to the background (Android) and its native window is about to be
+    // destroyed, so the surface drawing into it has to go (see lifecycle.rs)
+    fn suspended(&mut self, _event_loop: &ActiveEventLoop) {
+        for state in self.state.iter_mut().chain(self.windows.values_mut()) {
+            state.suspend();
+        }
     }
 
     // user_event just serves as a landing point for our `State` future.
     // `resumed` is not async so we need to offload the future and send the results somewhere
-    #[allow(unused_mut)]
-    fn user_event(&mut self, _event_loop: &ActiveEventLoop, mut event: State) {
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: UserEvent) {
         // This is where proxy.send_event() ends up
-        #[cfg(target_arch = "wasm32")]
-        {
-            event.window.request_redraw();
-            event.resize(
-                event.window.inner_size().width,
-                event.window.inner_size().height,
-            );
+        match event {
+            #[allow(unused_mut)]
+            UserEvent::StateCreated(mut state) => {
+                #[cfg(target_arch = "wasm32")]
+                if let Some(window) = state.window.clone() {
+                    window.request_redraw();
+                    state.resize(window.inner_size().width, window.inner_size().height);
+                }
+                self.set_state(*state);
+            }
+            UserEvent::Error(error) => self.fail(error),
+        }
+        if self.exit_requested {
+            event_loop.exit();
         }
-        self.state = Some(event);
     }
 
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
-        _window_id: winit::window::WindowId,
+        window_id: WindowId,
         event: WindowEvent,
     ) {
-        let state = match &mut self.state {
-            Some(canvas) => canvas,
-            None => return,
+        if !self.is_main_window(window_id) {
+            return self.other_window_event(window_id, event);
+        }
+        // Only the events App reacts to have an AppEvent
+        let Some(event) = AppEvent::from_window_event(&event) else {
+            return;
         };
+        let time = self.start.elapsed();
+        if self.replay.is_some() {
+            self.replay_window_event(event, time);
+        } else {
+            self.process_event(event, time);
+        }
+        if self.exit_requested {
+            event_loop.exit();
+        }
+    }
 
-        match event {
-            WindowEvent::CloseRequested => event_loop.exit(),
-            WindowEvent::Resized(size) => state.resize(size.width, size.height),
-            WindowEvent::RedrawRequested => {
-                state.render();
+    // With a frame rate cap, the next frame is drawn once the event loop has waited long
+    // enough. Input events still wake it up in the meantime.
+    fn new_events(&mut self, _event_loop: &ActiveEventLoop, cause: StartCause) {
+        if let StartCause::ResumeTimeReached { .. } = cause {
+            self.request_redraw();
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        self.create_pending_windows(event_loop);
+        match &self.frame_limiter {
+            Some(limiter) => {
+                event_loop.set_control_flow(ControlFlow::WaitUntil(limiter.next_frame()))
             }
-            // The curly braces {} allow for destructuring the KeyboardInput variant.
-            // This means its internal fields can be pulled out.
-            WindowEvent::KeyboardInput {
-                event: // This destructures the inner `KeyEvent` struct
-                KeyEvent {
-                    // Why PhysicalKey::Code?
-                    // For game controls or system commands (like quitting with Escape),
-                    // using PhysicalKey::Code is often preferred because it's consistent
-                    // across different keyboard layouts.
-                    physical_key: PhysicalKey::Code(code), // Extracts the physical key code (e.g., A, Escape)
-                    state, // Extracts the key state (Pressed or Released)
-                    .. // Ignores other fields of KeyEvent (e.g., logical_key, text)
-                },
-                .. // Ignores other fields of WindowEvent::KeyboardInput
-            } => match (code, state.is_pressed()) { // 
-                (KeyCode::Escape, true) => event_loop.exit(), // exit if ESC is pressed
-                _ => {} // do nothing if other keys are pressed
-            },
-            _ => {}
+            None => event_loop.set_control_flow(ControlFlow::Wait),
+        }
+    }
+
+    // Device events come from the input devices themselves rather than a window.
+    // MouseMotion is the raw mouse movement, which still works when the cursor is grabbed.
+    fn device_event(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+        _device_id: DeviceId,
+        event: DeviceEvent,
+    ) {
+        if let (None, DeviceEvent::MouseMotion { delta }) = (&self.replay, event) {
+            let event = AppEvent::MouseMotion {
+                dx: delta.0,
+                dy: delta.1,
+            };
+            self.process_event(event, self.start.elapsed());
         }
     }
 }
@@ -249,6 +1272,11 @@ impl ApplicationHandler<State> for App {
 // This function sets up the logger as well as creates the event_loop and our app and then
 // runs our app to completion
 pub fn run() -> anyhow::Result<()> {
+    run_with_options(cli::Options::default())
+}
+
+// run() with the command line options of the binary (see cli.rs)
+pub fn run_with_options(options: cli::Options) -> anyhow::Result<()> {
     // initialize logging
     #[cfg(not(target_arch = "wasm32"))]
     {
@@ -256,37 +1284,195 @@ pub fn run() -> anyhow::Result<()> {
     }
     #[cfg(target_arch = "wasm32")]
     {
-        console_log::init_with_level(log::Level::Info).unwrap_throw();
+        console_log::init_with_level(log::Level::Info)?;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if options.list_adapters {
+        list_adapters(&options.adapter);
+        return Ok(());
+    }
+
+    // --replay plays a recording back, and with --headless it is rendered without a
+    // window. Otherwise --headless renders --frames frames of the scene.
+    #[cfg(not(target_arch = "wasm32"))]
+    let replay = match &options.replay {
+        Some(path) => Some(recording::Recording::load(path)?),
+        None => None,
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    if options.headless {
+        return run_headless(&options, replay);
     }
 
     // Create the winit EventLoop
     // This mechanism dispatches events (user input, window events...) to the application.
     // .with_user_event() allows sending custom events later (used in WASM setup)
     // .build()? creates the event loop, propagating any build errors
-    let event_loop = EventLoop::with_user_event().build()?;
+    #[allow(unused_mut)]
+    let mut builder = EventLoop::with_user_event();
+    // On Linux, winit picks Wayland when it is available and X11 otherwise
+    #[cfg(target_os = "linux")]
+    match options.display_server {
+        Some(cli::DisplayServer::X11) => {
+            use winit::platform::x11::EventLoopBuilderExtX11;
+            builder.with_x11();
+        }
+        Some(cli::DisplayServer::Wayland) => {
+            use winit::platform::wayland::EventLoopBuilderExtWayland;
+            builder.with_wayland();
+        }
+        None => {}
+    }
+    #[cfg(not(target_os = "linux"))]
+    if options.display_server.is_some() {
+        log::warn!("--x11 and --wayland only apply on Linux");
+    }
+    let event_loop = builder.build()?;
+
+    run_event_loop(
+        event_loop,
+        &options,
+        #[cfg(not(target_arch = "wasm32"))]
+        replay,
+    )
+}
 
+// Creates the App and runs it until the event loop exits.
+// Shared by run() and android_main, which builds its event loop differently.
+fn run_event_loop(
+    event_loop: EventLoop<UserEvent>,
+    options: &cli::Options,
+    #[cfg(not(target_arch = "wasm32"))] replay: Option<recording::Recording>,
+) -> anyhow::Result<()> {
     // create main App struct
     // The event_loop parameter is conditionally passed for WASM targets
     let mut app = App::new(
         #[cfg(target_arch = "wasm32")]
         &event_loop,
     );
+    app.set_adapter_options(options.adapter.clone());
+    if let Some(mode) = options.present_mode {
+        app.set_present_mode(mode);
+    }
+    app.set_window_config(WindowConfig {
+        size: options.size,
+        ..Default::default()
+    });
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        if let Some(path) = &options.scene {
+            app.set_scene(path.display().to_string(), load_scene(path)?);
+        }
+        if let Some(path) = &options.record {
+            app.record_to(path)?;
+        }
+        if let Some(recording) = replay {
+            app.replay(recording);
+        }
+    }
 
     // start the winit event loop, handing control to your App
     event_loop.run_app(&mut app)?;
 
+    // The app may have exited because of an error (no GPU...)
+    if let Some(error) = app.error.take() {
+        return Err(error);
+    }
+
     // If the event loop exits successfully, return Ok(())
     // (): This is the "unit type" in Rust, essentially meaning "nothing" or "no specific value."
     // When a function returns Ok(()), it signifies success without returning any particular data.
     Ok(())
 }
 
+// --list-adapters: prints the report of every adapter, and which one --adapter picks
+#[cfg(not(target_arch = "wasm32"))]
+fn list_adapters(options: &AdapterOptions) {
+    let reports = options.list_adapters();
+    if reports.is_empty() {
+        println!("No adapter found for the backends {:?}", options.backends());
+        return;
+    }
+    let selected = options.selected_index(&reports);
+    for report in &reports {
+        println!("{}", report);
+    }
+    match (&options.adapter, selected) {
+        (Some(selector), Some(index)) => println!("--adapter {} picks #{}", selector, index),
+        (Some(selector), None) => println!("No adapter matches --adapter {}", selector),
+        (None, _) => {}
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_scene(path: &std::path::Path) -> anyhow::Result<Vec<u8>> {
+    std::fs::read(path).map_err(|e| anyhow::anyhow!("Unable to read {}: {}", path.display(), e))
+}
+
+// --headless: renders the scene or replays a recording without a window, and saves the
+// frames in --output if given
+#[cfg(not(target_arch = "wasm32"))]
+fn run_headless(
+    options: &cli::Options,
+    replay: Option<recording::Recording>,
+) -> anyhow::Result<()> {
+    // A recording starts with the size it was recorded at
+    let (width, height) = options
+        .size
+        .or(replay.as_ref().and_then(recording::Recording::initial_size))
+        .unwrap_or((800, 600));
+    let mut state = pollster::block_on(State::new_headless_with_options(
+        width,
+        height,
+        &options.adapter,
+    ))?;
+    if let Some(path) = &options.scene {
+        state.show_image(&load_scene(path)?, &path.display().to_string())?;
+    }
+
+    let output = options.output.as_deref();
+    let frames = match replay {
+        Some(recording) => pollster::block_on(App::replay_headless_on(state, recording, output))?,
+        None => pollster::block_on(App::render_headless(
+            state,
+            options.headless_frames(),
+            output,
+        ))?,
+    };
+    match output {
+        Some(output) => log::info!("Rendered {} frames into {}", frames, output.display()),
+        None => log::info!("Rendered {} frames", frames),
+    }
+    Ok(())
+}
+
+// Entry point on Android, called by the NativeActivity glue of android-activity on its
+// own thread. The crate is built as a cdylib and packaged into an APK (with cargo-apk,
+// xbuild...). There is no terminal, logs and errors go to logcat.
+#[cfg(target_os = "android")]
+#[unsafe(no_mangle)]
+fn android_main(android_app: winit::platform::android::activity::AndroidApp) {
+    use winit::platform::android::EventLoopBuilderExtAndroid;
+
+    android_logger::init_once(
+        android_logger::Config::default().with_max_level(log::LevelFilter::Info),
+    );
+    let result = EventLoop::with_user_event()
+        .with_android_app(android_app)
+        .build()
+        .map_err(anyhow::Error::from)
+        .and_then(|event_loop| run_event_loop(event_loop, &cli::Options::default(), None));
+    if let Err(error) = result {
+        log::error!("{:#}", error);
+    }
+}
+
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen(start)]
-// Set up console_error_panic_hook so we can see the code panic information in the browser
+// Set up console_error_panic_hook so we can see the code panic information in the browser.
+// An error is thrown as a JavaScript exception with its causes, so it shows in the console.
 pub fn run_web() -> Result<(), wasm_bindgen::JsValue> {
     console_error_panic_hook::set_once();
-    run().unwrap_throw();
-
-    Ok(())
+    run().map_err(|e| wasm_bindgen::JsValue::from_str(&format!("{:#}", e)))
 }
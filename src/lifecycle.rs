@@ -0,0 +1,76 @@
+// Suspending and resuming the app.
+// On Android (and iOS) the app is suspended when it goes to the background: the native
+// window behind the winit Window is destroyed, and any surface created from it becomes
+// invalid. winit calls `suspended` then, and `resumed` once the app is back in the
+// foreground with a new native window.
+//
+// Only the surface depends on the native window. The device, the queue and everything
+// created from them (pipelines, buffers, textures) stay alive while suspended, so resuming
+// only has to create a new surface and configure it.
+// Desktop platforms and the web never suspend, `resumed` is only called once at startup.
+
+use crate::{State, attachment::AttachmentDescriptor};
+
+impl State {
+    // Whether the surface was dropped by suspend and not created again yet
+    pub fn is_suspended(&self) -> bool {
+        self.window.is_some() && self.surface.is_none()
+    }
+
+    // Drops the surface, nothing is drawn until resume is called.
+    // Headless states keep drawing into their offscreen texture.
+    pub fn suspend(&mut self) {
+        if self.surface.take().is_some() {
+            self.is_surface_configured = false;
+            log::info!("Suspended, surface dropped");
+        }
+    }
+
+    // Creates a surface for the (new) native window of the same winit Window and
+    // configures it. Does nothing for headless states and when not suspended.
+    pub fn resume(&mut self) -> anyhow::Result<()> {
+        let (Some(instance), Some(window), None) =
+            (&self.instance, self.window.clone(), &self.surface)
+        else {
+            return Ok(());
+        };
+        let surface = instance.create_surface(window.clone())?;
+
+        // The new surface may not support the old format. The pipelines and the MSAA
+        // texture are made for the surface format, so they change with it.
+        let caps = surface.get_capabilities(&self.adapter);
+        let format = if caps.formats.contains(&self.config.format) {
+            self.config.format
+        } else {
+            caps.formats
+                .iter()
+                .copied()
+                .find(|f| f.is_srgb())
+                .or(caps.formats.first().copied())
+                .ok_or_else(|| {
+                    anyhow::anyhow!("The surface is not supported by the adapter anymore")
+                })?
+        };
+        if format != self.config.format {
+            log::warn!(
+                "Surface format changed from {:?} to {:?}",
+                self.config.format,
+                format
+            );
+            self.config.format = format;
+            if let Some(id) = self.msaa_attachment {
+                let desc = AttachmentDescriptor::new("MSAA Color Texture", format)
+                    .with_sample_count(self.sample_count);
+                self.replace_attachment(id, desc);
+            }
+            self.rebuild_pipelines();
+        }
+        self.surface = Some(surface);
+
+        // The window may have been resized while in the background
+        let size = window.inner_size();
+        self.resize(size.width, size.height);
+        log::info!("Resumed, surface recreated");
+        Ok(())
+    }
+}
@@ -1,8 +1,13 @@
-use learn_wgpu::run;
+use learn_wgpu::cli::{Options, USAGE};
+use learn_wgpu::run_with_options;
 
-fn main() {
-    unsafe {
-        std::env::set_var("WAYLAND_DISPLAY", ""); // Force X11 on Linux
+// Returning the error prints it with its causes, e.g. "No graphics adapter supports the
+// window's surface" followed by what wgpu reported
+fn main() -> anyhow::Result<()> {
+    let options = Options::parse(std::env::args_os().skip(1))?;
+    if options.help {
+        print!("{}", USAGE);
+        return Ok(());
     }
-    run().unwrap();
+    run_with_options(options)
 }
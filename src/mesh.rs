@@ -0,0 +1,199 @@
+// Vertex and index buffers.
+// A Mesh owns the GPU buffers for one piece of geometry and knows how to draw itself,
+// and the Vertex trait ties a vertex struct to the layout the pipeline needs to read it.
+
+use wgpu::util::DeviceExt;
+
+use crate::State;
+
+// Implemented by every struct used as a vertex.
+// bytemuck::Pod guarantees the struct can be copied into a buffer as plain bytes
+// (it must be #[repr(C)] with no padding and no pointers).
+pub trait Vertex: bytemuck::Pod {
+    // Describes how the struct is laid out in the vertex buffer
+    fn desc() -> wgpu::VertexBufferLayout<'static>;
+}
+
+// Index types supported by wgpu: u16 uses half the memory, u32 allows more than 65536 vertices
+pub trait Index: bytemuck::Pod {
+    const FORMAT: wgpu::IndexFormat;
+}
+
+impl Index for u16 {
+    const FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint16;
+}
+
+impl Index for u32 {
+    const FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint32;
+}
+
+// A vertex with a position and a color, used by the default scene
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ColorVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+}
+
+impl ColorVertex {
+    // @location(0) is the position and @location(1) the color in the shader.
+    // The attributes have to be a const so the layout can borrow them for 'static.
+    const ATTRIBS: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+}
+
+impl Vertex for ColorVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            // How wide a vertex is, the shader skips this many bytes to get to the next one
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            // Each element of the buffer is one vertex (and not one instance)
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+// A vertex with a position and texture coordinates, used by the textured pipeline
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TexturedVertex {
+    pub position: [f32; 3],
+    // (0, 0) is the top left corner of the texture and (1, 1) the bottom right one
+    pub tex_coords: [f32; 2],
+}
+
+impl TexturedVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
+}
+
+impl Vertex for TexturedVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+pub fn create_vertex_buffer<V: Vertex>(
+    device: &wgpu::Device,
+    label: &str,
+    vertices: &[V],
+) -> wgpu::Buffer {
+    // create_buffer_init comes from the DeviceExt trait, it creates and fills the buffer at once
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(label),
+        contents: bytemuck::cast_slice(vertices),
+        usage: wgpu::BufferUsages::VERTEX,
+    })
+}
+
+pub fn create_index_buffer<I: Index>(
+    device: &wgpu::Device,
+    label: &str,
+    indices: &[I],
+) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(label),
+        contents: bytemuck::cast_slice(indices),
+        usage: wgpu::BufferUsages::INDEX,
+    })
+}
+
+pub struct Mesh {
+    vertex_buffer: wgpu::Buffer,
+    // None when the vertices are drawn in order without an index buffer
+    index_buffer: Option<(wgpu::Buffer, wgpu::IndexFormat)>,
+    // Number of indices, or of vertices when there is no index buffer
+    num_elements: u32,
+    // What the buffers were filled with. Buffers die with their device, so a copy is kept
+    // to upload them again if the device is lost (see recovery.rs).
+    label: String,
+    vertex_data: Vec<u8>,
+    index_data: Option<Vec<u8>>,
+}
+
+impl Mesh {
+    // A mesh drawn straight from its vertices, every 3 vertices make a triangle
+    pub fn new<V: Vertex>(device: &wgpu::Device, label: &str, vertices: &[V]) -> Self {
+        Self {
+            vertex_buffer: create_vertex_buffer(device, label, vertices),
+            index_buffer: None,
+            num_elements: vertices.len() as u32,
+            label: label.to_string(),
+            vertex_data: bytemuck::cast_slice(vertices).to_vec(),
+            index_data: None,
+        }
+    }
+
+    // A mesh whose triangles are made of indices into `vertices`,
+    // so vertices shared between triangles are only stored once
+    pub fn new_indexed<V: Vertex, I: Index>(
+        device: &wgpu::Device,
+        label: &str,
+        vertices: &[V],
+        indices: &[I],
+    ) -> Self {
+        Self {
+            vertex_buffer: create_vertex_buffer(device, label, vertices),
+            index_buffer: Some((create_index_buffer(device, label, indices), I::FORMAT)),
+            num_elements: indices.len() as u32,
+            label: label.to_string(),
+            vertex_data: bytemuck::cast_slice(vertices).to_vec(),
+            index_data: Some(bytemuck::cast_slice(indices).to_vec()),
+        }
+    }
+
+    // The same mesh with its buffers created on `device`
+    pub(crate) fn recreate(&self, device: &wgpu::Device) -> Self {
+        let create_buffer = |contents: &[u8], usage| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&self.label),
+                contents,
+                usage,
+            })
+        };
+        let index_buffer = self
+            .index_buffer
+            .as_ref()
+            .zip(self.index_data.as_ref())
+            .map(|((_, format), data)| (create_buffer(data, wgpu::BufferUsages::INDEX), *format));
+        Self {
+            vertex_buffer: create_buffer(&self.vertex_data, wgpu::BufferUsages::VERTEX),
+            index_buffer,
+            num_elements: self.num_elements,
+            label: self.label.clone(),
+            vertex_data: self.vertex_data.clone(),
+            index_data: self.index_data.clone(),
+        }
+    }
+
+    // Records the draw call. The pipeline has to be set on the render pass already
+    // and its vertex layout must match the one this mesh was created with.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        match &self.index_buffer {
+            Some((index_buffer, format)) => {
+                render_pass.set_index_buffer(index_buffer.slice(..), *format);
+                render_pass.draw_indexed(0..self.num_elements, 0, 0..1);
+            }
+            None => render_pass.draw(0..self.num_elements, 0..1),
+        }
+    }
+}
+
+impl State {
+    // Adds a mesh to the ones drawn by render() with the default pipeline
+    pub fn add_mesh(&mut self, mesh: Mesh) {
+        self.meshes.push(mesh);
+    }
+
+    // Removes every mesh, textured or not
+    pub fn clear_meshes(&mut self) {
+        self.meshes.clear();
+        self.textured_meshes.clear();
+    }
+}
@@ -0,0 +1,228 @@
+// Mipmaps are smaller copies of a texture (half the size each level, down to 1x1).
+// When a texture is minified the sampler reads from the level closest to the on-screen
+// size instead of skipping texels, which is what makes far away textures shimmer.
+//
+// The levels can be generated on the GPU with one render pass per level, or on the CPU
+// when rendering into the texture isn't possible. Both use the same box filter, so the
+// results only differ by rounding.
+
+use crate::texture::ColorSpace;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MipmapMode {
+    // Only the full size level
+    #[default]
+    None,
+    // Every level is rendered from the previous one (needs RENDER_ATTACHMENT usage)
+    Gpu,
+    // Every level is computed on the CPU and uploaded with the base level
+    Cpu,
+}
+
+// Number of levels in a full mip chain: 256x100 -> 256, 128, 64, 32, 16, 8, 4, 2, 1 = 9
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+// Size of `level` for a texture of `size` texels, never smaller than 1
+pub fn mip_level_size(size: u32, level: u32) -> u32 {
+    (size >> level).max(1)
+}
+
+// Renders levels 1.. of `texture` from level 0
+pub(crate) fn generate_gpu(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) {
+    let shader = device.create_shader_module(wgpu::include_wgsl!("mipmap.wgsl"));
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Mipmap Bind Group Layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            // The shader uses textureLoad, so no sampler and no filtering is needed
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        }],
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Mipmap Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Mipmap Pipeline"),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(texture.format().into())],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    });
+
+    // A view of a single level, so we can read one level and render into the next
+    let level_view = |level: u32| {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Mip Level"),
+            base_mip_level: level,
+            mip_level_count: Some(1),
+            ..Default::default()
+        })
+    };
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Mipmap Encoder"),
+    });
+    for level in 1..texture.mip_level_count() {
+        let src_view = level_view(level - 1);
+        let dst_view = level_view(level);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Mipmap Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&src_view),
+            }],
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Mipmap Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &dst_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    // Every texel is written, so there is nothing to clear
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+}
+
+// Computes levels 1.. of `base` on the CPU, in the same order as the texture levels
+pub(crate) fn generate_cpu(
+    base: &image::RgbaImage,
+    color_space: ColorSpace,
+) -> Vec<image::RgbaImage> {
+    let mut levels = Vec::new();
+    let level_count = mip_level_count(base.width(), base.height());
+    // Averaging has to be done on linear values, otherwise sRGB textures get darker
+    let mut current = to_linear(base, color_space);
+    let (mut width, mut height) = base.dimensions();
+    for _ in 1..level_count {
+        let (next, next_width, next_height) = downsample(&current, width, height);
+        levels.push(from_linear(&next, next_width, next_height, color_space));
+        (current, width, height) = (next, next_width, next_height);
+    }
+    levels
+}
+
+// Same box filter as mipmap.wgsl, see tap_weight there.
+// Returns the (offset, weight) pairs of the source texels for destination texel `x`.
+fn taps(x: u32, src_size: u32, dst_size: u32) -> Vec<(u32, f32)> {
+    if src_size == 1 {
+        return vec![(x, 1.0)];
+    }
+    if src_size.is_multiple_of(2) {
+        return vec![(2 * x, 0.5), (2 * x + 1, 0.5)];
+    }
+    let n = dst_size as f32;
+    let total = 2.0 * n + 1.0;
+    vec![
+        (2 * x, (n - x as f32) / total),
+        (2 * x + 1, n / total),
+        (2 * x + 2, (x as f32 + 1.0) / total),
+    ]
+}
+
+fn downsample(src: &[[f32; 4]], width: u32, height: u32) -> (Vec<[f32; 4]>, u32, u32) {
+    let dst_width = mip_level_size(width, 1);
+    let dst_height = mip_level_size(height, 1);
+    let mut dst = Vec::with_capacity((dst_width * dst_height) as usize);
+    for y in 0..dst_height {
+        let y_taps = taps(y, height, dst_height);
+        for x in 0..dst_width {
+            let x_taps = taps(x, width, dst_width);
+            let mut color = [0.0; 4];
+            for &(sy, wy) in &y_taps {
+                for &(sx, wx) in &x_taps {
+                    let texel = src[(sy.min(height - 1) * width + sx.min(width - 1)) as usize];
+                    for (c, t) in color.iter_mut().zip(texel) {
+                        *c += wx * wy * t;
+                    }
+                }
+            }
+            dst.push(color);
+        }
+    }
+    (dst, dst_width, dst_height)
+}
+
+fn to_linear(img: &image::RgbaImage, color_space: ColorSpace) -> Vec<[f32; 4]> {
+    img.pixels()
+        .map(|p| {
+            let [r, g, b, a] = p.0.map(|c| c as f32 / 255.0);
+            match color_space {
+                // Alpha is always linear
+                ColorSpace::Srgb => [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a],
+                ColorSpace::Linear => [r, g, b, a],
+            }
+        })
+        .collect()
+}
+
+fn from_linear(
+    texels: &[[f32; 4]],
+    width: u32,
+    height: u32,
+    color_space: ColorSpace,
+) -> image::RgbaImage {
+    let raw = texels
+        .iter()
+        .flat_map(|&[r, g, b, a]| {
+            let rgb = match color_space {
+                ColorSpace::Srgb => [linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b)],
+                ColorSpace::Linear => [r, g, b],
+            };
+            [rgb[0], rgb[1], rgb[2], a].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+        })
+        .collect();
+    image::RgbaImage::from_raw(width, height, raw).unwrap()
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
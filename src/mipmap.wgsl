@@ -0,0 +1,56 @@
+// Downsamples one mip level into the next one.
+// A fullscreen triangle covers the whole destination level, and every fragment averages
+// the source texels under its footprint with a box filter.
+
+@group(0) @binding(0)
+var src: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // (-1, -1), (3, -1), (-1, 3): one triangle larger than the screen
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+// Weight of the i-th of the 3 taps for destination texel `x` along one axis.
+// When the source size is even each destination texel covers exactly 2 texels.
+// When it is odd (2n + 1 texels shrinking to n), each destination texel covers 2 + 1/n
+// source texels, so a third tap is needed and the taps are weighted by their coverage.
+fn tap_weight(i: u32, x: u32, src_size: u32, dst_size: u32) -> f32 {
+    if (src_size == 1u) {
+        return select(0.0, 1.0, i == 0u);
+    }
+    if (src_size % 2u == 0u) {
+        return select(0.0, 0.5, i < 2u);
+    }
+    let n = f32(dst_size);
+    let fx = f32(x);
+    let weights = array<f32, 3>(n - fx, n, fx + 1.0);
+    return weights[i] / (2.0 * n + 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let dst = vec2<u32>(position.xy);
+    let src_size = textureDimensions(src);
+    let dst_size = max(src_size / 2u, vec2<u32>(1u));
+    // The first source texel under the destination texel
+    let origin = select(dst * 2u, dst, src_size == vec2<u32>(1u));
+
+    // textureLoad converts sRGB texels to linear values, so the average is done in linear
+    // space and writing to an sRGB target converts the result back
+    var color = vec4<f32>(0.0);
+    for (var j = 0u; j < 3u; j++) {
+        let wy = tap_weight(j, dst.y, src_size.y, dst_size.y);
+        for (var i = 0u; i < 3u; i++) {
+            let wx = tap_weight(i, dst.x, src_size.x, dst_size.x);
+            let w = wx * wy;
+            if (w > 0.0) {
+                let texel = min(origin + vec2<u32>(i, j), src_size - 1u);
+                color += w * textureLoad(src, texel, 0);
+            }
+        }
+    }
+    return color;
+}
@@ -0,0 +1,141 @@
+// Multisample anti-aliasing (MSAA) smooths the jagged edges of triangles.
+// Every pixel of the render target stores several samples, and triangles covering only
+// some of them blend with what is behind. The multisampled texture can't be presented, so
+// at the end of the render pass it is resolved (averaged) into the surface texture.
+//
+// The multisampled color texture and the depth texture are attachments, so State::resize
+// recreates them. Pipelines have to be built for the same sample count as the render
+// pass: the built-in ones are rebuilt when it changes, custom ones have to be recreated.
+
+use crate::{State, attachment::AttachmentDescriptor};
+
+// The sample counts wgpu can use, 1 meaning no MSAA
+pub const SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+
+// WebGL2 only guarantees 4 samples, and browsers don't report what the GPU can do
+#[cfg(target_arch = "wasm32")]
+const MAX_SAMPLE_COUNT: u32 = 4;
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_SAMPLE_COUNT: u32 = 8;
+
+// The sample counts every one of `formats` can be rendered and resolved with.
+// Without TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES on the device only the counts
+// guaranteed by WebGPU (1 and 4) can be used, whatever the adapter supports.
+pub fn supported_sample_counts(
+    adapter: &wgpu::Adapter,
+    device: &wgpu::Device,
+    formats: &[wgpu::TextureFormat],
+) -> Vec<u32> {
+    let adapter_specific = device
+        .features()
+        .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+    let features = |format: wgpu::TextureFormat| {
+        if adapter_specific {
+            adapter.get_texture_format_features(format)
+        } else {
+            format.guaranteed_format_features(device.features())
+        }
+    };
+    SAMPLE_COUNTS
+        .into_iter()
+        .filter(|&count| count <= MAX_SAMPLE_COUNT)
+        .filter(|&count| {
+            formats.iter().all(|&format| {
+                let flags = features(format).flags;
+                // Color formats also need to be resolvable, depth is never resolved
+                let resolvable = format.is_depth_stencil_format()
+                    || flags.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE);
+                count == 1 || (flags.sample_count_supported(count) && resolvable)
+            })
+        })
+        .collect()
+}
+
+impl State {
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    // Sample counts usable with the current color and depth formats, in increasing order
+    pub fn supported_sample_counts(&self) -> Vec<u32> {
+        let mut formats = vec![self.config.format];
+        formats.extend(self.depth_format());
+        supported_sample_counts(&self.adapter, &self.device, &formats)
+    }
+
+    // Switches to `sample_count` samples per pixel. Counts the adapter doesn't support
+    // fall back to the highest supported count below them. Returns the count in use.
+    pub fn set_sample_count(&mut self, sample_count: u32) -> anyhow::Result<u32> {
+        if !SAMPLE_COUNTS.contains(&sample_count) {
+            anyhow::bail!(
+                "Invalid sample count {}, expected one of {:?}",
+                sample_count,
+                SAMPLE_COUNTS
+            );
+        }
+        let supported = self.supported_sample_counts();
+        let count = supported
+            .iter()
+            .copied()
+            .filter(|&count| count <= sample_count)
+            .max()
+            .unwrap_or(1);
+        if count != sample_count {
+            log::warn!(
+                "{}x MSAA is not supported (supported: {:?}), using {}x",
+                sample_count,
+                supported,
+                count
+            );
+        }
+        if count == self.sample_count {
+            return Ok(count);
+        }
+        self.sample_count = count;
+
+        // The color texture is only needed with MSAA, the surface texture is used otherwise
+        let desc = AttachmentDescriptor::new("MSAA Color Texture", self.config.format)
+            .with_sample_count(count);
+        self.msaa_attachment = match (self.msaa_attachment, count) {
+            (Some(id), 1) => {
+                self.remove_attachment(id);
+                None
+            }
+            (Some(id), _) => {
+                self.replace_attachment(id, desc);
+                Some(id)
+            }
+            (None, 1) => None,
+            (None, _) => Some(self.add_attachment(desc)),
+        };
+        // The depth texture must have the same sample count as the color target
+        if let (Some(id), Some(format)) = (self.depth_attachment, self.depth_format()) {
+            self.replace_attachment(id, self.depth_descriptor(format));
+        }
+        self.rebuild_pipelines();
+        Ok(count)
+    }
+
+    // Moves to the next supported sample count, going back to 1 after the highest
+    pub fn cycle_sample_count(&mut self) -> anyhow::Result<u32> {
+        let supported = self.supported_sample_counts();
+        let next = supported
+            .iter()
+            .copied()
+            .find(|&count| count > self.sample_count)
+            .unwrap_or(1);
+        self.set_sample_count(next)
+    }
+
+    // The view to draw into and the view it is resolved to, for the color attachment of
+    // the render pass targeting `view`
+    pub(crate) fn color_target<'a>(
+        &'a self,
+        view: &'a wgpu::TextureView,
+    ) -> (&'a wgpu::TextureView, Option<&'a wgpu::TextureView>) {
+        match self.msaa_attachment {
+            Some(id) => (self.attachment(id).view(), Some(view)),
+            None => (view, None),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+// A small builder around wgpu::RenderPipelineDescriptor.
+// Creating a render pipeline needs a lot of boilerplate, and most of it is the same for
+// every pipeline we make, so only the parts that actually change are exposed here.
+//
+// The builder is Clone and owns everything it needs, so State can keep it around and
+// rebuild the pipeline later (e.g. when the surface format changes).
+
+use std::borrow::Cow;
+
+use crate::{State, depth};
+
+#[derive(Clone, Debug)]
+pub struct PipelineBuilder {
+    label: String,
+    // WGSL source of the shader module containing both the vertex and fragment entry points
+    shader_source: Cow<'static, str>,
+    vs_entry: String,
+    fs_entry: String,
+    // Describes how the vertex buffers are laid out in memory, one per buffer slot
+    vertex_layouts: Vec<wgpu::VertexBufferLayout<'static>>,
+    bind_group_layouts: Vec<wgpu::BindGroupLayout>,
+    // None means the fragment output simply replaces what is in the target
+    blend: Option<wgpu::BlendState>,
+    topology: wgpu::PrimitiveTopology,
+    front_face: wgpu::FrontFace,
+    cull_mode: Option<wgpu::Face>,
+    // Fragments are kept when `depth_compare(fragment depth, stored depth)` is true.
+    // Only used when rendering with a depth buffer.
+    depth_compare: wgpu::CompareFunction,
+    depth_write_enabled: bool,
+}
+
+impl PipelineBuilder {
+    // The entry points default to `vs_main` and `fs_main`
+    pub fn new(label: &str, shader_source: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            label: label.to_string(),
+            shader_source: shader_source.into(),
+            vs_entry: "vs_main".to_string(),
+            fs_entry: "fs_main".to_string(),
+            vertex_layouts: Vec::new(),
+            bind_group_layouts: Vec::new(),
+            blend: Some(wgpu::BlendState::REPLACE),
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            // Triangles are front facing if their vertices are in counter-clockwise order
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            // Closer fragments have a smaller depth
+            depth_compare: wgpu::CompareFunction::Less,
+            depth_write_enabled: true,
+        }
+    }
+
+    pub fn entry_points(mut self, vs_entry: &str, fs_entry: &str) -> Self {
+        self.vs_entry = vs_entry.to_string();
+        self.fs_entry = fs_entry.to_string();
+        self
+    }
+
+    // Adds the layout of the next vertex buffer slot
+    pub fn vertex_layout(mut self, layout: wgpu::VertexBufferLayout<'static>) -> Self {
+        self.vertex_layouts.push(layout);
+        self
+    }
+
+    // Adds the layout of the next bind group (@group(0), @group(1)...)
+    pub fn bind_group_layout(mut self, layout: &wgpu::BindGroupLayout) -> Self {
+        self.bind_group_layouts.push(layout.clone());
+        self
+    }
+
+    // Swaps every bind group layout for the one `replace` returns, e.g. the same layout
+    // created on a new device. Returns false and changes nothing if one has no replacement.
+    pub(crate) fn replace_bind_group_layouts(
+        &mut self,
+        replace: impl Fn(&wgpu::BindGroupLayout) -> Option<wgpu::BindGroupLayout>,
+    ) -> bool {
+        let layouts: Option<Vec<_>> = self.bind_group_layouts.iter().map(replace).collect();
+        match layouts {
+            Some(layouts) => {
+                self.bind_group_layouts = layouts;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn blend(mut self, blend: Option<wgpu::BlendState>) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: wgpu::FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn depth_compare(mut self, depth_compare: wgpu::CompareFunction) -> Self {
+        self.depth_compare = depth_compare;
+        self
+    }
+
+    // Whether fragments that pass the depth test update the depth buffer.
+    // Usually disabled for transparent meshes, so they don't hide what is drawn after them.
+    pub fn depth_write(mut self, enabled: bool) -> Self {
+        self.depth_write_enabled = enabled;
+        self
+    }
+
+    // Picks the depth test matching Camera::reversed_z (Greater instead of Less)
+    pub fn reversed_z(self, reversed_z: bool) -> Self {
+        self.depth_compare(depth::compare_function(reversed_z))
+    }
+
+    // Creates the pipeline for render targets of the given format.
+    // The pipeline's depth format and sample count have to match the render pass:
+    // depth_format is None for passes without a depth texture, sample_count 1 without MSAA.
+    pub fn build(
+        &self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&self.label),
+            source: wgpu::ShaderSource::Wgsl(self.shader_source.clone()),
+        });
+
+        let bind_group_layouts: Vec<&wgpu::BindGroupLayout> =
+            self.bind_group_layouts.iter().collect();
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&self.label),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&self.label),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some(&self.vs_entry),
+                buffers: &self.vertex_layouts,
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            // The fragment stage is technically optional, but we always write colors
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some(&self.fs_entry),
+                // One color target with the same format as what we render to
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: self.blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: self.topology,
+                strip_index_format: None,
+                front_face: self.front_face,
+                cull_mode: self.cull_mode,
+                // Anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: self.depth_write_enabled,
+                depth_compare: self.depth_compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            // Only used for array textures
+            multiview: None,
+            cache: None,
+        })
+    }
+}
+
+impl State {
+    // Builds a pipeline whose color and depth targets and sample count match the ones State
+    // renders to
+    pub fn create_pipeline(&self, builder: &PipelineBuilder) -> wgpu::RenderPipeline {
+        builder.build(
+            &self.device,
+            self.config.format,
+            self.depth_format(),
+            self.sample_count,
+        )
+    }
+
+    // Replaces the pipeline used by the default render pass.
+    // The builder is kept to rebuild the pipeline when the render targets change.
+    pub fn set_render_pipeline(&mut self, builder: &PipelineBuilder) {
+        self.render_pipeline_builder = builder.clone();
+        self.render_pipeline = self.create_pipeline(builder);
+    }
+
+    // Recreates the built-in pipelines from their builders
+    pub(crate) fn rebuild_pipelines(&mut self) {
+        self.render_pipeline = self.create_pipeline(&self.render_pipeline_builder);
+        self.textured_pipeline = self.create_pipeline(&self.textured_pipeline_builder);
+    }
+}
@@ -0,0 +1,122 @@
+// The present mode decides what happens when a frame is ready before the screen is:
+// - Fifo waits for the next vertical blank (vsync). Frames are never torn, and rendering
+//   can't go faster than the display. Always supported.
+// - FifoRelaxed is Fifo, but a late frame is shown right away (tearing) instead of
+//   waiting for the next blank.
+// - Mailbox doesn't wait: the latest frame replaces the one waiting to be shown.
+//   No tearing, but frames are rendered for nothing.
+// - Immediate shows frames right away, tearing if needed. The lowest latency.
+// - AutoVsync and AutoNoVsync let wgpu pick the best supported mode with or without vsync.
+//
+// desired_maximum_frame_latency is how many frames the GPU may queue ahead of the screen.
+// 1 has the lowest input latency, 2 or 3 keep the GPU busy when frame times vary.
+
+use crate::State;
+
+// Order in which explicit modes replace each other when they aren't supported.
+// Fifo is last since every surface supports it.
+fn fallbacks(mode: wgpu::PresentMode) -> &'static [wgpu::PresentMode] {
+    use wgpu::PresentMode::*;
+    match mode {
+        Mailbox => &[Mailbox, Immediate, Fifo],
+        Immediate => &[Immediate, Mailbox, Fifo],
+        FifoRelaxed => &[FifoRelaxed, Fifo],
+        Fifo => &[Fifo],
+        AutoVsync => &[AutoVsync],
+        AutoNoVsync => &[AutoNoVsync],
+    }
+}
+
+// The mode to configure the surface with when `requested` is wanted and `supported` are
+// the modes of the surface capabilities. The Auto modes are always accepted, wgpu resolves
+// them when the surface is configured.
+pub fn select_present_mode(
+    requested: wgpu::PresentMode,
+    supported: &[wgpu::PresentMode],
+) -> wgpu::PresentMode {
+    fallbacks(requested)
+        .iter()
+        .copied()
+        .find(|mode| {
+            matches!(
+                mode,
+                wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync
+            ) || supported.contains(mode)
+        })
+        .unwrap_or(wgpu::PresentMode::Fifo)
+}
+
+// Names accepted for the present modes, e.g. on the command line
+pub fn parse_present_mode(s: &str) -> Option<wgpu::PresentMode> {
+    use wgpu::PresentMode::*;
+    match s.to_lowercase().replace('_', "-").as_str() {
+        "fifo" => Some(Fifo),
+        "fifo-relaxed" => Some(FifoRelaxed),
+        "mailbox" => Some(Mailbox),
+        "immediate" => Some(Immediate),
+        "auto-vsync" | "vsync" => Some(AutoVsync),
+        "auto-no-vsync" | "no-vsync" => Some(AutoNoVsync),
+        _ => None,
+    }
+}
+
+// Whether the mode waits for the vertical blank
+pub fn is_vsync(mode: wgpu::PresentMode) -> bool {
+    matches!(
+        mode,
+        wgpu::PresentMode::Fifo | wgpu::PresentMode::FifoRelaxed | wgpu::PresentMode::AutoVsync
+    )
+}
+
+impl State {
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.config.present_mode
+    }
+
+    // Modes the surface supports, empty when rendering headless
+    pub fn supported_present_modes(&self) -> Vec<wgpu::PresentMode> {
+        match &self.surface {
+            Some(surface) => surface.get_capabilities(&self.adapter).present_modes,
+            None => Vec::new(),
+        }
+    }
+
+    // Switches the present mode without recreating the window, falling back to a
+    // supported mode if needed. Returns the mode in use.
+    pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) -> wgpu::PresentMode {
+        let selected = if self.surface.is_some() {
+            select_present_mode(mode, &self.supported_present_modes())
+        } else {
+            // Nothing is presented, the mode is only remembered
+            mode
+        };
+        if selected != mode {
+            log::warn!(
+                "Present mode {:?} is not supported, using {:?}",
+                mode,
+                selected
+            );
+        }
+        self.config.present_mode = selected;
+        self.reconfigure_surface();
+        selected
+    }
+
+    pub fn frame_latency(&self) -> u32 {
+        self.config.desired_maximum_frame_latency
+    }
+
+    // How many frames may be queued ahead of the display, at least 1.
+    // It is a hint, the platform may not follow it exactly.
+    pub fn set_frame_latency(&mut self, frames: u32) {
+        self.config.desired_maximum_frame_latency = frames.max(1);
+        self.reconfigure_surface();
+    }
+
+    // Applies changes of the configuration to the surface
+    fn reconfigure_surface(&mut self) {
+        if let (Some(surface), true) = (&self.surface, self.is_surface_configured) {
+            surface.configure(&self.device, &self.config);
+        }
+    }
+}
@@ -0,0 +1,426 @@
+// Recording and replaying what the user did, to reproduce bugs frame for frame.
+// winit events can't be saved (or even created outside of winit), so the window events
+// App reacts to are first turned into AppEvents. Both live and replayed events then go
+// through the same code. A recording is a list of AppEvents with the frame they arrived
+// in and their time since the start of the app, saved as TOML:
+//
+//     [[events]]
+//     frame = 12
+//     time = 0.2034
+//     type = "keyboard"
+//     key = "KeyW"
+//     pressed = true
+//     repeat = false
+//
+// Every frame ends with a redraw_requested event. Its time drives the camera controllers,
+// so a replay moves the camera exactly like the recorded session did, whatever the
+// frame rate of the machine replaying it.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, TouchPhase, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+use crate::{App, State};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AppEvent {
+    Resized {
+        width: u32,
+        height: u32,
+    },
+    ScaleFactorChanged {
+        scale_factor: f64,
+    },
+    CloseRequested,
+    Focused {
+        focused: bool,
+    },
+    // Keys by position on the keyboard, see PhysicalKey
+    Keyboard {
+        key: KeyCode,
+        pressed: bool,
+        // Sent while a key is held down
+        repeat: bool,
+    },
+    MouseButton {
+        button: MouseButton,
+        pressed: bool,
+    },
+    // Mouse wheel, in lines
+    ScrollLines {
+        x: f32,
+        y: f32,
+    },
+    // Touchpad scrolling, in pixels
+    ScrollPixels {
+        x: f64,
+        y: f64,
+    },
+    // Position of the cursor in the window, in physical pixels
+    CursorMoved {
+        x: f64,
+        y: f64,
+    },
+    Touch {
+        id: u64,
+        phase: TouchPhase,
+        x: f64,
+        y: f64,
+    },
+    // Raw mouse movement (DeviceEvent::MouseMotion)
+    MouseMotion {
+        dx: f64,
+        dy: f64,
+    },
+    // The end of a frame
+    RedrawRequested,
+}
+
+impl AppEvent {
+    // None for the window events App ignores
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        Some(match event {
+            WindowEvent::Resized(size) => Self::Resized {
+                width: size.width,
+                height: size.height,
+            },
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => Self::ScaleFactorChanged {
+                scale_factor: *scale_factor,
+            },
+            WindowEvent::CloseRequested => Self::CloseRequested,
+            WindowEvent::Focused(focused) => Self::Focused { focused: *focused },
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state,
+                        repeat,
+                        ..
+                    },
+                ..
+            } => Self::Keyboard {
+                key: *key,
+                pressed: state.is_pressed(),
+                repeat: *repeat,
+            },
+            WindowEvent::MouseInput { button, state, .. } => Self::MouseButton {
+                button: *button,
+                pressed: *state == ElementState::Pressed,
+            },
+            WindowEvent::MouseWheel { delta, .. } => match *delta {
+                MouseScrollDelta::LineDelta(x, y) => Self::ScrollLines { x, y },
+                MouseScrollDelta::PixelDelta(position) => Self::ScrollPixels {
+                    x: position.x,
+                    y: position.y,
+                },
+            },
+            WindowEvent::CursorMoved { position, .. } => Self::CursorMoved {
+                x: position.x,
+                y: position.y,
+            },
+            WindowEvent::Touch(touch) => Self::Touch {
+                id: touch.id,
+                phase: touch.phase,
+                x: touch.location.x,
+                y: touch.location.y,
+            },
+            WindowEvent::RedrawRequested => Self::RedrawRequested,
+            _ => return None,
+        })
+    }
+
+    // The scrolling as winit reports it, for the scroll events
+    pub fn scroll_delta(&self) -> Option<MouseScrollDelta> {
+        match *self {
+            Self::ScrollLines { x, y } => Some(MouseScrollDelta::LineDelta(x, y)),
+            Self::ScrollPixels { x, y } => Some(MouseScrollDelta::PixelDelta((x, y).into())),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimedEvent {
+    // Number of frames drawn before the event arrived. A replay checks that it is still
+    // the case, or the events would reach a different frame than they were recorded in.
+    pub frame: u64,
+    // Seconds since the app started
+    pub time: f64,
+    #[serde(flatten)]
+    pub event: AppEvent,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    #[serde(default)]
+    pub events: Vec<TimedEvent>,
+}
+
+impl Recording {
+    pub fn from_toml(source: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(source)?)
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Unable to read {}: {}", path.display(), e))?;
+        Self::from_toml(&source)
+            .map_err(|e| anyhow::anyhow!("Invalid recording {}: {}", path.display(), e))
+    }
+
+    // The first size in the recording, which is the size of the window when the
+    // recording started
+    pub fn initial_size(&self) -> Option<(u32, u32)> {
+        self.events.iter().find_map(|timed| match timed.event {
+            AppEvent::Resized { width, height } => Some((width, height)),
+            _ => None,
+        })
+    }
+
+    // Number of frames drawn in the recording
+    pub fn frame_count(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|timed| timed.event == AppEvent::RedrawRequested)
+            .count()
+    }
+}
+
+// Writes events to a file as they arrive, so the recording survives a crash.
+// Every event is written as its own [[events]] table, and TOML allows appending more of
+// them, so the file is a valid recording at any time.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Recorder {
+    path: std::path::PathBuf,
+    writer: std::io::BufWriter<std::fs::File>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Recorder {
+    // Creates (or truncates) the file at `path`
+    pub fn create(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = std::fs::File::create(&path)
+            .map_err(|e| anyhow::anyhow!("Unable to create {}: {}", path.display(), e))?;
+        log::info!("Recording input to {}", path.display());
+        Ok(Self {
+            path,
+            writer: std::io::BufWriter::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    pub fn record(&mut self, event: TimedEvent) -> anyhow::Result<()> {
+        use std::io::Write;
+
+        let chunk = Recording {
+            events: vec![event],
+        }
+        .to_toml()?;
+        self.writer.write_all(chunk.as_bytes())?;
+        // Frames are a good moment to make sure everything reached the file
+        if event.event == AppEvent::RedrawRequested {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+// Feeds the events of a recording to the app one frame at a time
+pub(crate) struct Replay {
+    events: std::vec::IntoIter<TimedEvent>,
+}
+
+impl Replay {
+    pub(crate) fn new(recording: Recording) -> Self {
+        Self {
+            events: recording.events.into_iter(),
+        }
+    }
+
+    // The events of the next frame, ending with its redraw_requested event.
+    // Empty once the recording is over.
+    pub(crate) fn next_frame(&mut self) -> Vec<TimedEvent> {
+        let mut frame = Vec::new();
+        for timed in self.events.by_ref() {
+            frame.push(timed);
+            if timed.event == AppEvent::RedrawRequested {
+                break;
+            }
+        }
+        frame
+    }
+}
+
+// Errors when `timed` is replayed in another frame than it was recorded in: the recording
+// was edited, or events went missing
+fn check_frame(timed: &TimedEvent, frame: u64) -> anyhow::Result<()> {
+    if timed.frame != frame {
+        anyhow::bail!(
+            "{:?} was recorded in frame {} but replays in frame {}",
+            timed.event,
+            timed.frame,
+            frame
+        );
+    }
+    Ok(())
+}
+
+impl App {
+    // Saves every event reaching the app to `path`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn record_to(&mut self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        self.recorder = Some(Recorder::create(path)?);
+        Ok(())
+    }
+
+    // Plays `recording` back instead of the user's input, which is ignored until the
+    // recording is over. The window can still be resized and closed.
+    pub fn replay(&mut self, recording: Recording) {
+        log::info!("Replaying {} frames", recording.frame_count());
+        self.replay = Some(Replay::new(recording));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn record(&mut self, event: AppEvent, time: Duration) {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
+        let timed = TimedEvent {
+            frame: self.frame,
+            time: time.as_secs_f64(),
+            event,
+        };
+        // A recording with a hole in it would replay differently, so stop there
+        if let Err(e) = recorder.record(timed) {
+            log::error!(
+                "Unable to record to {}, stopping: {}",
+                recorder.path().display(),
+                e
+            );
+            self.recorder = None;
+        }
+    }
+
+    // A window event received while replaying. Every frame of the window draws the next
+    // frame of the recording.
+    pub(crate) fn replay_window_event(&mut self, event: AppEvent, time: Duration) {
+        match event {
+            AppEvent::RedrawRequested => {}
+            // The window itself still changes
+            AppEvent::Resized { .. }
+            | AppEvent::ScaleFactorChanged { .. }
+            | AppEvent::CloseRequested => return self.process_event(event, time),
+            // The user's input is ignored
+            _ => return,
+        }
+
+        let events = match &mut self.replay {
+            Some(replay) => replay.next_frame(),
+            None => Vec::new(),
+        };
+        if events.is_empty() {
+            log::info!("Replay finished after {} frames", self.frame);
+            self.replay = None;
+            return self.process_event(event, time);
+        }
+        for timed in events {
+            if let Err(e) = check_frame(&timed, self.frame) {
+                log::error!("Stopping the replay: {}", e);
+                self.replay = None;
+                return self.process_event(event, time);
+            }
+            match timed.event {
+                // Ask for the recorded size, the Resized event follows if the window
+                // manager agrees
+                AppEvent::Resized { width, height } => {
+                    if let Some(window) = self.state.as_ref().and_then(State::window) {
+                        let _ = window.request_inner_size(PhysicalSize::new(width, height));
+                    }
+                }
+                event => self.process_event(event, Duration::from_secs_f64(timed.time)),
+            }
+        }
+    }
+
+    // Replays a recording without a window, frame for frame. Each frame is saved as
+    // frame-NNNNN.png in `output` if given. Returns the number of frames drawn.
+    // The default bindings are used, not those of bindings.toml, so a replay gives the
+    // same result wherever it runs.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn replay_headless(
+        recording: Recording,
+        output: Option<&std::path::Path>,
+    ) -> anyhow::Result<u64> {
+        // The Resized events of the recording take care of the size after that
+        let (width, height) = recording.initial_size().unwrap_or((800, 600));
+        let state = State::new_headless(width, height).await?;
+        Self::replay_headless_on(state, recording, output).await
+    }
+
+    // replay_headless with a headless State made by the caller (another adapter, scene...)
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn replay_headless_on(
+        state: State,
+        recording: Recording,
+        output: Option<&std::path::Path>,
+    ) -> anyhow::Result<u64> {
+        // Replays use the default bindings, whatever bindings.toml says
+        let mut app = Self::with_input_map(crate::input::InputMap::default());
+        app.set_state(state);
+        if let Some(output) = output {
+            std::fs::create_dir_all(output)?;
+        }
+
+        for timed in recording.events {
+            check_frame(&timed, app.frame)?;
+            app.process_event(timed.event, Duration::from_secs_f64(timed.time));
+            if let (AppEvent::RedrawRequested, Some(output), Some(state)) =
+                (timed.event, output, &mut app.state)
+            {
+                let path = output.join(format!("frame-{:05}.png", app.frame - 1));
+                state.save_screenshot(path).await?;
+            }
+            if app.exit_requested {
+                break;
+            }
+        }
+        match app.error.take() {
+            Some(error) => Err(error),
+            None => Ok(app.frame),
+        }
+    }
+
+    // Renders `frames` frames at 60 frames per second without a window, like a recording
+    // without any input
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn render_headless(
+        state: State,
+        frames: u64,
+        output: Option<&std::path::Path>,
+    ) -> anyhow::Result<u64> {
+        let events = (1..=frames)
+            .map(|frame| TimedEvent {
+                frame: frame - 1,
+                time: frame as f64 / 60.0,
+                event: AppEvent::RedrawRequested,
+            })
+            .collect();
+        Self::replay_headless_on(state, Recording { events }, output).await
+    }
+}
@@ -0,0 +1,178 @@
+// Recovering from a lost surface or device.
+//
+// get_current_texture fails when the surface can't give us a texture to draw into:
+// - Lost and Outdated: the surface changed under us (resized, moved to another GPU...) and
+//   has to be configured again
+// - Timeout: no texture became available in time, e.g. while the window is hidden. The
+//   frame is skipped, the next one will likely work.
+// - OutOfMemory: there is nothing sensible left to do, the app exits
+//
+// The device itself can be lost too: driver crash or update, GPU reset, a laptop switching
+// GPUs... Everything created from it (buffers, textures, pipelines, bind groups) is
+// unusable from then on. wgpu calls the device lost callback, and State is rebuilt on a new
+// device from what it kept around: the adapter, the surface configuration, the attachment
+// descriptors, the pipeline builders and a copy of the meshes and textures.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    State,
+    camera::CameraBuffer,
+    texture::{Texture, TexturedMesh},
+};
+
+// What to do with a frame that couldn't be drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SurfaceErrorAction {
+    // Configure the surface again and draw the next frame as usual
+    Reconfigure,
+    SkipFrame,
+    Exit,
+}
+
+pub fn surface_error_action(error: &wgpu::SurfaceError) -> SurfaceErrorAction {
+    match error {
+        wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated => SurfaceErrorAction::Reconfigure,
+        wgpu::SurfaceError::OutOfMemory => SurfaceErrorAction::Exit,
+        wgpu::SurfaceError::Timeout | wgpu::SurfaceError::Other => SurfaceErrorAction::SkipFrame,
+    }
+}
+
+// Returns a flag set by the device lost callback.
+// Every device gets its own flag, so the old device can't flag the one replacing it.
+pub(crate) fn watch_device_loss(device: &wgpu::Device) -> Arc<AtomicBool> {
+    let lost = Arc::new(AtomicBool::new(false));
+    let flag = lost.clone();
+    // The callback may run on another thread, all it can do is leave a note for State
+    device.set_device_lost_callback(move |reason, message| {
+        log::error!("Device lost ({:?}): {}", reason, message);
+        flag.store(true, Ordering::Release);
+    });
+    lost
+}
+
+impl State {
+    // Whether the device was lost. Nothing can be drawn until recreate_device is called.
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::Acquire)
+    }
+
+    // Reacts to a frame render() couldn't draw, the caller should exit on Exit
+    pub fn handle_surface_error(&mut self, error: &wgpu::SurfaceError) -> SurfaceErrorAction {
+        let action = surface_error_action(error);
+        match action {
+            SurfaceErrorAction::Reconfigure => {
+                log::warn!("{}, reconfiguring the surface", error);
+                let (width, height) = match &self.window {
+                    Some(window) => window.inner_size().into(),
+                    None => (self.config.width, self.config.height),
+                };
+                self.resize(width, height);
+            }
+            SurfaceErrorAction::SkipFrame => log::warn!("{}, skipping the frame", error),
+            SurfaceErrorAction::Exit => log::error!("{}, exiting", error),
+        }
+        action
+    }
+
+    // Replaces a lost device with a new one from the same adapter, and creates everything
+    // that lived on the old device again. The settings (camera, MSAA, depth format,
+    // present mode...) stay as they were. If it fails, State is left untouched.
+    pub async fn recreate_device(&mut self) -> anyhow::Result<()> {
+        let (device, queue) = Self::request_device(&self.adapter).await?;
+        let device_lost = watch_device_loss(&device);
+        self.move_to_device(device, queue, device_lost)?;
+        log::info!("Device recreated");
+        Ok(())
+    }
+
+    // Moves to the device of `other` after the device both shared was lost and `other`
+    // recreated it (see windows.rs)
+    pub fn share_device(&mut self, other: &State) -> anyhow::Result<()> {
+        self.move_to_device(
+            other.device.clone(),
+            other.queue.clone(),
+            other.device_lost.clone(),
+        )
+    }
+
+    fn move_to_device(
+        &mut self,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        device_lost: Arc<AtomicBool>,
+    ) -> anyhow::Result<()> {
+        // Textures are the only part that can fail, so they go first
+        let texture_bind_group_layout = Texture::bind_group_layout(&device);
+        let mut textured_meshes = Vec::with_capacity(self.textured_meshes.len());
+        for textured in &self.textured_meshes {
+            let Some(source) = &textured.texture else {
+                log::warn!(
+                    "Dropping a mesh whose texture wasn't made by Texture, it can't be uploaded again"
+                );
+                continue;
+            };
+            let texture = source.upload(&device, &queue)?;
+            textured_meshes.push(TexturedMesh {
+                mesh: textured.mesh.recreate(&device),
+                bind_group: texture.create_bind_group(&device, &texture_bind_group_layout),
+                texture: texture.source,
+            });
+        }
+
+        // The pipeline builders hold bind group layouts of the old device. The ones State
+        // made are replaced by the same layouts made on the new device.
+        let camera_buffer = CameraBuffer::new(&device, &self.camera);
+        let old_camera_layout = self.camera_buffer.bind_group_layout().clone();
+        let old_texture_layout = self.texture_bind_group_layout.clone();
+        let replace = |layout: &wgpu::BindGroupLayout| {
+            if *layout == old_camera_layout {
+                Some(camera_buffer.bind_group_layout().clone())
+            } else if *layout == old_texture_layout {
+                Some(texture_bind_group_layout.clone())
+            } else {
+                None
+            }
+        };
+        if !self
+            .render_pipeline_builder
+            .replace_bind_group_layouts(replace)
+        {
+            log::warn!(
+                "The render pipeline uses bind group layouts made outside of State, going back to the default pipeline"
+            );
+            self.render_pipeline_builder =
+                crate::default_render_pipeline_builder(&camera_buffer, self.camera.reversed_z);
+        }
+        self.textured_pipeline_builder
+            .replace_bind_group_layouts(replace);
+
+        if let Some(surface) = &self.surface
+            && self.is_surface_configured
+        {
+            surface.configure(&device, &self.config);
+        }
+        if let Some(offscreen) = &mut self.offscreen {
+            offscreen.resize(&device, &self.config);
+        }
+        for attachment in self.attachments.iter_mut().flatten() {
+            attachment.resize(&device, self.config.width, self.config.height);
+        }
+        self.meshes = self
+            .meshes
+            .iter()
+            .map(|mesh| mesh.recreate(&device))
+            .collect();
+        self.textured_meshes = textured_meshes;
+        self.texture_bind_group_layout = texture_bind_group_layout;
+        self.camera_buffer = camera_buffer;
+        self.device_lost = device_lost;
+        self.device = device;
+        self.queue = queue;
+        self.rebuild_pipelines();
+        Ok(())
+    }
+}
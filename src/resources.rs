@@ -0,0 +1,67 @@
+// Loading asset files.
+// On native the files are read from the res/ folder next to the working directory.
+// On the web there is no file system, so the files are fetched from the server hosting
+// the page instead (e.g. http://localhost:8080/res/happy-tree.png).
+// Assets that should always be available can also be embedded with include_bytes! and
+// passed straight to Texture::from_bytes.
+
+use crate::{
+    compressed::{self, CompressionFamily},
+    texture::{Texture, TextureOptions},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
+    let path = std::path::Path::new("res").join(file_name);
+    std::fs::read(&path).map_err(|e| anyhow::anyhow!("Unable to read {}: {}", path.display(), e))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    // JsValue errors don't implement std::error::Error, so they are turned into strings
+    let js_error = |e: wasm_bindgen::JsValue| anyhow::anyhow!("{:?}", e);
+
+    let window = web_sys::window().ok_or_else(|| anyhow::anyhow!("No browser window"))?;
+    let url = format!("res/{}", file_name);
+    // fetch returns a Promise, JsFuture lets us await it like a Rust future
+    let response: web_sys::Response = JsFuture::from(window.fetch_with_str(&url))
+        .await
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(js_error)?;
+    if !response.ok() {
+        anyhow::bail!("Unable to fetch {}: HTTP {}", url, response.status());
+    }
+    let buffer = JsFuture::from(response.array_buffer().map_err(js_error)?)
+        .await
+        .map_err(js_error)?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+// Loads and uploads an image file from the res/ folder
+pub async fn load_texture(
+    file_name: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    options: TextureOptions,
+) -> anyhow::Result<Texture> {
+    let data = load_binary(file_name).await?;
+    Texture::from_bytes(device, queue, &data, file_name, options)
+}
+
+// Loads the variant of a texture compressed for the best family the device supports,
+// or `fallback` (e.g. a PNG) when there is none:
+// load_texture_variant(&[(Bc, "tree.bc7.ktx2"), (Astc, "tree.astc.ktx2")], "tree.png", ...)
+pub async fn load_texture_variant(
+    variants: &[(CompressionFamily, &str)],
+    fallback: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    options: TextureOptions,
+) -> anyhow::Result<Texture> {
+    let file_name = compressed::select(device.features(), variants).unwrap_or(&fallback);
+    load_texture(file_name, device, queue, options).await
+}
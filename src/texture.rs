@@ -0,0 +1,377 @@
+// Textures loaded from images.
+// A Texture bundles the wgpu texture with a view and a sampler, which is everything
+// needed to create a bind group so a shader can sample from it.
+
+use std::sync::Arc;
+
+use image::GenericImageView;
+
+use crate::{
+    State,
+    compressed::{self, CompressedImage},
+    mesh::{Mesh, TexturedVertex},
+    mipmap::{self, MipmapMode},
+};
+
+// How the texel values stored in the image should be interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    // Colors meant to be looked at (albedo, UI...). The GPU converts them to linear
+    // values when sampling, so the shader always works with linear colors.
+    Srgb,
+    // Data that is not a color (normal maps, roughness...), used as is
+    Linear,
+}
+
+// How the sampler reads the texture
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerOptions {
+    // What to do when a texel covers several pixels (magnified) or the other way around
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    // How to blend between mip levels
+    pub mipmap_filter: wgpu::FilterMode,
+    // What happens with texture coordinates outside of 0..1
+    pub address_mode: wgpu::AddressMode,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            address_mode: wgpu::AddressMode::ClampToEdge,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureOptions {
+    pub color_space: ColorSpace,
+    pub sampler: SamplerOptions,
+    // Whether (and where) the mip chain is generated. Set sampler.mipmap_filter to Linear
+    // for smooth transitions between the levels.
+    pub mipmaps: MipmapMode,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            color_space: ColorSpace::Srgb,
+            sampler: SamplerOptions::default(),
+            mipmaps: MipmapMode::None,
+        }
+    }
+}
+
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    // What the texture was made from, None when made with from_texture
+    pub(crate) source: Option<TextureSource>,
+}
+
+// Textures die with their device, so what they were made from is kept to upload them again
+// if the device is lost (see recovery.rs)
+#[derive(Clone)]
+pub(crate) enum TextureSource {
+    Image {
+        image: Arc<image::DynamicImage>,
+        label: String,
+        options: TextureOptions,
+    },
+    Compressed {
+        image: Arc<CompressedImage>,
+        label: String,
+        sampler: SamplerOptions,
+    },
+}
+
+impl TextureSource {
+    // Creates the texture again on `device`
+    pub(crate) fn upload(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> anyhow::Result<Texture> {
+        let mut texture = match self {
+            Self::Image {
+                image,
+                label,
+                options,
+            } => Texture::upload_image(device, queue, image, label, *options),
+            Self::Compressed {
+                image,
+                label,
+                sampler,
+            } => Texture::upload_compressed(device, queue, image, label, *sampler)?,
+        };
+        texture.source = Some(self.clone());
+        Ok(texture)
+    }
+}
+
+// A mesh drawn with the textured pipeline, and the bind group of its texture
+pub(crate) struct TexturedMesh {
+    pub(crate) mesh: Mesh,
+    pub(crate) bind_group: wgpu::BindGroup,
+    pub(crate) texture: Option<TextureSource>,
+}
+
+impl Texture {
+    // Decodes an encoded image (PNG, JPEG) and uploads it to the GPU.
+    // KTX2 and DDS files are uploaded with their own format and mip levels instead,
+    // see compressed.rs.
+    pub fn from_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+        options: TextureOptions,
+    ) -> anyhow::Result<Self> {
+        if compressed::is_container(bytes) {
+            let image =
+                CompressedImage::from_bytes_for(bytes, options.color_space, device.features())?;
+            return Self::from_compressed(device, queue, &image, label, options.sampler);
+        }
+        let img = image::load_from_memory(bytes)?;
+        Ok(Self::from_image(device, queue, &img, label, options))
+    }
+
+    // Reads an image file from disk. On the web, use resources::load_texture instead.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_path(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<std::path::Path>,
+        options: TextureOptions,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("Unable to read {}: {}", path.display(), e))?;
+        Self::from_bytes(device, queue, &bytes, &path.to_string_lossy(), options)
+    }
+
+    pub fn from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: &str,
+        options: TextureOptions,
+    ) -> Self {
+        let mut texture = Self::upload_image(device, queue, img, label, options);
+        texture.source = Some(TextureSource::Image {
+            image: Arc::new(img.clone()),
+            label: label.to_string(),
+            options,
+        });
+        texture
+    }
+
+    fn upload_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: &str,
+        options: TextureOptions,
+    ) -> Self {
+        // Images can have all kinds of pixel formats, the GPU texture is always RGBA8
+        let rgba = img.to_rgba8();
+        let dimensions = img.dimensions();
+
+        let size = wgpu::Extent3d {
+            width: dimensions.0,
+            height: dimensions.1,
+            // All textures are stored as 3D, a 2D texture has a depth of 1
+            depth_or_array_layers: 1,
+        };
+        let format = match options.color_space {
+            ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+        };
+        let mip_level_count = match options.mipmaps {
+            MipmapMode::None => 1,
+            MipmapMode::Gpu | MipmapMode::Cpu => mipmap::mip_level_count(size.width, size.height),
+        };
+        // TEXTURE_BINDING lets shaders sample it, COPY_DST lets us copy the pixels into it
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        if options.mipmaps == MipmapMode::Gpu {
+            // The GPU generates the levels by rendering into them
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        });
+
+        write_level(queue, &texture, 0, &rgba);
+        match options.mipmaps {
+            MipmapMode::None => {}
+            MipmapMode::Gpu => mipmap::generate_gpu(device, queue, &texture),
+            MipmapMode::Cpu => {
+                for (level, img) in mipmap::generate_cpu(&rgba, options.color_space)
+                    .iter()
+                    .enumerate()
+                {
+                    write_level(queue, &texture, level as u32 + 1, img);
+                }
+            }
+        }
+
+        Self::from_texture(device, texture, options.sampler)
+    }
+
+    // Creates the view and sampler of an already uploaded texture
+    pub fn from_texture(
+        device: &wgpu::Device,
+        texture: wgpu::Texture,
+        sampler: SamplerOptions,
+    ) -> Self {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: sampler.address_mode,
+            address_mode_v: sampler.address_mode,
+            address_mode_w: sampler.address_mode,
+            mag_filter: sampler.mag_filter,
+            min_filter: sampler.min_filter,
+            mipmap_filter: sampler.mipmap_filter,
+            ..Default::default()
+        });
+        Self {
+            texture,
+            view,
+            sampler,
+            source: None,
+        }
+    }
+
+    // Layout of the bind groups created by create_bind_group:
+    // @binding(0) is the texture and @binding(1) the sampler, both used in the fragment shader
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Texture Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    // Filtering must match the filterable sample type of the texture
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    // A bind group is the set of resources a shader actually gets, matching `layout`
+    pub fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Texture Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        })
+    }
+}
+
+impl State {
+    // Decodes and uploads an image (or KTX2/DDS file) with State's device and queue
+    pub fn create_texture(
+        &self,
+        bytes: &[u8],
+        label: &str,
+        options: TextureOptions,
+    ) -> anyhow::Result<Texture> {
+        Texture::from_bytes(&self.device, &self.queue, bytes, label, options)
+    }
+
+    // Layout of @group(1) in the textured pipeline (the camera is at @group(0))
+    pub fn texture_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.texture_bind_group_layout
+    }
+
+    // Adds a mesh made of mesh::TexturedVertex drawn with `texture` every frame
+    pub fn add_textured_mesh(&mut self, mesh: Mesh, texture: &Texture) {
+        let bind_group = texture.create_bind_group(&self.device, &self.texture_bind_group_layout);
+        self.textured_meshes.push(TexturedMesh {
+            mesh,
+            bind_group,
+            texture: texture.source.clone(),
+        });
+    }
+
+    // Replaces the scene with `bytes` (an image, KTX2 or DDS file) on a quad covering the
+    // view of the default camera
+    pub fn show_image(&mut self, bytes: &[u8], label: &str) -> anyhow::Result<()> {
+        let texture = self.create_texture(bytes, label, TextureOptions::default())?;
+        let vertices = [
+            ([-0.5, -0.5], [0.0, 1.0]),
+            ([0.5, -0.5], [1.0, 1.0]),
+            ([0.5, 0.5], [1.0, 0.0]),
+            ([-0.5, 0.5], [0.0, 0.0]),
+        ]
+        .map(|([x, y], tex_coords)| TexturedVertex {
+            position: [x, y, 0.0],
+            tex_coords,
+        });
+        let quad = Mesh::new_indexed(&self.device, label, &vertices, &[0u16, 1, 2, 0, 2, 3]);
+        self.clear_meshes();
+        self.add_textured_mesh(quad, &texture);
+        Ok(())
+    }
+}
+
+// Copies the pixels of `img` into mip level `level` of an RGBA8 texture
+fn write_level(queue: &wgpu::Queue, texture: &wgpu::Texture, level: u32, img: &image::RgbaImage) {
+    let (width, height) = img.dimensions();
+    queue.write_texture(
+        // Where to copy the pixel data
+        wgpu::TexelCopyTextureInfo {
+            texture,
+            mip_level: level,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        img,
+        // The layout of the pixels in memory
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * width),
+            rows_per_image: Some(height),
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
@@ -0,0 +1,120 @@
+// Simulation and rendering run at different rates.
+// The simulation (camera controllers, and anything moving in the scene) is updated in
+// fixed steps, e.g. 60 times per second, whatever the frame rate: the same input gives
+// the same result on a 30 Hz laptop and a 144 Hz monitor. Every frame adds its duration
+// to an accumulator and runs as many steps as fit in it. What is left is less than a step;
+// its fraction of a step (alpha) is used to draw the scene between the last two updates,
+// so movement stays smooth when the frame rate isn't a multiple of the update rate.
+//
+// The frame rate itself is either what the present mode allows (vsync or as fast as
+// possible), or capped by a FrameLimiter which makes the event loop wait between frames.
+
+use std::time::Duration;
+
+use web_time::Instant;
+
+use crate::State;
+
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    // Duration of one update
+    step: Duration,
+    // Most updates run for one frame. After a long frame (a breakpoint, dragging the
+    // window...) catching up on everything would make the next frame even longer, so the
+    // time beyond that is dropped and the simulation slows down instead.
+    max_steps: u32,
+    accumulator: Duration,
+}
+
+impl FixedTimestep {
+    pub fn new(step: Duration, max_steps: u32) -> Self {
+        Self {
+            step: step.max(Duration::from_micros(1)),
+            max_steps: max_steps.max(1),
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    // `rate` updates per second
+    pub fn from_rate(rate: f64, max_steps: u32) -> anyhow::Result<Self> {
+        Ok(Self::new(interval(rate, "update rate")?, max_steps))
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    // Adds the duration of a frame and returns how many updates to run
+    pub fn advance(&mut self, frame_time: Duration) -> u32 {
+        self.accumulator += frame_time;
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            if steps == self.max_steps {
+                self.accumulator = Duration::ZERO;
+                break;
+            }
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        steps
+    }
+
+    // How far the time is between the last update and the next, from 0 to 1
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
+
+impl Default for FixedTimestep {
+    // 60 updates per second
+    fn default() -> Self {
+        Self::new(Duration::from_secs_f64(1.0 / 60.0), 5)
+    }
+}
+
+// The time between two events happening `rate` times per second. 0, negative, infinite
+// and NaN rates are errors, as are rates so low the time doesn't fit in a Duration.
+fn interval(rate: f64, name: &str) -> anyhow::Result<Duration> {
+    if !(rate.is_finite() && rate > 0.0) {
+        anyhow::bail!("Invalid {} {}, it has to be a positive number", name, rate);
+    }
+    Duration::try_from_secs_f64(1.0 / rate)
+        .map_err(|_| anyhow::anyhow!("Invalid {} {}, it is too low", name, rate))
+}
+
+// Spaces frames at least 1 / max_fps apart
+#[derive(Debug, Clone)]
+pub struct FrameLimiter {
+    interval: Duration,
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    pub fn new(max_fps: f64) -> anyhow::Result<Self> {
+        Ok(Self {
+            interval: interval(max_fps, "frame rate limit")?,
+            next_frame: Instant::now(),
+        })
+    }
+
+    // Called when a frame starts, returns when the next one may start.
+    // A late frame doesn't make the following ones come faster to catch up: the interval
+    // counts from when it actually started.
+    pub fn frame_started(&mut self, now: Instant) -> Instant {
+        self.next_frame = self.next_frame.max(now) + self.interval;
+        self.next_frame
+    }
+
+    pub fn next_frame(&self) -> Instant {
+        self.next_frame
+    }
+}
+
+impl State {
+    // Advances the simulation by one fixed step
+    pub fn update(&mut self, dt: Duration) {
+        // Kept to draw the camera between the last two updates
+        self.previous_camera = self.camera;
+        self.update_camera(dt);
+    }
+}
@@ -0,0 +1,194 @@
+// How the main window looks: title, size, decorations, icon and fullscreen mode.
+// App::set_window_config changes it before the window is created.
+//
+// Fullscreen comes in two flavors:
+// - Borderless: a window covering the whole monitor, at the resolution of the desktop.
+//   Switching to it is instant and other windows can still show on top.
+// - Exclusive: the monitor switches to a video mode (resolution, refresh rate, bit depth)
+//   and the window owns it. Not every platform has it (Wayland and the web don't), winit
+//   ignores it there.
+//
+// The toggle_fullscreen action (F11 and Alt+Enter by default) switches the main window
+// between fullscreen and windowed, see App::process_actions.
+
+use winit::{
+    dpi::LogicalSize,
+    event_loop::ActiveEventLoop,
+    monitor::{MonitorHandle, VideoModeHandle},
+    window::{Fullscreen, Icon, Window, WindowAttributes},
+};
+
+use crate::App;
+
+#[derive(Debug, Clone)]
+pub struct WindowConfig {
+    pub title: String,
+    // In logical pixels, None lets the platform decide
+    pub size: Option<(u32, u32)>,
+    pub min_size: Option<(u32, u32)>,
+    pub resizable: bool,
+    // Title bar and borders
+    pub decorations: bool,
+    pub icon: Option<Icon>,
+    // Used from the start with start_fullscreen, and by the toggle_fullscreen action
+    pub fullscreen: FullscreenMode,
+    pub start_fullscreen: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            title: "Learn WGPU".to_string(),
+            size: None,
+            min_size: None,
+            resizable: true,
+            decorations: true,
+            icon: None,
+            fullscreen: FullscreenMode::Borderless,
+            start_fullscreen: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FullscreenMode {
+    Borderless,
+    Exclusive(VideoModeRequest),
+}
+
+// The video mode wanted in exclusive fullscreen. The monitor picks the closest one it has.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VideoModeRequest {
+    // In physical pixels, None for the largest
+    pub size: Option<(u32, u32)>,
+    // In millihertz like winit reports it (59940 for 59.94 Hz), None for the highest
+    pub refresh_rate_millihertz: Option<u32>,
+}
+
+// What VideoModeRequest looks at in a video mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoModeInfo {
+    pub size: (u32, u32),
+    pub refresh_rate_millihertz: u32,
+    pub bit_depth: u16,
+}
+
+impl From<&VideoModeHandle> for VideoModeInfo {
+    fn from(mode: &VideoModeHandle) -> Self {
+        Self {
+            size: mode.size().into(),
+            refresh_rate_millihertz: mode.refresh_rate_millihertz(),
+            bit_depth: mode.bit_depth(),
+        }
+    }
+}
+
+impl VideoModeRequest {
+    // The index of the mode closest to the request: the size first, then the refresh rate,
+    // then the highest bit depth. None without any mode.
+    pub fn best_match(&self, modes: &[VideoModeInfo]) -> Option<usize> {
+        let size_distance = |mode: &VideoModeInfo| match self.size {
+            Some((width, height)) => {
+                mode.size.0.abs_diff(width) as u64 + mode.size.1.abs_diff(height) as u64
+            }
+            // The largest mode is at distance 0
+            None => u64::MAX - mode.size.0 as u64 * mode.size.1 as u64,
+        };
+        let refresh_distance = |mode: &VideoModeInfo| match self.refresh_rate_millihertz {
+            Some(rate) => mode.refresh_rate_millihertz.abs_diff(rate),
+            None => u32::MAX - mode.refresh_rate_millihertz,
+        };
+        modes
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, mode)| {
+                (
+                    size_distance(mode),
+                    refresh_distance(mode),
+                    u16::MAX - mode.bit_depth,
+                )
+            })
+            .map(|(index, _)| index)
+    }
+
+    // The video mode of `monitor` closest to the request
+    pub fn select(&self, monitor: &MonitorHandle) -> Option<VideoModeHandle> {
+        let modes: Vec<_> = monitor.video_modes().collect();
+        let infos: Vec<_> = modes.iter().map(VideoModeInfo::from).collect();
+        let index = self.best_match(&infos)?;
+        modes.into_iter().nth(index)
+    }
+}
+
+impl FullscreenMode {
+    // The winit fullscreen for `monitor`, the one the window is on (None lets winit pick
+    // it). Exclusive goes back to borderless without a monitor or a video mode to use.
+    pub fn fullscreen(&self, monitor: Option<MonitorHandle>) -> Fullscreen {
+        match self {
+            Self::Borderless => Fullscreen::Borderless(monitor),
+            Self::Exclusive(request) => {
+                match monitor.as_ref().and_then(|monitor| request.select(monitor)) {
+                    Some(mode) => {
+                        log::info!("Exclusive fullscreen in {}", mode);
+                        Fullscreen::Exclusive(mode)
+                    }
+                    None => {
+                        log::warn!("No video mode for exclusive fullscreen, going borderless");
+                        Fullscreen::Borderless(monitor)
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl WindowConfig {
+    // Decodes an image file (PNG) into the window icon
+    pub fn with_icon_image(mut self, bytes: &[u8]) -> anyhow::Result<Self> {
+        let image = image::load_from_memory(bytes)?.to_rgba8();
+        let (width, height) = image.dimensions();
+        self.icon = Some(Icon::from_rgba(image.into_raw(), width, height)?);
+        Ok(self)
+    }
+
+    // The attributes of the main window. Exclusive fullscreen starts on the primary monitor.
+    pub fn attributes(&self, event_loop: &ActiveEventLoop) -> WindowAttributes {
+        let mut attributes = Window::default_attributes()
+            .with_title(self.title.clone())
+            .with_resizable(self.resizable)
+            .with_decorations(self.decorations)
+            .with_window_icon(self.icon.clone());
+        if let Some((width, height)) = self.size {
+            attributes = attributes.with_inner_size(LogicalSize::new(width, height));
+        }
+        if let Some((width, height)) = self.min_size {
+            attributes = attributes.with_min_inner_size(LogicalSize::new(width, height));
+        }
+        if self.start_fullscreen {
+            let monitor = event_loop
+                .primary_monitor()
+                .or_else(|| event_loop.available_monitors().next());
+            attributes = attributes.with_fullscreen(Some(self.fullscreen.fullscreen(monitor)));
+        }
+        attributes
+    }
+
+    // Switches `window` between fullscreen and windowed
+    pub fn toggle_fullscreen(&self, window: &Window) {
+        if window.fullscreen().is_some() {
+            log::info!("Windowed");
+            window.set_fullscreen(None);
+        } else {
+            log::info!("Fullscreen");
+            let monitor = window.current_monitor();
+            window.set_fullscreen(Some(self.fullscreen.fullscreen(monitor)));
+        }
+    }
+}
+
+impl App {
+    // Changes how the main window is created, has no effect once it exists
+    pub fn set_window_config(&mut self, config: WindowConfig) {
+        self.window_config = config;
+    }
+}
@@ -0,0 +1,189 @@
+// More than one window.
+// The main window is the one App was started with: it owns the input map, the recording
+// and the exit action, and closing it exits the app. Other windows (a tool window next to
+// the main view...) can be opened at runtime with App::open_window. Each one gets a State
+// of its own, with its own surface, configuration, camera and meshes, but all of them
+// draw with the device and queue of the main window. Closing one of them only closes it.
+//
+// winit tells which window an event is for with its WindowId, which is also how App finds
+// the State of a window. The events of the other windows are neither recorded nor
+// replayed.
+//
+// A device has a single device lost callback, so the windows share the flag of the main
+// window. The main window recreates the device after a loss, and the other windows move to
+// the new one (see recovery.rs).
+
+use std::sync::Arc;
+
+use winit::{
+    dpi::LogicalSize,
+    event::WindowEvent,
+    event_loop::ActiveEventLoop,
+    window::{Window, WindowAttributes, WindowId},
+};
+
+use crate::{
+    App, InitError, State, controller::OrbitController, recording::AppEvent,
+    recovery::SurfaceErrorAction,
+};
+
+impl State {
+    // Creates a State drawing into `window` with the device and queue of `other`.
+    // `other` has to draw into a window too, since the new surface is created from the same
+    // instance and has to be supported by the same adapter.
+    pub fn new_sharing(window: Arc<Window>, other: &State) -> Result<Self, InitError> {
+        let Some(instance) = other.instance.clone() else {
+            return Err(InitError::SharingHeadless);
+        };
+        let surface = instance.create_surface(window.clone())?;
+        if !other.adapter.is_surface_supported(&surface) {
+            return Err(InitError::UnsupportedSurface(other.adapter.get_info().name));
+        }
+        let mut state = Self::from_surface(
+            instance,
+            surface,
+            other.adapter.clone(),
+            other.device.clone(),
+            other.queue.clone(),
+            window,
+        )?;
+        state.device_lost = other.device_lost.clone();
+        Ok(state)
+    }
+}
+
+// The attributes of the window opened by the open_window action
+pub fn tool_window_attributes() -> WindowAttributes {
+    Window::default_attributes()
+        .with_title("Tool window")
+        .with_inner_size(LogicalSize::new(400.0, 300.0))
+}
+
+impl App {
+    // Opens a window next to the main one. Windows can only be created by the event loop,
+    // so it appears at the end of the current event loop iteration.
+    pub fn open_window(&mut self, attributes: WindowAttributes) {
+        self.pending_windows.push(attributes);
+    }
+
+    // Closes a window opened with open_window. Returns false for the main window and
+    // unknown ids, use the exit action to close the main window.
+    pub fn close_window(&mut self, id: WindowId) -> bool {
+        // Dropping the State drops the window, which closes it
+        self.windows.remove(&id).is_some()
+    }
+
+    // The windows opened with open_window, without the main window
+    pub fn window_ids(&self) -> impl Iterator<Item = WindowId> + '_ {
+        self.windows.keys().copied()
+    }
+
+    // Creates the windows asked for since the last call
+    pub(crate) fn create_pending_windows(&mut self, event_loop: &ActiveEventLoop) {
+        // The windows share the device of the main window, which has to exist first
+        let Some(main) = &self.state else {
+            return;
+        };
+        for attributes in std::mem::take(&mut self.pending_windows) {
+            // On the web, a window is a canvas. There is no canvas for it in the page, so
+            // winit creates one and appends it to the body.
+            #[cfg(target_arch = "wasm32")]
+            let attributes = {
+                use winit::platform::web::WindowAttributesExtWebSys;
+                attributes.with_append(true)
+            };
+            let window = match event_loop.create_window(attributes) {
+                Ok(window) => Arc::new(window),
+                Err(e) => {
+                    log::error!("Unable to open a window: {}", e);
+                    continue;
+                }
+            };
+            match State::new_sharing(window.clone(), main) {
+                Ok(mut state) => {
+                    state.set_camera_controller(Some(Box::new(OrbitController::default())));
+                    log::info!("Opened window {:?}", window.id());
+                    window.request_redraw();
+                    self.windows.insert(window.id(), state);
+                }
+                Err(e) => log::error!("Unable to draw into the new window: {}", e),
+            }
+        }
+    }
+
+    // Everything the app does with an event of a window opened with open_window
+    pub(crate) fn other_window_event(&mut self, id: WindowId, event: WindowEvent) {
+        let Some(state) = self.windows.get_mut(&id) else {
+            return;
+        };
+        match event {
+            WindowEvent::CloseRequested => {
+                self.close_window(id);
+            }
+            WindowEvent::Resized(size) => state.resize(size.width, size.height),
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                state.scale_factor_changed(scale_factor)
+            }
+            WindowEvent::RedrawRequested => {
+                // The main window recreates the device at its next frame
+                if state.is_device_lost() {
+                    return;
+                }
+                // The simulation steps ran in the frame of the main window
+                if let Err(e) = state.render_interpolated(self.timestep.alpha())
+                    && state.handle_surface_error(&e) == SurfaceErrorAction::Exit
+                {
+                    log::error!("Closing window {:?}, unable to render: {}", id, e);
+                    self.close_window(id);
+                    return;
+                }
+                if self.frame_limiter.is_none()
+                    && let Some(window) = state.window()
+                {
+                    window.request_redraw();
+                }
+            }
+            // The input of the window only drives its own camera
+            event => {
+                if let Some(event) = AppEvent::from_window_event(&event) {
+                    state.process_event(&event);
+                }
+            }
+        }
+    }
+
+    // Whether `id` is the main window. Events for windows that were just closed can still
+    // arrive, they belong to no window.
+    pub(crate) fn is_main_window(&self, id: WindowId) -> bool {
+        match &self.state {
+            Some(state) => state.window().is_some_and(|window| window.id() == id),
+            // On the web the main State is away while the device is recreated, its events
+            // are dropped anyway
+            None => !self.windows.contains_key(&id),
+        }
+    }
+
+    // Moves the other windows to the device of the main window after it was recreated.
+    // A window that can't move is closed.
+    pub(crate) fn share_main_device(&mut self) {
+        let Some(main) = &self.state else {
+            return;
+        };
+        self.windows.retain(|id, state| {
+            if state.device() == main.device() {
+                return true;
+            }
+            match state.share_device(main) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::error!(
+                        "Closing window {:?}, unable to move it to the new device: {}",
+                        id,
+                        e
+                    );
+                    false
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,82 @@
+// Picking an adapter by index or name, and the adapter reports of --list-adapters
+
+use learn_wgpu::adapter::{AdapterOptions, AdapterReport, AdapterSelector, vendor_name};
+
+fn report(index: usize, name: &str, vendor: u32) -> AdapterReport {
+    AdapterReport {
+        index,
+        info: wgpu::AdapterInfo {
+            name: name.to_string(),
+            vendor,
+            device: 0x2684,
+            device_type: wgpu::DeviceType::DiscreteGpu,
+            driver: "NVIDIA".to_string(),
+            driver_info: "550.54".to_string(),
+            backend: wgpu::Backend::Vulkan,
+        },
+        features: wgpu::Features::DEPTH_CLIP_CONTROL | wgpu::Features::TEXTURE_COMPRESSION_BC,
+        limits: wgpu::Limits::default(),
+    }
+}
+
+#[test]
+fn adapters_are_selected_by_index_or_name() {
+    let reports = [
+        report(0, "Intel(R) UHD Graphics 630", 0x8086),
+        report(1, "NVIDIA GeForce RTX 4090", 0x10de),
+        report(2, "llvmpipe (LLVM 15.0.6, 256 bits)", 0x10005),
+    ];
+    let select = |selector: &str| {
+        AdapterOptions {
+            adapter: Some(selector.parse().unwrap()),
+            ..Default::default()
+        }
+        .selected_index(&reports)
+    };
+    assert_eq!(select("1"), Some(1));
+    assert_eq!(select("geforce"), Some(1));
+    assert_eq!(select("LLVMPIPE"), Some(2));
+    // The first match wins
+    assert_eq!(select("i"), Some(0));
+    assert_eq!(select("3"), None);
+    assert_eq!(select("radeon"), None);
+    assert_eq!(AdapterOptions::default().selected_index(&reports), None);
+
+    assert_eq!("7".parse(), Ok(AdapterSelector::Index(7)));
+    assert_eq!(
+        AdapterSelector::Name("rtx".to_string()).to_string(),
+        "\"rtx\""
+    );
+}
+
+#[test]
+fn report_lists_the_adapter_details() {
+    let text = report(1, "NVIDIA GeForce RTX 4090", 0x10de).to_string();
+    for expected in [
+        "#1 NVIDIA GeForce RTX 4090",
+        "Vendor: NVIDIA (0x10de), device 0x2684",
+        "Type: DiscreteGpu",
+        "Backend: Vulkan",
+        "Driver: NVIDIA 550.54",
+        "Features: DEPTH_CLIP_CONTROL, TEXTURE_COMPRESSION_BC",
+        "max_texture_dimension_2d: 8192",
+    ] {
+        assert!(
+            text.contains(expected),
+            "{} missing from\n{}",
+            expected,
+            text
+        );
+    }
+    assert_eq!(vendor_name(0x1234), None);
+}
+
+// Whatever adapters this machine has, they are listed in order
+#[test]
+fn adapters_of_this_machine_are_listed() {
+    let reports = AdapterOptions::default().list_adapters();
+    for (index, report) in reports.iter().enumerate() {
+        assert_eq!(report.index, index);
+        assert!(report.to_string().starts_with(&format!("#{} ", index)));
+    }
+}
@@ -0,0 +1,61 @@
+// Depth conventions of the camera projections. What the camera sees is covered by the
+// golden tests.
+
+use cgmath::{Point3, Vector4};
+use learn_wgpu::camera::{Camera, Projection};
+
+// Depth after the perspective divide of a point `distance` units in front of the camera
+fn depth(camera: &Camera, distance: f32) -> f32 {
+    let clip = camera.build_view_projection_matrix() * Vector4::new(0.0, 0.0, -distance, 1.0);
+    clip.z / clip.w
+}
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!(
+        (actual - expected).abs() < 1e-5,
+        "{} != {}",
+        actual,
+        expected
+    );
+}
+
+fn cameras() -> [Camera; 2] {
+    let eye = Point3::new(0.0, 0.0, 0.0);
+    let target = Point3::new(0.0, 0.0, -1.0);
+    [
+        Camera::perspective(eye, target, 1.5),
+        Camera::orthographic(eye, target, 1.5, 2.0),
+    ]
+}
+
+fn planes(camera: &Camera) -> (f32, f32) {
+    match camera.projection {
+        Projection::Perspective { znear, zfar, .. }
+        | Projection::Orthographic { znear, zfar, .. } => (znear, zfar),
+    }
+}
+
+#[test]
+fn depth_goes_from_0_at_near_to_1_at_far() {
+    for camera in cameras() {
+        let (near, far) = planes(&camera);
+        assert_close(depth(&camera, near), 0.0);
+        assert_close(depth(&camera, far), 1.0);
+        // Closer is smaller
+        assert!(depth(&camera, 1.0) < depth(&camera, 2.0));
+    }
+}
+
+#[test]
+fn reversed_z_goes_from_1_at_near_to_0_at_far() {
+    for camera in cameras() {
+        let camera = Camera {
+            reversed_z: true,
+            ..camera
+        };
+        let (near, far) = planes(&camera);
+        assert_close(depth(&camera, near), 1.0);
+        assert_close(depth(&camera, far), 0.0);
+        assert!(depth(&camera, 1.0) > depth(&camera, 2.0));
+    }
+}
@@ -0,0 +1,18 @@
+// Capturing frames with capture_frame
+
+mod harness;
+
+#[test]
+fn nothing_is_captured_at_zero_size() {
+    let Some(mut state) = harness::headless_state(8, 8) else {
+        return;
+    };
+    // What a minimized window reports
+    state.resize(0, 0);
+    assert!(pollster::block_on(state.capture_frame()).is_err());
+
+    // Back to a real size, capturing works again
+    state.resize(8, 4);
+    let frame = pollster::block_on(state.capture_frame()).unwrap();
+    assert_eq!(frame.dimensions(), (8, 4));
+}
@@ -0,0 +1,84 @@
+// Parsing the command line of the learn_wgpu binary
+
+use std::path::PathBuf;
+
+use learn_wgpu::adapter::AdapterSelector;
+use learn_wgpu::cli::{DisplayServer, Options};
+
+fn parse(args: &str) -> anyhow::Result<Options> {
+    Options::parse(args.split_whitespace())
+}
+
+#[test]
+fn options_are_parsed() {
+    let options = parse(
+        "--backend vulkan,gl --adapter nvidia --power high --present-mode mailbox \
+         --size=1280x720 --scene res/tree.png --x11",
+    )
+    .unwrap();
+    assert_eq!(
+        options.adapter.backends,
+        Some(wgpu::Backends::VULKAN | wgpu::Backends::GL)
+    );
+    assert_eq!(
+        options.adapter.adapter,
+        Some(AdapterSelector::Name("nvidia".to_string()))
+    );
+    assert_eq!(
+        options.adapter.power_preference,
+        wgpu::PowerPreference::HighPerformance
+    );
+    assert_eq!(options.present_mode, Some(wgpu::PresentMode::Mailbox));
+    assert_eq!(options.size, Some((1280, 720)));
+    assert_eq!(options.scene, Some(PathBuf::from("res/tree.png")));
+    assert_eq!(options.display_server, Some(DisplayServer::X11));
+    assert!(!options.headless);
+
+    let options = parse("--headless --frames 10 --output out --adapter 1").unwrap();
+    assert!(options.headless);
+    assert_eq!(options.headless_frames(), 10);
+    assert_eq!(options.output, Some(PathBuf::from("out")));
+    assert_eq!(options.adapter.adapter, Some(AdapterSelector::Index(1)));
+
+    // wgpu's aliases work too
+    assert_eq!(
+        parse("--backend VK,d3d12,mtl,gles")
+            .unwrap()
+            .adapter
+            .backends,
+        Some(
+            wgpu::Backends::VULKAN
+                | wgpu::Backends::DX12
+                | wgpu::Backends::METAL
+                | wgpu::Backends::GL
+        )
+    );
+
+    assert_eq!(parse("").unwrap(), Options::default());
+    assert_eq!(parse("--headless").unwrap().headless_frames(), 60);
+    assert!(parse("-h").unwrap().help);
+    assert!(parse("--list-adapters").unwrap().list_adapters);
+}
+
+#[test]
+fn invalid_options_are_errors() {
+    for args in [
+        "--bogus",
+        "--backend nothing",
+        "--backend vulkan,foo",
+        "--backend vulkan,",
+        "--power max",
+        "--present-mode sometimes",
+        "--size 1280",
+        "--size 0x720",
+        "--frames many --headless",
+        "--scene",
+        // Only useful without a window, or with one
+        "--output out",
+        "--headless --record input.toml",
+        // A replay renders as many frames as it recorded
+        "--headless --replay input.toml --frames 10",
+    ] {
+        assert!(parse(args).is_err(), "{} was accepted", args);
+    }
+}
@@ -0,0 +1,582 @@
+// Parsing of KTX2 and DDS containers and the choice between compressed variants.
+// How the textures look once uploaded is covered by the golden tests.
+
+use learn_wgpu::compressed::{self, CompressedImage, CompressionFamily};
+use learn_wgpu::texture::ColorSpace;
+
+// Distinct bytes for every level, so levels that are swapped or cut short are noticed
+fn level_data(level: usize, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + level * 31) as u8).collect()
+}
+
+// A KTX2 file with an empty data format descriptor and `levels` (level 0 first)
+fn ktx2_file(
+    format: u32,
+    width: u32,
+    height: u32,
+    supercompression: u32,
+    levels: &[Vec<u8>],
+) -> Vec<u8> {
+    // The descriptor only contains its own size
+    let dfd = 4u32.to_le_bytes();
+    ktx2_file_with(format, width, height, supercompression, &dfd, &[], levels)
+}
+
+// A Basis Universal file: no format, a descriptor with the color model and the
+// supercompression global data of ETC1S files
+fn basis_file(
+    color_model: ktx2::ColorModel,
+    width: u32,
+    height: u32,
+    supercompression: u32,
+    global_data: &[u8],
+    levels: &[Vec<u8>],
+) -> Vec<u8> {
+    let block_size = ktx2::DfdHeader::LENGTH + ktx2::DfdBlockHeaderBasic::LENGTH;
+    let basic = ktx2::DfdBlockHeaderBasic {
+        color_model: Some(color_model),
+        color_primaries: None,
+        transfer_function: Some(ktx2::TransferFunction::Linear),
+        flags: ktx2::DataFormatFlags::STRAIGHT_ALPHA,
+        texel_block_dimensions: [4, 4, 1, 1].map(|d| std::num::NonZeroU8::new(d).unwrap()),
+        bytes_planes: [0; 8],
+    };
+    let mut dfd = ((4 + block_size) as u32).to_le_bytes().to_vec();
+    dfd.extend_from_slice(&ktx2::DfdHeader::BASIC.as_bytes(block_size as u16));
+    dfd.extend_from_slice(&basic.as_bytes());
+    ktx2_file_with(
+        0,
+        width,
+        height,
+        supercompression,
+        &dfd,
+        global_data,
+        levels,
+    )
+}
+
+fn ktx2_file_with(
+    format: u32,
+    width: u32,
+    height: u32,
+    supercompression: u32,
+    dfd: &[u8],
+    global_data: &[u8],
+    levels: &[Vec<u8>],
+) -> Vec<u8> {
+    let index_end = ktx2::Header::LENGTH + levels.len() * ktx2::LevelIndex::LENGTH;
+    let global_data_offset = index_end + dfd.len();
+    let mut offset = (global_data_offset + global_data.len()) as u64;
+    let mut index = Vec::new();
+    for data in levels {
+        let level = ktx2::LevelIndex {
+            byte_offset: offset,
+            byte_length: data.len() as u64,
+            uncompressed_byte_length: 0,
+        };
+        index.extend_from_slice(&level.as_bytes());
+        offset += data.len() as u64;
+    }
+
+    let header = ktx2::Header {
+        format: ktx2::Format::new(format),
+        type_size: 1,
+        pixel_width: width,
+        pixel_height: height,
+        pixel_depth: 0,
+        layer_count: 0,
+        face_count: 1,
+        level_count: levels.len() as u32,
+        supercompression_scheme: ktx2::SupercompressionScheme::new(supercompression),
+        index: ktx2::Index {
+            dfd_byte_offset: index_end as u32,
+            dfd_byte_length: dfd.len() as u32,
+            kvd_byte_offset: 0,
+            kvd_byte_length: 0,
+            sgd_byte_offset: global_data_offset as u64,
+            sgd_byte_length: global_data.len() as u64,
+        },
+    };
+    let mut file = header.as_bytes().to_vec();
+    file.extend_from_slice(&index);
+    file.extend_from_slice(dfd);
+    file.extend_from_slice(global_data);
+    for data in levels {
+        file.extend_from_slice(data);
+    }
+    file
+}
+
+#[test]
+fn ktx2_zstd_levels() {
+    // ETC2 RGB8 is 8 bytes per 4x4 block: 8x4 texels = 2 blocks, then 4x2 = 1 block
+    let levels = [level_data(0, 16), level_data(1, 8)];
+    let compressed: Vec<Vec<u8>> = levels
+        .iter()
+        .map(|data| {
+            ruzstd::encoding::compress_to_vec(
+                &data[..],
+                ruzstd::encoding::CompressionLevel::Fastest,
+            )
+        })
+        .collect();
+    let file = ktx2_file(147, 8, 4, 2, &compressed);
+
+    assert!(compressed::is_container(&file));
+    let image = CompressedImage::from_bytes(&file, ColorSpace::Srgb).unwrap();
+    assert_eq!(image.format, wgpu::TextureFormat::Etc2Rgb8Unorm);
+    assert_eq!((image.width, image.height), (8, 4));
+    assert_eq!(image.levels, levels);
+}
+
+#[test]
+fn ktx2_astc_format() {
+    // 158 is ASTC 4x4 sRGB, 16 bytes per block
+    let file = ktx2_file(158, 4, 4, 0, &[level_data(0, 16)]);
+    let image = CompressedImage::from_ktx2(&file).unwrap();
+    assert_eq!(
+        image.format,
+        wgpu::TextureFormat::Astc {
+            block: wgpu::AstcBlock::B4x4,
+            channel: wgpu::AstcChannel::UnormSrgb,
+        }
+    );
+    assert_eq!(
+        CompressionFamily::of(image.format),
+        Some(CompressionFamily::Astc)
+    );
+    let decoded = image.decode().unwrap();
+    assert_eq!(decoded.format, wgpu::TextureFormat::Rgba8UnormSrgb);
+    assert_eq!(decoded.levels[0].len(), 4 * 4 * 4);
+}
+
+#[test]
+fn every_format_decodes() {
+    use wgpu::TextureFormat as F;
+    let mut formats = vec![
+        (F::Bc1RgbaUnorm, F::Rgba8Unorm),
+        (F::Bc2RgbaUnormSrgb, F::Rgba8UnormSrgb),
+        (F::Bc3RgbaUnorm, F::Rgba8Unorm),
+        (F::Bc4RUnorm, F::Rgba8Unorm),
+        (F::Bc4RSnorm, F::Rgba8Snorm),
+        (F::Bc5RgUnorm, F::Rgba8Unorm),
+        (F::Bc5RgSnorm, F::Rgba8Snorm),
+        (F::Bc6hRgbUfloat, F::Rgba16Float),
+        (F::Bc6hRgbFloat, F::Rgba16Float),
+        (F::Bc7RgbaUnormSrgb, F::Rgba8UnormSrgb),
+        (F::Etc2Rgb8Unorm, F::Rgba8Unorm),
+        (F::Etc2Rgb8A1UnormSrgb, F::Rgba8UnormSrgb),
+        (F::Etc2Rgba8Unorm, F::Rgba8Unorm),
+        (F::EacR11Unorm, F::Rgba16Float),
+        (F::EacR11Snorm, F::Rgba16Float),
+        (F::EacRg11Unorm, F::Rgba16Float),
+        (F::EacRg11Snorm, F::Rgba16Float),
+    ];
+    for block in [wgpu::AstcBlock::B5x4, wgpu::AstcBlock::B12x12] {
+        formats.push((
+            F::Astc {
+                block,
+                channel: wgpu::AstcChannel::Unorm,
+            },
+            F::Rgba8Unorm,
+        ));
+    }
+    for (format, decoded_format) in formats {
+        // Partial blocks at the right and bottom edges are cropped
+        let (block_width, block_height) = format.block_dimensions();
+        let (width, height) = (block_width + 1, block_height + 1);
+        let block_size = format.block_copy_size(None).unwrap() as usize;
+        let image = CompressedImage {
+            format,
+            width,
+            height,
+            levels: vec![level_data(0, 4 * block_size)],
+        };
+        let decoded = image.decode().unwrap();
+        assert_eq!(decoded.format, decoded_format, "{:?}", format);
+        let texel_size = decoded_format.block_copy_size(None).unwrap();
+        assert_eq!(
+            decoded.levels[0].len(),
+            (width * height * texel_size) as usize,
+            "{:?}",
+            format
+        );
+    }
+}
+
+#[test]
+fn astc_hdr_is_not_decoded() {
+    let image = CompressedImage {
+        format: wgpu::TextureFormat::Astc {
+            block: wgpu::AstcBlock::B4x4,
+            channel: wgpu::AstcChannel::Hdr,
+        },
+        width: 4,
+        height: 4,
+        levels: vec![level_data(0, 16)],
+    };
+    assert!(image.decode().is_err());
+}
+
+// Basis Universal bitstreams are read from the lowest bit of each byte up
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: usize,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: usize) {
+        for i in 0..count {
+            if self.bits.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            *self.bytes.last_mut().unwrap() |= (((value >> i) & 1) as u8) << (self.bits % 8);
+            self.bits += 1;
+        }
+    }
+
+    // A Huffman table of `symbol_count` symbols where the `used` ones, in increasing
+    // order, get codes of the same length. The code lengths are themselves coded with
+    // 1 bit codes: 0 for unused symbols, 1 for the length of the used ones. Tables with
+    // no used symbols are written as empty.
+    fn huffman_table(&mut self, symbol_count: u32, used: &[u32]) {
+        if used.is_empty() {
+            self.write(0, 14);
+            return;
+        }
+        self.write(symbol_count, 14);
+        let length = used.len().next_power_of_two().trailing_zeros().max(1);
+        let order = [
+            17, 18, 19, 20, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15, 16,
+        ];
+        self.write(order.len() as u32, 5);
+        for symbol in order {
+            self.write((symbol == 0 || symbol == length) as u32, 3);
+        }
+        for symbol in 0..symbol_count {
+            self.write(used.contains(&symbol) as u32, 1);
+        }
+    }
+
+    // Codes are written from their top bit down
+    fn symbol(&mut self, used: &[u32], symbol: u32) {
+        let length = used.len().next_power_of_two().trailing_zeros().max(1) as usize;
+        let code = used.iter().position(|&s| s == symbol).unwrap() as u32;
+        self.write(code.reverse_bits() >> (32 - length), length);
+    }
+}
+
+// An 8x4 ETC1S texture: both blocks use the only endpoint, color (31, 0, 16) in 5 bits
+// with modifier table 3, and the only selectors, 0 to 3 from left to right in each row
+fn etc1s_file(with_alpha: bool) -> Vec<u8> {
+    // Deltas from (16, 16, 16) and table 0, the middle color model for all channels
+    let mut endpoints = BitWriter::default();
+    let color_deltas = [0, 15, 16];
+    endpoints.huffman_table(32, &[]);
+    endpoints.huffman_table(32, &color_deltas);
+    endpoints.huffman_table(32, &[]);
+    endpoints.huffman_table(8, &[3]);
+    // Not grayscale
+    endpoints.write(0, 1);
+    endpoints.symbol(&[3], 3);
+    for delta in [15, 16, 0] {
+        endpoints.symbol(&color_deltas, delta);
+    }
+
+    // No global or hybrid codebook, raw selectors
+    let mut selectors = BitWriter::default();
+    selectors.write(0b100, 3);
+    selectors.write(0xe4e4e4e4, 32);
+
+    // Blocks predict their endpoint with a delta of 0 from the last one (3), then use the
+    // one on their left (0), there is no selector history
+    let mut tables = BitWriter::default();
+    tables.huffman_table(257, &[0b0011]);
+    tables.huffman_table(1, &[0]);
+    tables.huffman_table(2, &[0]);
+    tables.huffman_table(64, &[]);
+    tables.write(0, 13);
+
+    let mut slice = BitWriter::default();
+    slice.symbol(&[0b0011], 0b0011);
+    slice.symbol(&[0], 0);
+    slice.symbol(&[0], 0);
+    slice.symbol(&[0], 0);
+
+    let mut global_data = Vec::new();
+    global_data.extend_from_slice(&1u16.to_le_bytes());
+    global_data.extend_from_slice(&1u16.to_le_bytes());
+    for length in [
+        endpoints.bytes.len(),
+        selectors.bytes.len(),
+        tables.bytes.len(),
+        0,
+    ] {
+        global_data.extend_from_slice(&(length as u32).to_le_bytes());
+    }
+    // The alpha slice is the same as the color one, its green channel being the alpha
+    let slice_length = slice.bytes.len() as u32;
+    let alpha = if with_alpha {
+        [0, slice_length]
+    } else {
+        [0, 0]
+    };
+    for value in [0, 0, slice_length, alpha[0], alpha[1]] {
+        global_data.extend_from_slice(&value.to_le_bytes());
+    }
+    for bytes in [endpoints.bytes, selectors.bytes, tables.bytes] {
+        global_data.extend_from_slice(&bytes);
+    }
+    basis_file(
+        ktx2::ColorModel::ETC1S,
+        8,
+        4,
+        1,
+        &global_data,
+        &[slice.bytes],
+    )
+}
+
+// The texels of etc1s_file: the 5 bit color extended to 8 bits, plus -42, -13, 13 or 42
+fn etc1s_texels() -> Vec<[u8; 4]> {
+    (0..32)
+        .map(|i| {
+            let modifier = [-42, -13, 13, 42][i % 4];
+            let [r, g, b] = [255, 0, 132].map(|c: i32| (c + modifier).clamp(0, 255) as u8);
+            [r, g, b, 255]
+        })
+        .collect()
+}
+
+fn texels(image: &CompressedImage) -> Vec<[u8; 4]> {
+    image.levels[0]
+        .chunks_exact(4)
+        .map(|texel| texel.try_into().unwrap())
+        .collect()
+}
+
+#[test]
+fn ktx2_etc1s_is_transcoded_to_etc2() {
+    let image = CompressedImage::from_ktx2(&etc1s_file(false)).unwrap();
+    assert_eq!(image.format, wgpu::TextureFormat::Etc2Rgb8Unorm);
+    assert_eq!(texels(&image.decode().unwrap()), etc1s_texels());
+
+    // EAC gets close to the alpha, but not always exactly
+    let image = CompressedImage::from_ktx2(&etc1s_file(true)).unwrap();
+    assert_eq!(image.format, wgpu::TextureFormat::Etc2Rgba8Unorm);
+    let decoded = texels(&image.decode().unwrap());
+    for (texel, expected) in decoded.iter().zip(etc1s_texels()) {
+        assert_eq!(texel[..3], expected[..3]);
+        assert!(texel[3].abs_diff(expected[1]) <= 2, "{:?}", decoded);
+    }
+}
+
+// A 12x4 UASTC texture: a solid color block (mode 8), a block with 8 bit RGB endpoints
+// going from (0, 255, 64) to (255, 0, 64) left to right (mode 1), and one going from
+// (0, 255, 125) to (255, 0, 125) with endpoints made of a trit and 6 bits (mode 0)
+fn uastc_file(supercompression: u32) -> Vec<u8> {
+    let solid: u128 = 0x17 | 10 << 5 | 20 << 13 | 30 << 21 | 40 << 29;
+    let mut gradient = BitWriter::default();
+    gradient.write(0x35, 6);
+    // Hints for transcoding to BC7 or ETC
+    gradient.write(0, 15);
+    for endpoint in [0, 255, 255, 0, 64, 64] {
+        gradient.write(endpoint, 8);
+    }
+    // The first weight has one bit less
+    for i in 0..16 {
+        gradient.write(i % 4, if i == 0 { 1 } else { 2 });
+    }
+    gradient.bytes.resize(16, 0);
+
+    let mut trits = BitWriter::default();
+    trits.write(0x1, 4);
+    trits.write(0, 15);
+    // The trits of the 5 first values as a base 3 number, then the one of the last value,
+    // then the low bits of every value. Like in ASTC the order of the values isn't the
+    // order of the colors: 0 is 0, 1 is 255 and 1 * 64 + 62 is 125.
+    trits.write(81, 8);
+    trits.write(1, 2);
+    for endpoint in [0, 1, 1, 0, 62, 62] {
+        trits.write(endpoint, 6);
+    }
+    for i in 0..16 {
+        trits.write(i % 4 * 5, if i == 0 { 3 } else { 4 });
+    }
+    trits.bytes.resize(16, 0);
+
+    let mut level = solid.to_le_bytes().to_vec();
+    level.extend_from_slice(&gradient.bytes);
+    level.extend_from_slice(&trits.bytes);
+    if supercompression == 2 {
+        level = ruzstd::encoding::compress_to_vec(
+            &level[..],
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+    }
+    basis_file(
+        ktx2::ColorModel::UASTC,
+        12,
+        4,
+        supercompression,
+        &[],
+        &[level],
+    )
+}
+
+fn check_uastc_texels(texels: &[[u8; 4]], tolerance: u8) {
+    for (i, texel) in texels.iter().enumerate() {
+        let (x, y) = (i % 12, i / 12);
+        // The weights are 0, 21, 43 and 64 sixty-fourths
+        let t = [0, 84, 171, 255][x % 4];
+        let expected = match x / 4 {
+            0 => [10, 20, 30, 40],
+            1 => [t, 255 - t, 64, 255],
+            _ => [t, 255 - t, 125, 255],
+        };
+        for c in 0..4 {
+            assert!(
+                texel[c].abs_diff(expected[c]) <= tolerance,
+                "texel ({}, {}): {:?} instead of {:?}",
+                x,
+                y,
+                texel,
+                expected
+            );
+        }
+    }
+}
+
+#[test]
+fn ktx2_uastc_is_transcoded_to_astc() {
+    for supercompression in [0, 2] {
+        let image = CompressedImage::from_ktx2(&uastc_file(supercompression)).unwrap();
+        assert_eq!(
+            image.format,
+            wgpu::TextureFormat::Astc {
+                block: wgpu::AstcBlock::B4x4,
+                channel: wgpu::AstcChannel::Unorm,
+            }
+        );
+        check_uastc_texels(&texels(&image.decode().unwrap()), 1);
+    }
+}
+
+#[test]
+fn basis_universal_falls_back_to_bc7_then_rgba8() {
+    let bc = wgpu::Features::TEXTURE_COMPRESSION_BC;
+    let file = uastc_file(0);
+    let image = CompressedImage::from_bytes_for(&file, ColorSpace::Srgb, bc).unwrap();
+    assert_eq!(image.format, wgpu::TextureFormat::Bc7RgbaUnorm);
+    check_uastc_texels(&texels(&image.decode().unwrap()), 4);
+
+    let image = CompressedImage::from_bytes_for(&etc1s_file(false), ColorSpace::Srgb, bc).unwrap();
+    assert_eq!(image.format, wgpu::TextureFormat::Bc7RgbaUnorm);
+
+    let none = wgpu::Features::empty();
+    let image = CompressedImage::from_bytes_for(&file, ColorSpace::Srgb, none).unwrap();
+    assert_eq!(image.format, wgpu::TextureFormat::Rgba8Unorm);
+    check_uastc_texels(&texels(&image), 1);
+}
+
+#[test]
+fn invalid_uastc_blocks_are_rejected() {
+    // 0x45 is the reserved mode code
+    let level = 0x45u128.to_le_bytes().to_vec();
+    let file = basis_file(ktx2::ColorModel::UASTC, 4, 4, 0, &[], &[level]);
+    assert!(CompressedImage::from_ktx2(&file).is_err());
+}
+
+#[test]
+fn ktx2_missing_level_data_is_rejected() {
+    // A 8x8 BC1 level needs 4 blocks of 8 bytes
+    let file = ktx2_file(131, 8, 8, 0, &[level_data(0, 16)]);
+    assert!(CompressedImage::from_ktx2(&file).is_err());
+}
+
+#[test]
+fn ktx2_too_many_levels_are_rejected() {
+    // A 4x4 texture only has 3 levels: 4x4, 2x2 and 1x1
+    let levels: Vec<_> = (0..4).map(|level| level_data(level, 8)).collect();
+    let file = ktx2_file(131, 4, 4, 0, &levels);
+    let error = CompressedImage::from_ktx2(&file).unwrap_err();
+    assert!(error.to_string().contains("mip levels"), "{}", error);
+    assert!(CompressedImage::from_ktx2(&ktx2_file(131, 4, 4, 0, &levels[..3])).is_ok());
+}
+
+#[test]
+fn dds_mip_levels() {
+    let mut dds = ddsfile::Dds::new_dxgi(ddsfile::NewDxgiParams {
+        height: 8,
+        width: 8,
+        depth: None,
+        format: ddsfile::DxgiFormat::BC3_UNorm_sRGB,
+        mipmap_levels: Some(4),
+        array_layers: None,
+        caps2: None,
+        is_cubemap: false,
+        resource_dimension: ddsfile::D3D10ResourceDimension::Texture2D,
+        alpha_mode: ddsfile::AlphaMode::Unknown,
+    })
+    .unwrap();
+    // BC3 is 16 bytes per block: 8x8 = 4 blocks, then one block for 4x4, 2x2 and 1x1
+    let levels = [
+        level_data(0, 64),
+        level_data(1, 16),
+        level_data(2, 16),
+        level_data(3, 16),
+    ];
+    dds.get_mut_data(0)
+        .unwrap()
+        .copy_from_slice(&levels.concat());
+    let mut file = Vec::new();
+    dds.write(&mut file).unwrap();
+
+    let image = CompressedImage::from_bytes(&file, ColorSpace::Linear).unwrap();
+    assert_eq!(image.format, wgpu::TextureFormat::Bc3RgbaUnormSrgb);
+    assert_eq!((image.width, image.height), (8, 8));
+    assert_eq!(image.levels, levels);
+}
+
+#[test]
+fn dds_legacy_format_uses_color_space() {
+    let mut dds = ddsfile::Dds::new_d3d(ddsfile::NewD3dParams {
+        height: 4,
+        width: 4,
+        depth: None,
+        format: ddsfile::D3DFormat::DXT1,
+        mipmap_levels: None,
+        caps2: None,
+    })
+    .unwrap();
+    dds.get_mut_data(0)
+        .unwrap()
+        .copy_from_slice(&level_data(0, 8));
+    let mut file = Vec::new();
+    dds.write(&mut file).unwrap();
+
+    let srgb = CompressedImage::from_dds(&file, ColorSpace::Srgb).unwrap();
+    assert_eq!(srgb.format, wgpu::TextureFormat::Bc1RgbaUnormSrgb);
+    let linear = CompressedImage::from_dds(&file, ColorSpace::Linear).unwrap();
+    assert_eq!(linear.format, wgpu::TextureFormat::Bc1RgbaUnorm);
+}
+
+#[test]
+fn select_prefers_bc_then_astc_then_etc2() {
+    use CompressionFamily::*;
+    use wgpu::Features as F;
+
+    let variants = [(Etc2, "etc2"), (Astc, "astc"), (Bc, "bc")];
+    let all = compressed::COMPRESSION_FEATURES;
+    assert_eq!(compressed::select(all, &variants), Some(&"bc"));
+    let mobile = F::TEXTURE_COMPRESSION_ETC2 | F::TEXTURE_COMPRESSION_ASTC;
+    assert_eq!(compressed::select(mobile, &variants), Some(&"astc"));
+    assert_eq!(
+        compressed::select(F::TEXTURE_COMPRESSION_ETC2, &variants),
+        Some(&"etc2")
+    );
+    assert_eq!(compressed::select(F::empty(), &variants), None);
+    // Only the variants that exist are considered
+    assert_eq!(compressed::select(all, &variants[..1]), Some(&"etc2"));
+}
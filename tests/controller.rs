@@ -0,0 +1,115 @@
+// Camera controllers, driven through the same methods window_event and device_event use.
+
+use std::time::Duration;
+
+use cgmath::{InnerSpace, MetricSpace, Point3};
+use learn_wgpu::camera::Camera;
+use learn_wgpu::controller::{CameraController, FlyController, FpsController, OrbitController};
+use winit::event::{MouseButton, MouseScrollDelta};
+use winit::keyboard::KeyCode;
+
+fn camera() -> Camera {
+    Camera::perspective(Point3::new(0.0, 0.0, 5.0), Point3::new(0.0, 0.0, 0.0), 1.0)
+}
+
+fn assert_close(actual: Point3<f32>, expected: Point3<f32>) {
+    assert!(
+        actual.distance(expected) < 1e-4,
+        "{:?} != {:?}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn movement_does_not_depend_on_frame_rate() {
+    let mut controller = FlyController::default();
+    controller.process_keyboard(KeyCode::KeyW, true);
+
+    let mut slow = camera();
+    controller.update_camera(&mut slow, Duration::from_millis(500));
+    let mut fast = camera();
+    for _ in 0..50 {
+        controller.update_camera(&mut fast, Duration::from_millis(10));
+    }
+
+    assert_close(slow.eye, fast.eye);
+    // Half a second forward (down -Z) at the default speed
+    assert_close(
+        slow.eye,
+        Point3::new(0.0, 0.0, 5.0 - controller.speed * 0.5),
+    );
+}
+
+#[test]
+fn released_keys_stop_the_camera() {
+    let mut controller = FlyController::default();
+    let mut camera = camera();
+    controller.process_keyboard(KeyCode::KeyE, true);
+    controller.process_keyboard(KeyCode::KeyE, false);
+    controller.process_keyboard(KeyCode::KeyD, true);
+    controller.reset();
+    controller.update_camera(&mut camera, Duration::from_secs(1));
+    assert_close(camera.eye, Point3::new(0.0, 0.0, 5.0));
+}
+
+#[test]
+fn fly_looks_around_only_while_the_right_button_is_held() {
+    let mut controller = FlyController::default();
+    let mut camera = camera();
+    controller.process_mouse_motion(100.0, 0.0);
+    controller.update_camera(&mut camera, Duration::ZERO);
+    assert_close(camera.target, Point3::new(0.0, 0.0, 4.0));
+
+    controller.process_mouse_button(MouseButton::Right, true);
+    assert!(controller.cursor_grabbed());
+    controller.process_mouse_motion(100.0, 0.0);
+    controller.update_camera(&mut camera, Duration::ZERO);
+    // Moving the mouse right turns right, towards +X
+    assert!(camera.target.x > 0.0);
+    assert_close(camera.eye, Point3::new(0.0, 0.0, 5.0));
+}
+
+#[test]
+fn orbit_keeps_the_distance_to_the_target() {
+    let mut controller = OrbitController::default();
+    let mut camera = camera();
+    controller.process_mouse_button(MouseButton::Left, true);
+    controller.process_mouse_motion(150.0, -80.0);
+    controller.update_camera(&mut camera, Duration::from_millis(16));
+
+    assert_close(camera.target, Point3::new(0.0, 0.0, 0.0));
+    assert!((camera.eye.distance(camera.target) - 5.0).abs() < 1e-4);
+    // Dragging right moves the camera to the left of the scene
+    assert!(camera.eye.x < 0.0);
+}
+
+#[test]
+fn orbit_zooms_with_the_wheel() {
+    let mut controller = OrbitController::default();
+    let mut camera = camera();
+    controller.process_scroll(MouseScrollDelta::LineDelta(0.0, 2.0));
+    controller.update_camera(&mut camera, Duration::from_millis(16));
+    let expected = 5.0 * (1.0 - controller.zoom_speed).powi(2);
+    assert!((camera.eye.distance(camera.target) - expected).abs() < 1e-4);
+}
+
+#[test]
+fn fps_walks_on_the_ground_when_looking_down() {
+    let mut controller = FpsController::default();
+    let mut camera = camera();
+    // Click to grab the cursor, then look down
+    controller.process_mouse_button(MouseButton::Left, true);
+    assert!(controller.cursor_grabbed());
+    controller.process_mouse_motion(0.0, 300.0);
+    controller.process_keyboard(KeyCode::KeyW, true);
+    controller.update_camera(&mut camera, Duration::from_secs(1));
+
+    assert!((camera.target - camera.eye).normalize().y < -0.5);
+    assert_close(camera.eye, Point3::new(0.0, 0.0, 5.0 - controller.speed));
+
+    // Escape releases the cursor instead of reaching the app
+    assert!(controller.process_keyboard(KeyCode::Escape, true));
+    assert!(!controller.cursor_grabbed());
+    assert!(!controller.process_keyboard(KeyCode::Escape, true));
+}
@@ -0,0 +1,30 @@
+// Startup errors keep what went wrong and why.
+
+use learn_wgpu::InitError;
+
+#[test]
+fn init_errors_describe_the_failed_step() {
+    let error = InitError::CanvasMissing("canvas".to_string());
+    assert_eq!(
+        error.to_string(),
+        "No <canvas id=\"canvas\"> element found in the page"
+    );
+
+    // run() returns anyhow errors, the InitError can still be told apart
+    let error = anyhow::Error::from(InitError::UnsupportedSurface("llvmpipe".to_string()));
+    assert!(matches!(
+        error.downcast_ref::<InitError>(),
+        Some(InitError::UnsupportedSurface(_))
+    ));
+    assert!(format!("{:#}", error).contains("llvmpipe"));
+}
+
+// A recording that can't be replayed reports its error instead of panicking
+#[test]
+fn replay_errors_are_returned() {
+    let recording = learn_wgpu::recording::Recording::default();
+    let file = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("not-a-directory");
+    std::fs::write(&file, "").unwrap();
+    let result = pollster::block_on(learn_wgpu::App::replay_headless(recording, Some(&file)));
+    assert!(result.is_err());
+}
@@ -0,0 +1,487 @@
+// Golden-image regression tests: every scene is rendered headlessly and compared
+// against the reference PNGs in tests/golden/ (see harness/mod.rs).
+
+mod harness;
+
+use harness::{Golden, Tolerance, compare};
+use learn_wgpu::camera::Camera;
+use learn_wgpu::compressed::CompressedImage;
+use learn_wgpu::mesh::{ColorVertex, Mesh, TexturedVertex};
+use learn_wgpu::mipmap::MipmapMode;
+use learn_wgpu::texture::{SamplerOptions, Texture, TextureOptions};
+
+#[test]
+fn default_scene() {
+    Golden::slanted_edges().check("default_scene", |_| {});
+}
+
+#[test]
+fn default_scene_after_resize() {
+    Golden::slanted_edges().check("default_scene_resized", |state| state.resize(48, 32));
+}
+
+#[test]
+fn custom_clear_color() {
+    Golden::slanted_edges().check("custom_clear_color", |state| {
+        state.set_clear_color(wgpu::Color::WHITE);
+    });
+}
+
+// The default triangle seen from above and to the right, so it gets smaller and skewed
+#[test]
+fn perspective_camera() {
+    Golden::slanted_edges().check("perspective_camera", |state| {
+        state.set_camera(Camera::perspective(
+            (1.0, 0.5, 2.0).into(),
+            (0.0, 0.0, 0.0).into(),
+            1.0,
+        ));
+    });
+}
+
+// A red quad in front of a green one, added first so the green one is drawn over it.
+// The depth test keeps the red quad visible where they overlap.
+fn check_depth(name: &str, setup: impl FnOnce(&mut learn_wgpu::State)) {
+    Golden::slanted_edges().check(name, |state| {
+        setup(state);
+        state.set_camera(Camera {
+            reversed_z: state.camera().reversed_z,
+            ..Camera::perspective((0.0, 0.0, 3.0).into(), (0.0, 0.0, 0.0).into(), 1.0)
+        });
+        state.clear_meshes();
+        for (center, z, color) in [(-0.3, 0.0, [1.0, 0.0, 0.0]), (0.3, -1.0, [0.0, 1.0, 0.0])] {
+            let vertices =
+                [[-0.6, -0.6], [0.6, -0.6], [0.6, 0.6], [-0.6, 0.6]].map(|[x, y]| ColorVertex {
+                    position: [center + x, y, z],
+                    color,
+                });
+            let quad = Mesh::new_indexed(state.device(), "Quad", &vertices, &[0u16, 1, 2, 0, 2, 3]);
+            state.add_mesh(quad);
+        }
+    });
+}
+
+#[test]
+fn depth_test() {
+    check_depth("depth_test", |_| {});
+}
+
+#[test]
+fn depth_test_reversed_z() {
+    check_depth("depth_test", |state| state.set_reversed_z(true));
+}
+
+#[test]
+fn depth_test_with_stencil() {
+    check_depth("depth_test", |state| {
+        state
+            .set_depth_format(wgpu::TextureFormat::Depth24PlusStencil8)
+            .unwrap()
+    });
+}
+
+// The edges of the default triangle blend with the background
+#[test]
+fn msaa_4x() {
+    Golden::slanted_edges().check("msaa_4x", |state| {
+        assert!(state.supported_sample_counts().contains(&4));
+        assert_eq!(state.set_sample_count(4).unwrap(), 4);
+        assert!(state.set_sample_count(3).is_err());
+    });
+}
+
+// Switching MSAA off again gives exactly the frame without MSAA
+#[test]
+fn msaa_off() {
+    Golden::slanted_edges().check("default_scene", |state| {
+        state.set_sample_count(4).unwrap();
+        state.resize(64, 64);
+        // Cycling goes to the next supported count, then back to 1
+        let supported = state.supported_sample_counts();
+        let next = supported.iter().copied().find(|&count| count > 4);
+        assert_eq!(state.cycle_sample_count().unwrap(), next.unwrap_or(1));
+        state.set_sample_count(1).unwrap();
+    });
+}
+
+// The depth test still works with multisampled depth
+#[test]
+fn depth_test_msaa() {
+    check_depth("depth_test_msaa", |state| {
+        state.set_sample_count(4).unwrap();
+    });
+}
+
+#[test]
+fn indexed_quad() {
+    Golden::slanted_edges().check("indexed_quad", |state| {
+        let vertices = [
+            ([-0.5, -0.5], [1.0, 1.0, 0.0]),
+            ([0.5, -0.5], [0.0, 1.0, 1.0]),
+            ([0.5, 0.5], [1.0, 0.0, 1.0]),
+            ([-0.5, 0.5], [1.0, 1.0, 1.0]),
+        ]
+        .map(|([x, y], color)| ColorVertex {
+            position: [x, y, 0.0],
+            color,
+        });
+        let indices: [u32; 6] = [0, 1, 2, 0, 2, 3];
+        let quad = Mesh::new_indexed(state.device(), "Quad", &vertices, &indices);
+        state.clear_meshes();
+        state.add_mesh(quad);
+    });
+}
+
+// A black and white checkerboard encoded as PNG, like an image file loaded from disk
+fn checkerboard_png(width: u32, height: u32) -> Vec<u8> {
+    let img = image::RgbaImage::from_fn(width, height, |x, y| {
+        if (x + y) % 2 == 0 {
+            image::Rgba([255, 255, 255, 255])
+        } else {
+            image::Rgba([0, 0, 0, 255])
+        }
+    });
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    img.write_to(&mut bytes, image::ImageFormat::Png).unwrap();
+    bytes.into_inner()
+}
+
+// Replaces the scene with a quad covering `size` of the frame (1.0 = whole frame)
+fn add_textured_quad(
+    state: &mut learn_wgpu::State,
+    size: f32,
+    png: &[u8],
+    options: TextureOptions,
+) {
+    let texture = state.create_texture(png, "Checkerboard", options).unwrap();
+    add_quad_with_texture(state, size, &texture);
+}
+
+fn add_quad_with_texture(state: &mut learn_wgpu::State, size: f32, texture: &Texture) {
+    let h = size / 2.0;
+    let vertices = [
+        ([-h, -h], [0.0, 1.0]),
+        ([h, -h], [1.0, 1.0]),
+        ([h, h], [1.0, 0.0]),
+        ([-h, h], [0.0, 0.0]),
+    ]
+    .map(|([x, y], tex_coords)| TexturedVertex {
+        position: [x, y, 0.0],
+        tex_coords,
+    });
+    let quad = Mesh::new_indexed(state.device(), "Quad", &vertices, &[0u16, 1, 2, 0, 2, 3]);
+    state.clear_meshes();
+    state.add_textured_mesh(quad, texture);
+}
+
+#[test]
+fn textured_quad() {
+    Golden::default().check("textured_quad", |state| {
+        let options = TextureOptions {
+            sampler: SamplerOptions {
+                mag_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        add_textured_quad(state, 1.0, &checkerboard_png(4, 4), options);
+    });
+}
+
+// The same checkerboard as a JPEG gives the same frame, give or take the compression error
+#[test]
+fn textured_quad_from_jpeg() {
+    let png = image::load_from_memory(&checkerboard_png(4, 4)).unwrap();
+    let mut jpeg = std::io::Cursor::new(Vec::new());
+    let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 100);
+    png.to_rgb8().write_with_encoder(encoder).unwrap();
+    let golden = Golden {
+        tolerance: Tolerance::PerChannel(8),
+        ..Default::default()
+    };
+    golden.check("textured_quad", |state| {
+        let options = TextureOptions {
+            sampler: SamplerOptions {
+                mag_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        add_textured_quad(state, 1.0, jpeg.get_ref(), options);
+    });
+}
+
+// Destroys the device like a driver crash would, then lets State rebuild everything
+fn lose_device(state: &mut learn_wgpu::State) {
+    state.device().destroy();
+    // The device lost callback runs when the device is polled
+    let _ = state.device().poll(wgpu::PollType::Wait);
+    assert!(state.is_device_lost());
+    pollster::block_on(state.recreate_device()).unwrap();
+    assert!(!state.is_device_lost());
+}
+
+// The meshes, attachments and MSAA settings survive a device loss
+#[test]
+fn msaa_after_device_loss() {
+    Golden::slanted_edges().check("msaa_4x", |state| {
+        state.set_sample_count(4).unwrap();
+        lose_device(state);
+        assert_eq!(state.sample_count(), 4);
+    });
+}
+
+#[test]
+fn textured_quad_after_device_loss() {
+    Golden::default().check("textured_quad", |state| {
+        let options = TextureOptions {
+            sampler: SamplerOptions {
+                mag_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        add_textured_quad(state, 1.0, &checkerboard_png(4, 4), options);
+        lose_device(state);
+    });
+}
+
+// A window opened next to the main one moves to the device the main window recreated
+#[test]
+fn textured_quad_on_shared_device() {
+    Golden::default().check("textured_quad", |state| {
+        let options = TextureOptions {
+            sampler: SamplerOptions {
+                mag_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        add_textured_quad(state, 1.0, &checkerboard_png(4, 4), options);
+        let config = state.config();
+        let main = pollster::block_on(learn_wgpu::State::new_headless(config.width, config.height))
+            .unwrap();
+        state.share_device(&main).unwrap();
+        assert!(state.device() == main.device());
+    });
+}
+
+// A non-power-of-two checkerboard shrunk to a few pixels: with mipmaps it averages to grey
+// instead of picking random black and white texels. GPU and CPU generation share the
+// reference image, so they also have to agree with each other.
+fn check_mipmapped_quad(mipmaps: MipmapMode) {
+    Golden::default().check("mipmapped_quad", |state| {
+        let options = TextureOptions {
+            sampler: SamplerOptions {
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            },
+            mipmaps,
+            ..Default::default()
+        };
+        add_textured_quad(state, 0.25, &checkerboard_png(63, 45), options);
+    });
+}
+
+#[test]
+fn mipmapped_quad_gpu() {
+    check_mipmapped_quad(MipmapMode::Gpu);
+}
+
+#[test]
+fn mipmapped_quad_cpu() {
+    check_mipmapped_quad(MipmapMode::Cpu);
+}
+
+// 32x32 texels of random blocks, which exercises every mode of the formats
+fn random_blocks(format: wgpu::TextureFormat) -> CompressedImage {
+    let block_size = format.block_copy_size(None).unwrap() as usize;
+    let (block_width, block_height) = format.block_dimensions();
+    let block_count = (32 / block_width * 32 / block_height) as usize;
+    let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+    let mut random_block = || {
+        (0..block_size)
+            .map(|_| {
+                // xorshift, so the references don't depend on a random crate
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                (seed >> 32) as u8
+            })
+            .collect::<Vec<_>>()
+    };
+    // Most random ASTC blocks are invalid (reserved modes, HDR endpoints...) and decode to
+    // magenta, those are skipped
+    let is_valid = |block: &[u8]| {
+        let image = CompressedImage {
+            format,
+            width: block_width,
+            height: block_height,
+            levels: vec![block.to_vec()],
+        };
+        let decoded = image.decode().unwrap();
+        !decoded.levels[0]
+            .chunks_exact(4)
+            .all(|texel| texel == [255, 0, 255, 255])
+    };
+    let mut data = Vec::with_capacity(block_count * block_size);
+    while data.len() < block_count * block_size {
+        let block = random_block();
+        if !matches!(format, wgpu::TextureFormat::Astc { .. }) || is_valid(&block) {
+            data.extend(block);
+        }
+    }
+    CompressedImage {
+        format,
+        width: 32,
+        height: 32,
+        levels: vec![data],
+    }
+}
+
+// Renders the blocks uploaded as is (when the adapter supports the format) and decoded to
+// RGBA8 on the CPU. Both share the reference, so the software decoder has to match the GPU.
+fn check_compressed(name: &str, format: wgpu::TextureFormat) {
+    let image = random_blocks(format);
+    let decoded = image.decode().unwrap();
+    for image in [&image, &decoded] {
+        Golden::default().check(name, |state| {
+            let sampler = SamplerOptions {
+                mag_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            };
+            let texture =
+                Texture::from_compressed(state.device(), state.queue(), image, name, sampler)
+                    .unwrap();
+            add_quad_with_texture(state, 1.0, &texture);
+        });
+    }
+}
+
+#[test]
+fn compressed_bc1() {
+    check_compressed("compressed_bc1", wgpu::TextureFormat::Bc1RgbaUnorm);
+}
+
+#[test]
+fn compressed_bc2() {
+    check_compressed("compressed_bc2", wgpu::TextureFormat::Bc2RgbaUnorm);
+}
+
+#[test]
+fn compressed_bc3_srgb() {
+    check_compressed("compressed_bc3_srgb", wgpu::TextureFormat::Bc3RgbaUnormSrgb);
+}
+
+#[test]
+fn compressed_bc4() {
+    check_compressed("compressed_bc4", wgpu::TextureFormat::Bc4RUnorm);
+}
+
+#[test]
+fn compressed_bc5() {
+    check_compressed("compressed_bc5", wgpu::TextureFormat::Bc5RgUnorm);
+}
+
+#[test]
+fn compressed_etc2_rgb8() {
+    check_compressed("compressed_etc2_rgb8", wgpu::TextureFormat::Etc2Rgb8Unorm);
+}
+
+#[test]
+fn compressed_etc2_rgba8_srgb() {
+    check_compressed(
+        "compressed_etc2_rgba8_srgb",
+        wgpu::TextureFormat::Etc2Rgba8UnormSrgb,
+    );
+}
+
+#[test]
+fn compressed_bc4_snorm() {
+    check_compressed("compressed_bc4_snorm", wgpu::TextureFormat::Bc4RSnorm);
+}
+
+#[test]
+fn compressed_bc5_snorm() {
+    check_compressed("compressed_bc5_snorm", wgpu::TextureFormat::Bc5RgSnorm);
+}
+
+#[test]
+fn compressed_bc6h() {
+    check_compressed("compressed_bc6h", wgpu::TextureFormat::Bc6hRgbUfloat);
+}
+
+#[test]
+fn compressed_bc6h_signed() {
+    check_compressed("compressed_bc6h_signed", wgpu::TextureFormat::Bc6hRgbFloat);
+}
+
+#[test]
+fn compressed_bc7() {
+    check_compressed("compressed_bc7", wgpu::TextureFormat::Bc7RgbaUnorm);
+}
+
+#[test]
+fn compressed_bc7_srgb() {
+    check_compressed("compressed_bc7_srgb", wgpu::TextureFormat::Bc7RgbaUnormSrgb);
+}
+
+#[test]
+fn compressed_etc2_rgb8a1() {
+    check_compressed(
+        "compressed_etc2_rgb8a1",
+        wgpu::TextureFormat::Etc2Rgb8A1Unorm,
+    );
+}
+
+#[test]
+fn compressed_eac_r11() {
+    check_compressed("compressed_eac_r11", wgpu::TextureFormat::EacR11Unorm);
+}
+
+#[test]
+fn compressed_eac_rg11_snorm() {
+    check_compressed(
+        "compressed_eac_rg11_snorm",
+        wgpu::TextureFormat::EacRg11Snorm,
+    );
+}
+
+#[test]
+fn compressed_astc_4x4() {
+    check_compressed(
+        "compressed_astc_4x4",
+        wgpu::TextureFormat::Astc {
+            block: wgpu::AstcBlock::B4x4,
+            channel: wgpu::AstcChannel::Unorm,
+        },
+    );
+}
+
+#[test]
+fn compressed_astc_8x8_srgb() {
+    check_compressed(
+        "compressed_astc_8x8_srgb",
+        wgpu::TextureFormat::Astc {
+            block: wgpu::AstcBlock::B8x8,
+            channel: wgpu::AstcChannel::UnormSrgb,
+        },
+    );
+}
+
+#[test]
+fn compare_flags_pixels_over_tolerance() {
+    let expected = image::RgbaImage::from_pixel(4, 4, image::Rgba([100, 100, 100, 255]));
+    let mut actual = expected.clone();
+    actual.put_pixel(1, 1, image::Rgba([103, 100, 100, 255]));
+    actual.put_pixel(2, 2, image::Rgba([255, 255, 255, 255]));
+
+    let (failing, diff) = compare(&expected, &actual, Tolerance::PerChannel(2));
+    assert_eq!(failing, 2);
+    assert_eq!(diff.get_pixel(2, 2), &image::Rgba([255, 0, 0, 255]));
+
+    // A barely visible change passes the perceptual comparison, white on grey doesn't
+    let (failing, _) = compare(&expected, &actual, Tolerance::Perceptual(0.01));
+    assert_eq!(failing, 1);
+}
@@ -0,0 +1,202 @@
+// Golden-image test harness.
+//
+// A scene is rendered headlessly and compared against a reference PNG checked in under
+// tests/golden/. When the comparison fails, the rendered frame and a diff image are written
+// to target/golden/ so the difference can be inspected.
+//
+// To (re)generate the references after an intended visual change, run:
+//     UPDATE_GOLDEN=1 cargo test --test golden
+//
+// Tests fail when no adapter can be found. On machines without any GPU or software renderer,
+// GOLDEN_ALLOW_SKIP=1 makes the tests needing a State pass without checking anything instead.
+
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+use image::{Rgba, RgbaImage};
+use learn_wgpu::State;
+
+// How two pixels are compared
+#[derive(Debug, Clone, Copy)]
+pub enum Tolerance {
+    // Every channel of a pixel may differ by at most this amount (0..=255)
+    PerChannel(u8),
+    // Perceptual difference in YIQ space (like pixelmatch), 0.0 = identical, 1.0 = black vs white.
+    // Small hue shifts that are hard to see count less than brightness changes.
+    Perceptual(f32),
+}
+
+// Settings of a single golden comparison
+#[derive(Debug, Clone, Copy)]
+pub struct Golden {
+    pub width: u32,
+    pub height: u32,
+    pub tolerance: Tolerance,
+    // Fraction of the pixels (0.0..=1.0) allowed to be over the tolerance. None by default,
+    // tests loosen it where rasterization is known to differ between GPUs.
+    pub max_failing_fraction: f32,
+}
+
+impl Default for Golden {
+    fn default() -> Self {
+        Self {
+            width: 64,
+            height: 64,
+            tolerance: Tolerance::PerChannel(2),
+            max_failing_fraction: 0.0,
+        }
+    }
+}
+
+impl Golden {
+    // For scenes with slanted triangle edges. The references come from llvmpipe, GPUs with
+    // less subpixel precision can flip the pixels whose center lies almost exactly on an
+    // edge, from the clear color to the triangle or back. The edges of the default triangle
+    // cross about 100 pixels at 64x64, 1% of the frame is 40 of them.
+    pub fn slanted_edges() -> Self {
+        Self {
+            max_failing_fraction: 0.01,
+            ..Self::default()
+        }
+    }
+}
+
+// A headless State, or None when there is no adapter and GOLDEN_ALLOW_SKIP=1 is set
+pub fn headless_state(width: u32, height: u32) -> Option<State> {
+    match pollster::block_on(State::new_headless(width, height)) {
+        Ok(state) => Some(state),
+        Err(e) if std::env::var("GOLDEN_ALLOW_SKIP").is_ok_and(|v| v == "1") => {
+            eprintln!("Skipping, no headless State: {}", e);
+            None
+        }
+        Err(e) => panic!(
+            "Unable to create a headless State: {} (set GOLDEN_ALLOW_SKIP=1 to skip)",
+            e
+        ),
+    }
+}
+
+impl Golden {
+    // Renders a frame with a headless State prepared by `setup` and compares it against
+    // tests/golden/<name>.png
+    pub fn check(&self, name: &str, setup: impl FnOnce(&mut State)) {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let Some(mut state) = headless_state(self.width, self.height) else {
+            return;
+        };
+        setup(&mut state);
+        let actual = pollster::block_on(state.capture_frame()).expect("Unable to capture frame");
+
+        let reference_path = golden_dir().join(format!("{}.png", name));
+        if std::env::var("UPDATE_GOLDEN").is_ok_and(|v| v == "1") {
+            actual.save(&reference_path).unwrap();
+            eprintln!("Updated {}", reference_path.display());
+            return;
+        }
+
+        let expected = image::open(&reference_path)
+            .unwrap_or_else(|e| {
+                panic!(
+                    "Unable to open {}: {} (run with UPDATE_GOLDEN=1 to create it)",
+                    reference_path.display(),
+                    e
+                )
+            })
+            .to_rgba8();
+        assert_eq!(
+            expected.dimensions(),
+            actual.dimensions(),
+            "{}: reference and rendered frame have different sizes",
+            name
+        );
+
+        let (failing, diff) = compare(&expected, &actual, self.tolerance);
+        let total = (self.width * self.height) as f32;
+        if failing as f32 / total > self.max_failing_fraction {
+            let output_dir = output_dir();
+            std::fs::create_dir_all(&output_dir).unwrap();
+            let actual_path = output_dir.join(format!("{}-actual.png", name));
+            let diff_path = output_dir.join(format!("{}-diff.png", name));
+            actual.save(&actual_path).unwrap();
+            diff.save(&diff_path).unwrap();
+            panic!(
+                "{}: {} of {} pixels differ from {} (rendered: {}, diff: {})",
+                name,
+                failing,
+                total,
+                reference_path.display(),
+                actual_path.display(),
+                diff_path.display()
+            );
+        }
+    }
+}
+
+// Returns the number of pixels over the tolerance and a diff image where those pixels are
+// red and all the others are a faded grey version of the reference
+pub fn compare(
+    expected: &RgbaImage,
+    actual: &RgbaImage,
+    tolerance: Tolerance,
+) -> (usize, RgbaImage) {
+    let mut failing = 0;
+    let mut diff = RgbaImage::new(expected.width(), expected.height());
+    for ((e, a), d) in expected
+        .pixels()
+        .zip(actual.pixels())
+        .zip(diff.pixels_mut())
+    {
+        let matches = match tolerance {
+            Tolerance::PerChannel(max) => {
+                e.0.iter()
+                    .zip(a.0.iter())
+                    .all(|(e, a)| e.abs_diff(*a) <= max)
+            }
+            Tolerance::Perceptual(threshold) => perceptual_delta(e, a) <= threshold,
+        };
+        *d = if matches {
+            let grey = 128 + (luma(e) * 127.0) as u8 / 2;
+            Rgba([grey, grey, grey, 255])
+        } else {
+            failing += 1;
+            Rgba([255, 0, 0, 255])
+        };
+    }
+    (failing, diff)
+}
+
+fn luma(p: &Rgba<u8>) -> f32 {
+    (0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32) / 255.0
+}
+
+// Color difference in YIQ space from "Measuring perceived color difference using YIQ NTSC
+// transmission color space in mobile applications" (Kotsarenko & Ramos), the metric used by
+// pixelmatch. Pixels are blended over white first so transparency is taken into account.
+fn perceptual_delta(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    let blend = |p: &Rgba<u8>| {
+        let alpha = p[3] as f32 / 255.0;
+        [0, 1, 2].map(|i| 255.0 + (p[i] as f32 - 255.0) * alpha)
+    };
+    let [r1, g1, b1] = blend(a);
+    let [r2, g2, b2] = blend(b);
+    let (dr, dg, db) = (r1 - r2, g1 - g2, b1 - b2);
+    let y = dr * 0.298_895 + dg * 0.586_622 + db * 0.114_482;
+    let i = dr * 0.595_978 - dg * 0.274_176 - db * 0.321_802;
+    let q = dr * 0.211_470 - dg * 0.522_617 + db * 0.311_147;
+    // 35215 is the largest possible delta (black vs white), used to normalize to 0..1
+    (0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q) / 35215.0
+}
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+}
+
+fn output_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("golden")
+}
@@ -0,0 +1,141 @@
+// Actions and axes, driven through the same methods window_event and device_event use.
+
+use learn_wgpu::input::{AxisBinding, Binding, Input, InputMap, Modifier, ScrollDirection, action};
+use winit::event::{MouseButton, MouseScrollDelta};
+use winit::keyboard::KeyCode;
+
+#[test]
+fn bindings_are_read_from_toml() {
+    let map = InputMap::from_toml(
+        r#"
+        [actions]
+        exit = [{ key = "KeyQ" }, { mouse = "Middle" }]
+        zoom_in = [{ scroll = "up" }, "touch"]
+        toggle_fullscreen = [{ key_with = { key = "Enter", modifier = "alt" } }]
+
+        [axes]
+        zoom = [{ buttons = { negative = { key = "Minus" }, positive = { key = "Equal" } } }, "scroll"]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        map.actions[action::EXIT],
+        [
+            Binding::Key(KeyCode::KeyQ),
+            Binding::Mouse(MouseButton::Middle)
+        ]
+    );
+    assert_eq!(
+        map.actions["zoom_in"],
+        [Binding::Scroll(ScrollDirection::Up), Binding::Touch]
+    );
+    assert_eq!(
+        map.actions[action::TOGGLE_FULLSCREEN],
+        [Binding::KeyWith {
+            key: KeyCode::Enter,
+            modifier: Modifier::Alt,
+        }]
+    );
+    assert_eq!(
+        map.axes["zoom"],
+        [
+            AxisBinding::Buttons {
+                negative: Binding::Key(KeyCode::Minus),
+                positive: Binding::Key(KeyCode::Equal),
+            },
+            AxisBinding::Scroll,
+        ]
+    );
+
+    // Writing the map back gives the same bindings
+    assert_eq!(InputMap::from_toml(&map.to_toml().unwrap()).unwrap(), map);
+    assert!(InputMap::from_toml("[actions]\nexit = [{ key = \"NotAKey\" }]").is_err());
+}
+
+#[test]
+fn just_pressed_lasts_one_frame() {
+    let mut input = Input::default();
+    input.process_binding(Binding::Key(KeyCode::Escape), true);
+    assert!(input.just_pressed(action::EXIT));
+    assert!(input.pressed(action::EXIT));
+    assert!(!input.just_pressed(action::SCREENSHOT));
+
+    input.end_frame();
+    // Key repeats aren't new presses
+    input.process_binding(Binding::Key(KeyCode::Escape), true);
+    assert!(!input.just_pressed(action::EXIT));
+    assert!(input.pressed(action::EXIT));
+
+    input.end_frame();
+    input.process_binding(Binding::Key(KeyCode::Escape), false);
+    assert!(input.just_released(action::EXIT));
+    assert!(!input.pressed(action::EXIT));
+    input.end_frame();
+    assert!(!input.just_released(action::EXIT));
+}
+
+#[test]
+fn a_tap_within_a_frame_is_not_lost() {
+    let mut input = Input::default();
+    input.process_binding(Binding::Key(KeyCode::F12), true);
+    input.process_binding(Binding::Key(KeyCode::F12), false);
+    assert!(input.just_pressed(action::SCREENSHOT));
+    assert!(input.just_released(action::SCREENSHOT));
+    assert!(input.pressed(action::SCREENSHOT));
+}
+
+#[test]
+fn modifier_bindings_need_the_modifier_held() {
+    let mut input = Input::default();
+    input.process_binding(Binding::Key(KeyCode::Enter), true);
+    assert!(!input.just_pressed(action::TOGGLE_FULLSCREEN));
+    input.process_binding(Binding::Key(KeyCode::Enter), false);
+    input.end_frame();
+
+    // Either Alt key works
+    input.process_binding(Binding::Key(KeyCode::AltRight), true);
+    input.process_binding(Binding::Key(KeyCode::Enter), true);
+    assert!(input.just_pressed(action::TOGGLE_FULLSCREEN));
+    assert!(input.pressed(action::TOGGLE_FULLSCREEN));
+    input.end_frame();
+    assert!(!input.just_pressed(action::TOGGLE_FULLSCREEN));
+
+    // Releasing Alt first ends the combination
+    input.process_binding(Binding::Key(KeyCode::AltRight), false);
+    assert!(!input.pressed(action::TOGGLE_FULLSCREEN));
+
+    // F11 needs no modifier
+    input.process_binding(Binding::Key(KeyCode::F11), true);
+    assert!(input.just_pressed(action::TOGGLE_FULLSCREEN));
+}
+
+#[test]
+fn axes_add_up_their_bindings() {
+    let mut map = InputMap::empty();
+    map.bind_axis(
+        "zoom",
+        AxisBinding::Buttons {
+            negative: Binding::Key(KeyCode::Minus),
+            positive: Binding::Key(KeyCode::Equal),
+        },
+    );
+    map.bind_axis("zoom", AxisBinding::Scroll);
+    map.bind_axis("turn", AxisBinding::MouseX);
+    map.bind("zoom_in", Binding::Scroll(ScrollDirection::Up));
+    let mut input = Input::new(map);
+
+    input.process_binding(Binding::Key(KeyCode::Equal), true);
+    input.process_scroll(MouseScrollDelta::LineDelta(0.0, 2.0));
+    input.process_mouse_motion(3.0, 4.0);
+    input.process_mouse_motion(2.0, 0.0);
+    assert_eq!(input.axis("zoom"), 3.0);
+    assert_eq!(input.axis("turn"), 5.0);
+    assert!(input.just_pressed("zoom_in"));
+    assert_eq!(input.axis("unknown"), 0.0);
+
+    // Held keys keep their value, the movement is per frame
+    input.end_frame();
+    assert_eq!(input.axis("zoom"), 1.0);
+    assert_eq!(input.axis("turn"), 0.0);
+    assert!(!input.pressed("zoom_in"));
+}
@@ -0,0 +1,16 @@
+// Suspending and resuming State.
+
+mod harness;
+
+// Only windows have a surface to drop, a headless state keeps rendering
+#[test]
+fn headless_state_ignores_suspend() {
+    let Some(mut state) = harness::headless_state(8, 8) else {
+        return;
+    };
+    state.suspend();
+    assert!(!state.is_suspended());
+    state.resume().unwrap();
+    let pixels = pollster::block_on(state.render_to_pixels()).unwrap();
+    assert_eq!(pixels.len(), 8 * 8 * 4);
+}
@@ -0,0 +1,44 @@
+// Choosing a present mode the surface supports.
+
+mod harness;
+
+use learn_wgpu::present::{is_vsync, select_present_mode};
+use wgpu::PresentMode::*;
+
+#[test]
+fn supported_modes_are_kept() {
+    let supported = [Fifo, FifoRelaxed, Mailbox, Immediate];
+    for mode in supported {
+        assert_eq!(select_present_mode(mode, &supported), mode);
+    }
+}
+
+#[test]
+fn unsupported_modes_fall_back() {
+    // A typical Wayland compositor
+    let wayland = [Fifo, Mailbox];
+    assert_eq!(select_present_mode(Immediate, &wayland), Mailbox);
+    assert_eq!(select_present_mode(FifoRelaxed, &wayland), Fifo);
+    // Only Fifo is guaranteed, e.g. on the web
+    assert_eq!(select_present_mode(Mailbox, &[Fifo]), Fifo);
+    assert_eq!(select_present_mode(Immediate, &[]), Fifo);
+    // wgpu resolves the Auto modes itself
+    assert_eq!(select_present_mode(AutoNoVsync, &[Fifo]), AutoNoVsync);
+    // Immediate can't replace a vsync mode
+    assert!(is_vsync(select_present_mode(
+        FifoRelaxed,
+        &[Immediate, Fifo]
+    )));
+}
+
+#[test]
+fn headless_state_remembers_the_mode() {
+    let Some(mut state) = harness::headless_state(8, 8) else {
+        return;
+    };
+    assert_eq!(state.set_present_mode(Immediate), Immediate);
+    assert_eq!(state.present_mode(), Immediate);
+    state.set_frame_latency(0);
+    assert_eq!(state.frame_latency(), 1);
+    assert!(state.supported_present_modes().is_empty());
+}
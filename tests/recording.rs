@@ -0,0 +1,193 @@
+// Saving recordings and replaying them headlessly.
+
+use std::path::{Path, PathBuf};
+
+use learn_wgpu::App;
+use learn_wgpu::input::{Binding, InputMap, action};
+use learn_wgpu::recording::{AppEvent, Recorder, Recording, TimedEvent};
+use winit::event::{MouseButton, TouchPhase};
+use winit::keyboard::KeyCode;
+
+// Builds a recording frame by frame: events are added to the current frame, and
+// `redraw` ends it `dt` seconds after the previous one
+#[derive(Default)]
+struct Builder {
+    recording: Recording,
+    frame: u64,
+    time: f64,
+}
+
+impl Builder {
+    fn event(mut self, event: AppEvent) -> Self {
+        self.recording.events.push(TimedEvent {
+            frame: self.frame,
+            time: self.time,
+            event,
+        });
+        self
+    }
+
+    fn key(self, key: KeyCode, pressed: bool) -> Self {
+        self.event(AppEvent::Keyboard {
+            key,
+            pressed,
+            repeat: false,
+        })
+    }
+
+    fn redraw(mut self, dt: f64) -> Self {
+        self.time += dt;
+        self = self.event(AppEvent::RedrawRequested);
+        self.frame += 1;
+        self
+    }
+}
+
+fn output_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn frame(dir: &Path, frame: u64) -> image::RgbaImage {
+    image::open(dir.join(format!("frame-{:05}.png", frame)))
+        .unwrap()
+        .into_rgba8()
+}
+
+#[test]
+fn recorder_output_can_be_loaded() {
+    let events = [
+        AppEvent::Resized {
+            width: 64,
+            height: 48,
+        },
+        AppEvent::Keyboard {
+            key: KeyCode::KeyW,
+            pressed: true,
+            repeat: false,
+        },
+        AppEvent::MouseButton {
+            button: MouseButton::Right,
+            pressed: true,
+        },
+        AppEvent::ScrollLines { x: 0.0, y: -1.5 },
+        AppEvent::Touch {
+            id: 3,
+            phase: TouchPhase::Moved,
+            x: 10.5,
+            y: 20.0,
+        },
+        AppEvent::MouseMotion { dx: 0.1, dy: -7.0 },
+        AppEvent::RedrawRequested,
+    ];
+    let recording = Recording {
+        events: events
+            .iter()
+            .enumerate()
+            .map(|(i, &event)| TimedEvent {
+                frame: i as u64 / 3,
+                time: i as f64 / 7.0,
+                event,
+            })
+            .collect(),
+    };
+
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("recording.toml");
+    let mut recorder = Recorder::create(&path).unwrap();
+    for &timed in &recording.events {
+        recorder.record(timed).unwrap();
+    }
+    drop(recorder);
+
+    let loaded = Recording::load(&path).unwrap();
+    assert_eq!(loaded, recording);
+    assert_eq!(loaded.initial_size(), Some((64, 48)));
+    assert_eq!(loaded.frame_count(), 1);
+}
+
+// Strafing right with the fly camera moves the triangle left. Replaying twice gives the
+// same frames, and only the recorded times matter.
+#[test]
+fn headless_replay_is_deterministic() {
+    let recording = Builder::default()
+        .event(AppEvent::Resized {
+            width: 64,
+            height: 48,
+        })
+        .redraw(0.016)
+        .key(KeyCode::Digit2, true)
+        .redraw(0.016)
+        .key(KeyCode::KeyD, true)
+        .redraw(0.1)
+        .redraw(0.25)
+        .key(KeyCode::KeyD, false)
+        .redraw(0.5)
+        .redraw(0.5)
+        .recording;
+
+    let first = output_dir("replay-first");
+    let second = output_dir("replay-second");
+    let frames = pollster::block_on(App::replay_headless(recording.clone(), Some(&first))).unwrap();
+    assert_eq!(frames, 6);
+    pollster::block_on(App::replay_headless(recording, Some(&second))).unwrap();
+
+    for i in 0..frames {
+        assert_eq!(frame(&first, i), frame(&second, i), "frame {}", i);
+    }
+    // Nothing moves until D is held, and after it is released. Frame 3 is drawn between
+    // the last two updates, so the camera shows at rest from frame 4.
+    assert_eq!(frame(&first, 0), frame(&first, 1));
+    assert_ne!(frame(&first, 1), frame(&first, 2));
+    assert_ne!(frame(&first, 2), frame(&first, 3));
+    assert_ne!(frame(&first, 3), frame(&first, 4));
+    assert_eq!(frame(&first, 4), frame(&first, 5));
+    assert_eq!(frame(&first, 5).dimensions(), (64, 48));
+}
+
+#[test]
+fn headless_replay_stops_at_exit() {
+    let recording = Builder::default()
+        .redraw(0.016)
+        .key(KeyCode::Escape, true)
+        .redraw(0.016)
+        .redraw(0.016)
+        .recording;
+    let frames = pollster::block_on(App::replay_headless(recording, None)).unwrap();
+    // The exit action is handled at the start of the second frame, which is still drawn
+    assert_eq!(frames, 2);
+}
+
+#[test]
+fn headless_replay_checks_the_frames() {
+    let mut recording = Builder::default()
+        .redraw(0.016)
+        .key(KeyCode::KeyW, true)
+        .redraw(0.016)
+        .recording;
+    // The key press claims to be from the first frame, which is already drawn
+    recording.events[1].frame = 0;
+    let error = pollster::block_on(App::replay_headless(recording, None)).unwrap_err();
+    assert!(error.to_string().contains("frame 0"), "{}", error);
+}
+
+// Replays use the default bindings: Escape exits after the frame it was pressed in
+#[test]
+fn headless_replay_uses_the_default_bindings() {
+    let recording = Builder::default()
+        .key(KeyCode::Escape, true)
+        .redraw(0.016)
+        .redraw(0.016)
+        .recording;
+    let frames = pollster::block_on(App::replay_headless(recording, None));
+    assert_eq!(frames.unwrap(), 1);
+}
+
+// An App made with its own bindings doesn't read bindings.toml
+#[test]
+fn app_uses_the_bindings_it_is_made_with() {
+    let mut map = InputMap::empty();
+    map.bind(action::EXIT, Binding::Key(KeyCode::KeyQ));
+    let app = App::with_input_map(map.clone());
+    assert_eq!(app.input().map(), &map);
+}
@@ -0,0 +1,34 @@
+// Reacting to surface errors.
+
+mod harness;
+
+use learn_wgpu::recovery::{SurfaceErrorAction, surface_error_action};
+
+#[test]
+fn surface_errors_have_an_action() {
+    use wgpu::SurfaceError::*;
+    assert_eq!(surface_error_action(&Lost), SurfaceErrorAction::Reconfigure);
+    assert_eq!(
+        surface_error_action(&Outdated),
+        SurfaceErrorAction::Reconfigure
+    );
+    assert_eq!(
+        surface_error_action(&Timeout),
+        SurfaceErrorAction::SkipFrame
+    );
+    assert_eq!(surface_error_action(&Other), SurfaceErrorAction::SkipFrame);
+    assert_eq!(surface_error_action(&OutOfMemory), SurfaceErrorAction::Exit);
+}
+
+#[test]
+fn headless_state_reconfigures_on_lost() {
+    let Some(mut state) = harness::headless_state(8, 8) else {
+        return;
+    };
+    assert_eq!(
+        state.handle_surface_error(&wgpu::SurfaceError::Lost),
+        SurfaceErrorAction::Reconfigure
+    );
+    assert_eq!((state.config().width, state.config().height), (8, 8));
+    assert!(!state.is_device_lost());
+}
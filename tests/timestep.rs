@@ -0,0 +1,66 @@
+// Fixed-timestep updates and frame rate limiting.
+
+use std::time::Duration;
+
+use learn_wgpu::timestep::{FixedTimestep, FrameLimiter};
+
+const MS: Duration = Duration::from_millis(1);
+
+#[test]
+fn frames_run_the_steps_that_fit() {
+    let mut timestep = FixedTimestep::new(10 * MS, 5);
+    assert_eq!(timestep.advance(4 * MS), 0);
+    assert!((timestep.alpha() - 0.4).abs() < 1e-4);
+    // The leftover time is carried to the next frame
+    assert_eq!(timestep.advance(17 * MS), 2);
+    assert!((timestep.alpha() - 0.1).abs() < 1e-4);
+    assert_eq!(timestep.advance(9 * MS), 1);
+    assert!(timestep.alpha() < 1e-4);
+}
+
+#[test]
+fn long_frames_are_clamped() {
+    let mut timestep = FixedTimestep::new(10 * MS, 3);
+    assert_eq!(timestep.advance(Duration::from_secs(2)), 3);
+    // The time that didn't fit is dropped rather than caught up on later
+    assert_eq!(timestep.alpha(), 0.0);
+    assert_eq!(timestep.advance(10 * MS), 1);
+}
+
+#[test]
+fn rate_sets_the_step() {
+    let timestep = FixedTimestep::from_rate(50.0, 5).unwrap();
+    assert_eq!(timestep.step(), 20 * MS);
+    assert_eq!(
+        FixedTimestep::default().step(),
+        Duration::from_secs_f64(1.0 / 60.0)
+    );
+}
+
+#[test]
+fn invalid_rates_are_errors() {
+    for rate in [
+        0.0,
+        -30.0,
+        f64::NAN,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        1e-300,
+    ] {
+        assert!(FixedTimestep::from_rate(rate, 5).is_err(), "{}", rate);
+        assert!(FrameLimiter::new(rate).is_err(), "{}", rate);
+    }
+}
+
+#[test]
+fn limiter_spaces_frames() {
+    let mut limiter = FrameLimiter::new(100.0).unwrap();
+    let start = limiter.next_frame();
+    // On time: the next frame is one interval later
+    assert_eq!(limiter.frame_started(start), start + 10 * MS);
+    assert_eq!(limiter.frame_started(start + 10 * MS), start + 20 * MS);
+    // Late: the next frame is one interval after the late one, without catching up
+    let late = start + 55 * MS;
+    assert_eq!(limiter.frame_started(late), late + 10 * MS);
+    assert_eq!(limiter.frame_started(late), late + 20 * MS);
+}
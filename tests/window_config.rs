@@ -0,0 +1,60 @@
+// Picking the video mode of exclusive fullscreen, and the window icon
+
+use learn_wgpu::window_config::{VideoModeInfo, VideoModeRequest, WindowConfig};
+
+fn mode(width: u32, height: u32, hz: u32, bit_depth: u16) -> VideoModeInfo {
+    VideoModeInfo {
+        size: (width, height),
+        refresh_rate_millihertz: hz * 1000,
+        bit_depth,
+    }
+}
+
+#[test]
+fn video_mode_closest_to_the_request() {
+    let modes = [
+        mode(1280, 720, 60, 32),
+        mode(1920, 1080, 60, 24),
+        mode(1920, 1080, 60, 32),
+        mode(1920, 1080, 144, 32),
+        mode(2560, 1440, 60, 32),
+    ];
+
+    // Largest size, then highest refresh rate
+    assert_eq!(VideoModeRequest::default().best_match(&modes), Some(4));
+    let full_hd = VideoModeRequest {
+        size: Some((1920, 1080)),
+        refresh_rate_millihertz: None,
+    };
+    assert_eq!(full_hd.best_match(&modes), Some(3));
+    // The highest bit depth among equal modes
+    let full_hd_60 = VideoModeRequest {
+        refresh_rate_millihertz: Some(60_000),
+        ..full_hd
+    };
+    assert_eq!(full_hd_60.best_match(&modes), Some(2));
+    // No exact match: the nearest size wins over the refresh rate
+    let odd = VideoModeRequest {
+        size: Some((1300, 700)),
+        refresh_rate_millihertz: Some(144_000),
+    };
+    assert_eq!(odd.best_match(&modes), Some(0));
+    assert_eq!(full_hd.best_match(&[]), None);
+}
+
+#[test]
+fn icon_from_png() {
+    let image = image::RgbaImage::from_pixel(16, 16, image::Rgba([255, 0, 0, 255]));
+    let mut png = std::io::Cursor::new(Vec::new());
+    image.write_to(&mut png, image::ImageFormat::Png).unwrap();
+
+    let config = WindowConfig::default()
+        .with_icon_image(png.get_ref())
+        .unwrap();
+    assert!(config.icon.is_some());
+    assert!(
+        WindowConfig::default()
+            .with_icon_image(b"not a png")
+            .is_err()
+    );
+}